@@ -1,7 +1,17 @@
 use tokio::sync::Mutex;
 
+use crate::{
+    config::Config, identity_providers::IdentityProviders, mailers::Mailer, sms::SmsSender,
+    stores::login_attempts::LoginAttemptStore,
+};
+
 pub struct AppState {
     pub health_check_response: String,
     pub visit_count: Mutex<i32>,
     pub database: mongodb::Client,
-}
\ No newline at end of file
+    pub config: Config,
+    pub login_attempts: Box<dyn LoginAttemptStore>,
+    pub mailer: Box<dyn Mailer>,
+    pub sms_sender: Box<dyn SmsSender>,
+    pub identity_providers: IdentityProviders,
+}
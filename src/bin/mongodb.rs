@@ -1,7 +1,6 @@
-use mongodb::{bson::doc, bson::Document, options::ClientOptions, Collection};
+use mongodb::{bson::doc, options::ClientOptions, Collection};
 use std::error::Error;
 use mlum_inner::models::users::User;
-use tokio;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
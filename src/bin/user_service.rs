@@ -3,9 +3,63 @@ use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
 
 use mlum_inner::app_state::AppState;
+use mlum_inner::config::Config;
+use mlum_inner::errors::WebError;
 use mlum_inner::routers::*;
+use mlum_inner::identity_providers::IdentityProviders;
+use mlum_inner::mailers::{file::FileMailer, smtp::SmtpMailer, Mailer};
+use mlum_inner::sms::log::LogSmsSender;
+use mlum_inner::services::access_tokens::serv_access_token_indexes;
+use mlum_inner::services::audit_events::{serv_audit_event_indexes, serv_audit_event_prune};
+use mlum_inner::services::email_verifications::serv_email_verification_indexes;
+use mlum_inner::services::linked_identities::serv_linked_identity_indexes;
+use mlum_inner::services::login_challenges::serv_login_challenge_indexes;
+use mlum_inner::services::oauth::serv_oauth_indexes;
+use mlum_inner::services::password_resets::serv_password_reset_indexes;
+use mlum_inner::services::phone_codes::serv_phone_code_indexes;
+use mlum_inner::services::two_factors::{serv_two_factor_indexes, serv_two_factor_secret_migrate};
+use mlum_inner::services::users::{
+    serv_user_deletion_cleanup, serv_user_indexes, serv_user_role_bootstrap,
+};
+use mlum_inner::services::sessions::serv_session_indexes;
+use mlum_inner::services::token_digests::serv_token_digest_migrate;
+use mlum_inner::stores::login_attempts::{
+    LoginAttemptStore, MemoryLoginAttemptStore, MongoLoginAttemptStore,
+};
 use tokio::sync::Mutex;
 
+/**
+ * Choose the login attempt store, LOGIN_ATTEMPT_STORE=memory keeps the attempts in memory
+ */
+fn login_attempt_store(database: &mongodb::Client, config: &Config) -> Box<dyn LoginAttemptStore> {
+    match std::env::var("LOGIN_ATTEMPT_STORE").as_deref() {
+        Ok("memory") => Box::new(MemoryLoginAttemptStore::default()),
+        _ => Box::new(MongoLoginAttemptStore::new(database, &config.login_throttle)),
+    }
+}
+
+/**
+ * Choose the mailer, MAILER=smtp sends real mails, MAILER=file appends them to MAIL_FILE,
+ * otherwise they are printed to stdout
+ */
+fn mailer(config: &Config) -> Box<dyn Mailer> {
+    match std::env::var("MAILER").as_deref() {
+        Ok("smtp") => {
+            Box::new(SmtpMailer::new(&config.smtp).expect("Failed to configure the SMTP mailer"))
+        }
+        Ok("file") => Box::new(FileMailer::new(std::env::var("MAIL_FILE").ok().map(Into::into))),
+        _ => Box::new(FileMailer::new(None)),
+    }
+}
+
+/**
+ * Turn a failed startup step into the error main exits with
+ * @param context What the step was doing
+ */
+fn startup_error(context: &'static str) -> impl FnOnce(WebError) -> std::io::Error {
+    move |err| std::io::Error::other(format!("{}: {}", context, err))
+}
+
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
     dotenv().ok();
@@ -13,11 +67,94 @@ async fn main() -> Result<(), std::io::Error> {
     let database = mongodb::Client::with_uri_str(&database_url)
         .await
         .expect("Failed to connect to database");
+    let config = Config::from_env().map_err(std::io::Error::other)?;
+    config.check_secrets().map_err(std::io::Error::other)?;
+    // replace the raw tokens of earlier versions before the indexes of the digests are built
+    serv_token_digest_migrate(&database, &config)
+        .await
+        .map_err(startup_error("Failed to migrate the stored tokens"))?;
+    serv_two_factor_secret_migrate(&database, &config)
+        .await
+        .map_err(startup_error("Failed to encrypt the two-factor secrets"))?;
+    serv_user_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create user indexes"))?;
+    serv_session_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create session indexes"))?;
 
+    serv_password_reset_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create password reset indexes"))?;
+    serv_email_verification_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create email verification indexes"))?;
+    serv_phone_code_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create phone code indexes"))?;
+    serv_two_factor_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create two-factor indexes"))?;
+    serv_login_challenge_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create login challenge indexes"))?;
+    serv_access_token_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create personal access token indexes"))?;
+    serv_oauth_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create OAuth indexes"))?;
+    serv_linked_identity_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create linked identity indexes"))?;
+    serv_audit_event_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create audit event indexes"))?;
+    MongoLoginAttemptStore::create_indexes(&database)
+        .await
+        .map_err(startup_error("Failed to create login attempt indexes"))?;
+
+    serv_user_role_bootstrap(&database, &config)
+        .await
+        .map_err(startup_error("Failed to grant the admin role"))?;
+
+    // drop the audit events older than the retention
+    let audit_database = database.clone();
+    let audit_config = config.audit.clone();
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(audit_config.prune_interval.max(1)));
+        loop {
+            interval.tick().await;
+            if let Err(err) = serv_audit_event_prune(&audit_database, &audit_config).await {
+                eprintln!("Failed to prune the audit log: {}", err);
+            }
+        }
+    });
+    // anonymize or purge the deleted users after their grace period
+    let deletion_database = database.clone();
+    let deletion_config = config.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            deletion_config.account_deletion.cleanup_interval.max(1),
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(err) = serv_user_deletion_cleanup(&deletion_database, &deletion_config).await {
+                eprintln!("Failed to clean up the deleted users: {}", err);
+            }
+        }
+    });
     let shared_data = web::Data::new(AppState {
         health_check_response: "App Service is OK.".to_string(),
         visit_count: Mutex::new(0),
+        login_attempts: login_attempt_store(&database, &config),
+        mailer: mailer(&config),
+        // TODO: plug a real SMS gateway
+        sms_sender: Box::new(LogSmsSender),
+        identity_providers: IdentityProviders::from_config(&config.identity_providers),
         database,
+        config,
     });
 
     let app = move || {
@@ -30,6 +167,8 @@ async fn main() -> Result<(), std::io::Error> {
                 .max_age(3600)
             )
             .configure(users::user_routers)
+            .configure(admin::admin_routers)
+            .configure(oauth::oauth_routers)
             .configure(general::general_routers)
     };
 
@@ -0,0 +1,857 @@
+use std::str::FromStr;
+
+use crate::utils::token::token_keys_check;
+
+/**
+ * Runtime configuration of the service.
+ * Every value can be overridden by an environment variable (or the .env file).
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub password_hash: PasswordHashConfig,
+    pub password_policy: PasswordPolicyConfig,
+    pub token: TokenConfig,
+    pub session: SessionConfig,
+    pub login_throttle: LoginThrottleConfig,
+    pub smtp: SmtpConfig,
+    pub password_reset: PasswordResetConfig,
+    pub email_verification: EmailVerificationConfig,
+    pub phone_code: PhoneCodeConfig,
+    pub two_factor: TwoFactorConfig,
+    pub admin: AdminConfig,
+    pub identifier: IdentifierConfig,
+    pub service_clients: ServiceClientsConfig,
+    pub oauth: OAuthConfig,
+    pub audit: AuditConfig,
+    pub account_deletion: AccountDeletionConfig,
+    pub identity_providers: IdentityProvidersConfig,
+}
+
+/**
+ * Cost parameters of the Argon2id password hash
+ */
+#[derive(Debug, Clone)]
+pub struct PasswordHashConfig {
+    // memory size in KiB
+    pub memory_cost: u32,
+    // number of iterations
+    pub time_cost: u32,
+    // degree of parallelism
+    pub parallelism: u32,
+}
+
+/**
+ * Rules of new passwords, checked at registration, password change and reset
+ */
+#[derive(Debug, Clone)]
+pub struct PasswordPolicyConfig {
+    // length in characters
+    pub min_length: usize,
+    pub max_length: usize,
+    // required character classes
+    pub require_letter: bool,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // minimum strength score, from 0 (too guessable) to 4 (very unguessable)
+    pub min_score: u8,
+    // refuse the passwords of the bundled common password list
+    pub reject_common: bool,
+}
+
+/**
+ * Signature algorithm of the access tokens
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenAlgorithm {
+    HS256,
+    EdDSA,
+}
+
+/**
+ * Signing keys and lifetimes of the issued tokens
+ */
+#[derive(Debug, Clone)]
+pub struct TokenConfig {
+    pub algorithm: TokenAlgorithm,
+    // shared secret for HS256
+    pub secret: String,
+    // PEM encoded keys for EdDSA
+    pub private_key: String,
+    pub public_key: String,
+    pub issuer: String,
+    // lifetime of an access token in seconds, refresh tokens live as long as their session
+    pub access_token_lifetime: i64,
+    // key of the digests the opaque tokens are stored as, changing it invalidates them
+    pub digest_key: String,
+}
+
+// minimal length of the keys which protect the stored secrets
+pub const MIN_SECRET_KEY_LEN: usize = 32;
+
+/**
+ * Lifetimes of the login sessions, in seconds.
+ * A session expires after the idle timeout without use, every use pushes the expiry
+ * forward until the absolute timeout since the login
+ */
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub idle_timeout: i64,
+    pub absolute_timeout: i64,
+    // logins with remember_me
+    pub remember_idle_timeout: i64,
+    pub remember_absolute_timeout: i64,
+}
+
+/**
+ * Limits of failed logins before an account or a client is locked out
+ */
+#[derive(Debug, Clone)]
+pub struct LoginThrottleConfig {
+    // failures per username before the account is locked
+    pub max_user_failures: u32,
+    // failures per client ip before the ip is locked
+    pub max_ip_failures: u32,
+    // failures older than this window (in seconds) are forgotten
+    pub failure_window: i64,
+    // lockout duration in seconds
+    pub lockout_duration: i64,
+    // delay of the first failure in milliseconds, doubled by every following failure
+    pub base_delay: u64,
+    pub max_delay: u64,
+    // take the client ip from the Forwarded and X-Forwarded-For headers,
+    // only enable it behind a proxy which overwrites them
+    pub trust_proxy_headers: bool,
+}
+
+/**
+ * Connection to the SMTP relay, used when MAILER=smtp
+ */
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    // sender address, e.g. "mlum <noreply@mlum.com>"
+    pub from: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordResetConfig {
+    // lifetime of a reset token in seconds
+    pub token_lifetime: i64,
+    // link sent to the user, {token} is replaced by the reset token
+    pub url: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailVerificationConfig {
+    // lifetime of a verification code in seconds
+    pub code_lifetime: i64,
+    // minimum delay between two verification mails in seconds
+    pub resend_interval: i64,
+    // link sent to the user, {code} is replaced by the verification code
+    pub url: String,
+    // refuse logins until the email is verified
+    pub required: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhoneCodeConfig {
+    // lifetime of a code in seconds
+    pub code_lifetime: i64,
+    // wrong codes before the code is dropped
+    pub max_attempts: u32,
+    // minimum delay between two codes sent to a number, in seconds
+    pub resend_interval: i64,
+    // codes sent to a number per hour
+    pub max_sends_per_hour: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TwoFactorConfig {
+    // issuer shown in authenticator apps
+    pub issuer: String,
+    // time steps accepted before and after the current one
+    pub skew: i64,
+    // lifetime of a login challenge in seconds
+    pub challenge_lifetime: i64,
+    // wrong codes before the challenge is dropped
+    pub max_attempts: u32,
+    // number of recovery codes issued at once
+    pub recovery_codes: usize,
+    // key the TOTP secrets are encrypted with, changing it disables every second factor
+    pub secret_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    // user promoted to admin at startup, empty to skip
+    pub bootstrap_username: String,
+    // lifetime of an impersonation in seconds, it can not be refreshed
+    pub impersonation_lifetime: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdentifierConfig {
+    // country code of phone numbers typed without one, e.g. "86"
+    pub phone_country_code: String,
+}
+
+/**
+ * Credentials of the internal services allowed to introspect tokens
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ServiceClientsConfig {
+    // (client id, client secret) pairs
+    pub clients: Vec<(String, String)>,
+}
+
+/**
+ * Single sign-on, the service acting as an OAuth2 / OpenID Connect provider
+ */
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    // public base url of the service, the issuer of the id tokens
+    pub issuer_url: String,
+    // consent page of the frontend, the authorization request is appended as query
+    pub consent_url: String,
+    // lifetime of an authorization code in seconds
+    pub code_lifetime: i64,
+    // lifetime of an id token in seconds
+    pub id_token_lifetime: i64,
+}
+
+/**
+ * An external OAuth2 / OpenID Connect provider the users can sign in with
+ */
+#[derive(Debug, Clone, Default)]
+pub struct IdentityProviderConfig {
+    // name in the urls, e.g. "github"
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    // space separated
+    pub scopes: String,
+    // fields of the userinfo response
+    pub subject_field: String,
+    pub username_field: String,
+    pub email_field: String,
+    pub email_verified_field: String,
+}
+
+/**
+ * Sign-in with external identity providers
+ */
+#[derive(Debug, Clone)]
+pub struct IdentityProvidersConfig {
+    pub providers: Vec<IdentityProviderConfig>,
+    // callback page of the frontend registered at the providers, "{provider}" is replaced by the name
+    pub redirect_url: String,
+    // lifetime of a pending sign-in in seconds
+    pub state_lifetime: i64,
+}
+
+/**
+ * Retention of the audit log
+ */
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    // events older than this are dropped, 0 keeps them forever
+    pub retention_days: i64,
+    // delay between two prunes in seconds
+    pub prune_interval: u64,
+}
+
+/**
+ * What happens to a deleted account once its grace period is over
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeletionMode {
+    // the personal data is erased, the account stays as a placeholder of its content
+    Anonymize,
+    // the account is removed
+    Purge,
+}
+
+/**
+ * Lifecycle of the deleted accounts
+ */
+#[derive(Debug, Clone)]
+pub struct AccountDeletionConfig {
+    // seconds during which a login restores a deleted account
+    pub grace_period: i64,
+    pub mode: DeletionMode,
+    // delay between two runs of the cleanup in seconds
+    pub cleanup_interval: u64,
+}
+
+impl Config {
+    /**
+     * Load the configuration from the environment
+     *
+     * @return The configuration, missing values fall back to the defaults,
+     *         or the error of a file which can not be read
+     */
+    pub fn from_env() -> Result<Self, String> {
+        let default = Config::default();
+        Ok(Config {
+            password_hash: PasswordHashConfig {
+                memory_cost: env_or("ARGON2_MEMORY_COST", default.password_hash.memory_cost),
+                time_cost: env_or("ARGON2_TIME_COST", default.password_hash.time_cost),
+                parallelism: env_or("ARGON2_PARALLELISM", default.password_hash.parallelism),
+            },
+            password_policy: PasswordPolicyConfig {
+                min_length: env_or("PASSWORD_MIN_LENGTH", default.password_policy.min_length),
+                max_length: env_or("PASSWORD_MAX_LENGTH", default.password_policy.max_length),
+                require_letter: env_or(
+                    "PASSWORD_REQUIRE_LETTER",
+                    default.password_policy.require_letter,
+                ),
+                require_lowercase: env_or(
+                    "PASSWORD_REQUIRE_LOWERCASE",
+                    default.password_policy.require_lowercase,
+                ),
+                require_uppercase: env_or(
+                    "PASSWORD_REQUIRE_UPPERCASE",
+                    default.password_policy.require_uppercase,
+                ),
+                require_digit: env_or(
+                    "PASSWORD_REQUIRE_DIGIT",
+                    default.password_policy.require_digit,
+                ),
+                require_symbol: env_or(
+                    "PASSWORD_REQUIRE_SYMBOL",
+                    default.password_policy.require_symbol,
+                ),
+                min_score: env_or("PASSWORD_MIN_SCORE", default.password_policy.min_score),
+                reject_common: env_or(
+                    "PASSWORD_REJECT_COMMON",
+                    default.password_policy.reject_common,
+                ),
+            },
+            token: TokenConfig::from_env(default.token)?,
+            session: SessionConfig {
+                idle_timeout: env_or("SESSION_IDLE_TIMEOUT", default.session.idle_timeout),
+                absolute_timeout: env_or(
+                    "SESSION_ABSOLUTE_TIMEOUT",
+                    default.session.absolute_timeout,
+                ),
+                remember_idle_timeout: env_or(
+                    "SESSION_REMEMBER_IDLE_TIMEOUT",
+                    default.session.remember_idle_timeout,
+                ),
+                remember_absolute_timeout: env_or(
+                    "SESSION_REMEMBER_ABSOLUTE_TIMEOUT",
+                    default.session.remember_absolute_timeout,
+                ),
+            },
+            login_throttle: LoginThrottleConfig {
+                max_user_failures: env_or(
+                    "LOGIN_MAX_USER_FAILURES",
+                    default.login_throttle.max_user_failures,
+                ),
+                max_ip_failures: env_or(
+                    "LOGIN_MAX_IP_FAILURES",
+                    default.login_throttle.max_ip_failures,
+                ),
+                failure_window: env_or(
+                    "LOGIN_FAILURE_WINDOW",
+                    default.login_throttle.failure_window,
+                ),
+                lockout_duration: env_or(
+                    "LOGIN_LOCKOUT_DURATION",
+                    default.login_throttle.lockout_duration,
+                ),
+                base_delay: env_or("LOGIN_BASE_DELAY", default.login_throttle.base_delay),
+                max_delay: env_or("LOGIN_MAX_DELAY", default.login_throttle.max_delay),
+                trust_proxy_headers: env_or(
+                    "TRUST_PROXY_HEADERS",
+                    default.login_throttle.trust_proxy_headers,
+                ),
+            },
+            smtp: SmtpConfig {
+                host: env_or("SMTP_HOST", default.smtp.host),
+                port: env_or("SMTP_PORT", default.smtp.port),
+                username: env_or("SMTP_USERNAME", default.smtp.username),
+                password: env_or("SMTP_PASSWORD", default.smtp.password),
+                from: env_or("MAIL_FROM", default.smtp.from),
+            },
+            password_reset: PasswordResetConfig {
+                token_lifetime: env_or(
+                    "PASSWORD_RESET_LIFETIME",
+                    default.password_reset.token_lifetime,
+                ),
+                url: env_or("PASSWORD_RESET_URL", default.password_reset.url),
+            },
+            email_verification: EmailVerificationConfig {
+                code_lifetime: env_or(
+                    "EMAIL_VERIFICATION_LIFETIME",
+                    default.email_verification.code_lifetime,
+                ),
+                resend_interval: env_or(
+                    "EMAIL_VERIFICATION_RESEND_INTERVAL",
+                    default.email_verification.resend_interval,
+                ),
+                url: env_or("EMAIL_VERIFICATION_URL", default.email_verification.url),
+                required: env_or(
+                    "EMAIL_VERIFICATION_REQUIRED",
+                    default.email_verification.required,
+                ),
+            },
+            phone_code: PhoneCodeConfig {
+                code_lifetime: env_or("PHONE_CODE_LIFETIME", default.phone_code.code_lifetime),
+                max_attempts: env_or("PHONE_CODE_MAX_ATTEMPTS", default.phone_code.max_attempts),
+                resend_interval: env_or(
+                    "PHONE_CODE_RESEND_INTERVAL",
+                    default.phone_code.resend_interval,
+                ),
+                max_sends_per_hour: env_or(
+                    "PHONE_CODE_MAX_SENDS_PER_HOUR",
+                    default.phone_code.max_sends_per_hour,
+                ),
+            },
+            two_factor: TwoFactorConfig {
+                issuer: env_or("TWO_FACTOR_ISSUER", default.two_factor.issuer),
+                skew: env_or("TWO_FACTOR_SKEW", default.two_factor.skew),
+                challenge_lifetime: env_or(
+                    "TWO_FACTOR_CHALLENGE_LIFETIME",
+                    default.two_factor.challenge_lifetime,
+                ),
+                max_attempts: env_or("TWO_FACTOR_MAX_ATTEMPTS", default.two_factor.max_attempts),
+                recovery_codes: env_or(
+                    "TWO_FACTOR_RECOVERY_CODES",
+                    default.two_factor.recovery_codes,
+                ),
+                secret_key: std::env::var("TWO_FACTOR_SECRET_KEY")
+                    .unwrap_or(default.two_factor.secret_key),
+            },
+            admin: AdminConfig {
+                bootstrap_username: env_or(
+                    "ADMIN_BOOTSTRAP_USERNAME",
+                    default.admin.bootstrap_username,
+                ),
+                impersonation_lifetime: env_or(
+                    "ADMIN_IMPERSONATION_LIFETIME",
+                    default.admin.impersonation_lifetime,
+                ),
+            },
+            identifier: IdentifierConfig {
+                phone_country_code: env_or(
+                    "PHONE_COUNTRY_CODE",
+                    default.identifier.phone_country_code,
+                ),
+            },
+            service_clients: ServiceClientsConfig::from_env(),
+            oauth: OAuthConfig {
+                issuer_url: env_or("OAUTH_ISSUER_URL", default.oauth.issuer_url),
+                consent_url: env_or("OAUTH_CONSENT_URL", default.oauth.consent_url),
+                code_lifetime: env_or("OAUTH_CODE_LIFETIME", default.oauth.code_lifetime),
+                id_token_lifetime: env_or(
+                    "OAUTH_ID_TOKEN_LIFETIME",
+                    default.oauth.id_token_lifetime,
+                ),
+            },
+            audit: AuditConfig {
+                retention_days: env_or("AUDIT_RETENTION_DAYS", default.audit.retention_days),
+                prune_interval: env_or("AUDIT_PRUNE_INTERVAL", default.audit.prune_interval),
+            },
+            account_deletion: AccountDeletionConfig {
+                grace_period: env_or(
+                    "ACCOUNT_DELETION_GRACE_PERIOD",
+                    default.account_deletion.grace_period,
+                ),
+                mode: match std::env::var("ACCOUNT_DELETION_MODE").as_deref() {
+                    Ok("purge") => DeletionMode::Purge,
+                    Ok("anonymize") => DeletionMode::Anonymize,
+                    _ => default.account_deletion.mode,
+                },
+                cleanup_interval: env_or(
+                    "ACCOUNT_DELETION_CLEANUP_INTERVAL",
+                    default.account_deletion.cleanup_interval,
+                ),
+            },
+            identity_providers: IdentityProvidersConfig::from_env(default.identity_providers),
+        })
+    }
+
+    /**
+     * Check the keys the stored secrets are protected with
+     *
+     * @return The variables which are missing or too short,
+     *         or the signing keys which can not sign and verify a token
+     *
+     * @note Without them a leaked database can be brute-forced offline,
+     *       so the service refuses to start instead of falling back to an empty key.
+     *       A broken key pair would otherwise only show when the first login fails
+     */
+    pub fn check_secrets(&self) -> Result<(), String> {
+        let weak: Vec<&str> = [
+            ("TOKEN_DIGEST_KEY", &self.token.digest_key),
+            ("TWO_FACTOR_SECRET_KEY", &self.two_factor.secret_key),
+        ]
+        .into_iter()
+        .filter(|(_, key)| key.len() < MIN_SECRET_KEY_LEN)
+        .map(|(name, _)| name)
+        .collect();
+        if !weak.is_empty() {
+            return Err(format!(
+                "{} must be set to at least {} characters",
+                weak.join(", "),
+                MIN_SECRET_KEY_LEN
+            ));
+        }
+        token_keys_check(&self.token).map_err(|err| match self.token.algorithm {
+            TokenAlgorithm::EdDSA => format!(
+                "JWT_PRIVATE_KEY and JWT_PUBLIC_KEY must name a matching EdDSA key pair: {}",
+                err
+            ),
+            TokenAlgorithm::HS256 => format!("JWT_SECRET can not sign tokens: {}", err),
+        })
+    }
+}
+
+impl TokenConfig {
+    /**
+     * Load the token configuration from the environment
+     * @param default The values used when a variable is missing
+     *
+     * @return The configuration, or the error of a key file which can not be read
+     *
+     * @note EdDSA keys are read from the PEM files named by JWT_PRIVATE_KEY and JWT_PUBLIC_KEY
+     */
+    fn from_env(default: TokenConfig) -> Result<Self, String> {
+        let algorithm = match std::env::var("JWT_ALGORITHM").as_deref() {
+            Ok("EdDSA") => TokenAlgorithm::EdDSA,
+            _ => default.algorithm,
+        };
+        let read_key = |key: &str| match std::env::var(key) {
+            Ok(path) => std::fs::read_to_string(&path)
+                .map_err(|err| format!("Failed to read the JWT key file {}: {}", path, err)),
+            Err(_) => Ok(String::new()),
+        };
+        Ok(TokenConfig {
+            algorithm,
+            secret: std::env::var("JWT_SECRET").unwrap_or(default.secret),
+            private_key: read_key("JWT_PRIVATE_KEY")?,
+            public_key: read_key("JWT_PUBLIC_KEY")?,
+            issuer: std::env::var("JWT_ISSUER").unwrap_or(default.issuer),
+            access_token_lifetime: env_or("ACCESS_TOKEN_LIFETIME", default.access_token_lifetime),
+            digest_key: std::env::var("TOKEN_DIGEST_KEY").unwrap_or(default.digest_key),
+        })
+    }
+}
+
+impl SessionConfig {
+    /**
+     * Get the idle and the absolute timeout of a session
+     * @param remember_me Whether the user asked to stay logged in
+     */
+    pub fn timeouts(&self, remember_me: bool) -> (i64, i64) {
+        if remember_me {
+            (self.remember_idle_timeout, self.remember_absolute_timeout)
+        } else {
+            (self.idle_timeout, self.absolute_timeout)
+        }
+    }
+}
+
+impl ServiceClientsConfig {
+    /**
+     * Load the service credentials from SERVICE_CLIENTS
+     *
+     * @note The variable holds "id:secret" pairs separated by commas, e.g. "discussions:s3cr3t,search:t0ken"
+     */
+    fn from_env() -> Self {
+        let clients = std::env::var("SERVICE_CLIENTS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|client| client.trim().split_once(':'))
+            .map(|(id, secret)| (id.to_string(), secret.to_string()))
+            .collect();
+        ServiceClientsConfig { clients }
+    }
+}
+
+impl IdentityProviderConfig {
+    /**
+     * Get the known settings of a well-known provider
+     * @param name The name of the provider
+     *
+     * @note Unknown providers start empty, every url must be configured
+     */
+    fn preset(name: &str) -> Self {
+        let mut provider = IdentityProviderConfig {
+            name: name.to_string(),
+            scopes: "openid profile email".to_string(),
+            subject_field: "sub".to_string(),
+            username_field: "preferred_username".to_string(),
+            email_field: "email".to_string(),
+            email_verified_field: "email_verified".to_string(),
+            ..Default::default()
+        };
+        if name == "github" {
+            provider.authorize_url = "https://github.com/login/oauth/authorize".to_string();
+            provider.token_url = "https://github.com/login/oauth/access_token".to_string();
+            provider.userinfo_url = "https://api.github.com/user".to_string();
+            provider.scopes = "read:user user:email".to_string();
+            provider.subject_field = "id".to_string();
+            provider.username_field = "login".to_string();
+        }
+        provider
+    }
+}
+
+impl IdentityProvidersConfig {
+    /**
+     * Load the providers named by IDENTITY_PROVIDERS
+     * @param default The values used when a variable is missing
+     *
+     * @note IDENTITY_PROVIDERS holds names separated by commas, e.g. "github,campus",
+     *       each provider reads IDENTITY_PROVIDER_<NAME>_CLIENT_ID, _CLIENT_SECRET, _AUTHORIZE_URL,
+     *       _TOKEN_URL, _USERINFO_URL, _SCOPES, _SUBJECT_FIELD, _USERNAME_FIELD, _EMAIL_FIELD
+     *       and _EMAIL_VERIFIED_FIELD
+     */
+    fn from_env(default: IdentityProvidersConfig) -> Self {
+        let providers = std::env::var("IDENTITY_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let preset = IdentityProviderConfig::preset(name);
+                let prefix = format!(
+                    "IDENTITY_PROVIDER_{}_",
+                    name.to_uppercase().replace('-', "_")
+                );
+                let var = |key: &str, default: String| {
+                    std::env::var(format!("{}{}", prefix, key)).unwrap_or(default)
+                };
+                IdentityProviderConfig {
+                    name: preset.name.clone(),
+                    client_id: var("CLIENT_ID", preset.client_id),
+                    client_secret: var("CLIENT_SECRET", preset.client_secret),
+                    authorize_url: var("AUTHORIZE_URL", preset.authorize_url),
+                    token_url: var("TOKEN_URL", preset.token_url),
+                    userinfo_url: var("USERINFO_URL", preset.userinfo_url),
+                    scopes: var("SCOPES", preset.scopes),
+                    subject_field: var("SUBJECT_FIELD", preset.subject_field),
+                    username_field: var("USERNAME_FIELD", preset.username_field),
+                    email_field: var("EMAIL_FIELD", preset.email_field),
+                    email_verified_field: var("EMAIL_VERIFIED_FIELD", preset.email_verified_field),
+                }
+            })
+            .collect();
+        IdentityProvidersConfig {
+            providers,
+            redirect_url: env_or("IDENTITY_PROVIDER_REDIRECT_URL", default.redirect_url),
+            state_lifetime: env_or("IDENTITY_PROVIDER_STATE_LIFETIME", default.state_lifetime),
+        }
+    }
+
+    /**
+     * Get the callback url registered at a provider
+     * @param provider The name of the provider
+     */
+    pub fn redirect_uri(&self, provider: &str) -> String {
+        self.redirect_url.replace("{provider}", provider)
+    }
+}
+
+impl Default for PasswordHashConfig {
+    // OWASP recommended minimum for Argon2id
+    fn default() -> Self {
+        PasswordHashConfig {
+            memory_cost: 19456,
+            time_cost: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        PasswordPolicyConfig {
+            min_length: 8,
+            max_length: 128,
+            require_letter: true,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: true,
+            require_symbol: false,
+            min_score: 2,
+            reject_common: true,
+        }
+    }
+}
+
+impl Default for TokenConfig {
+    // the random secret invalidates every access token after a restart, set JWT_SECRET in production.
+    // The digest key has no usable default, the service refuses to start until TOKEN_DIGEST_KEY is set
+    fn default() -> Self {
+        TokenConfig {
+            algorithm: TokenAlgorithm::HS256,
+            secret: nanoid::nanoid!(64),
+            private_key: String::new(),
+            public_key: String::new(),
+            issuer: "mlum".to_string(),
+            access_token_lifetime: 900,
+            digest_key: String::new(),
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            idle_timeout: 12 * 3600,
+            absolute_timeout: 7 * 24 * 3600,
+            remember_idle_timeout: 30 * 24 * 3600,
+            remember_absolute_timeout: 90 * 24 * 3600,
+        }
+    }
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        LoginThrottleConfig {
+            max_user_failures: 5,
+            max_ip_failures: 20,
+            failure_window: 900,
+            lockout_duration: 900,
+            base_delay: 250,
+            max_delay: 4000,
+            trust_proxy_headers: false,
+        }
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: "localhost".to_string(),
+            port: 587,
+            username: String::new(),
+            password: String::new(),
+            from: "mlum <noreply@localhost>".to_string(),
+        }
+    }
+}
+
+impl Default for PasswordResetConfig {
+    fn default() -> Self {
+        PasswordResetConfig {
+            token_lifetime: 3600,
+            url: "http://localhost:8080/password/reset?token={token}".to_string(),
+        }
+    }
+}
+
+impl Default for EmailVerificationConfig {
+    fn default() -> Self {
+        EmailVerificationConfig {
+            code_lifetime: 24 * 3600,
+            resend_interval: 60,
+            url: "http://localhost:8080/email/verify?code={code}".to_string(),
+            required: false,
+        }
+    }
+}
+
+impl Default for PhoneCodeConfig {
+    fn default() -> Self {
+        PhoneCodeConfig {
+            code_lifetime: 300,
+            max_attempts: 5,
+            resend_interval: 60,
+            max_sends_per_hour: 5,
+        }
+    }
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        AdminConfig {
+            bootstrap_username: String::new(),
+            impersonation_lifetime: 900,
+        }
+    }
+}
+
+impl Default for TwoFactorConfig {
+    fn default() -> Self {
+        TwoFactorConfig {
+            issuer: "mlum".to_string(),
+            skew: 1,
+            challenge_lifetime: 300,
+            max_attempts: 5,
+            recovery_codes: 10,
+            secret_key: String::new(),
+        }
+    }
+}
+
+impl Default for OAuthConfig {
+    fn default() -> Self {
+        OAuthConfig {
+            issuer_url: "http://localhost:9999".to_string(),
+            consent_url: "http://localhost:8080/oauth/consent".to_string(),
+            code_lifetime: 60,
+            id_token_lifetime: 3600,
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            retention_days: 365,
+            prune_interval: 3600,
+        }
+    }
+}
+
+impl Default for AccountDeletionConfig {
+    fn default() -> Self {
+        AccountDeletionConfig {
+            grace_period: 30 * 24 * 3600,
+            mode: DeletionMode::Anonymize,
+            cleanup_interval: 3600,
+        }
+    }
+}
+
+impl Default for IdentityProvidersConfig {
+    fn default() -> Self {
+        IdentityProvidersConfig {
+            providers: vec![],
+            redirect_url: "http://localhost:3000/login/{provider}/callback".to_string(),
+            state_lifetime: 600,
+        }
+    }
+}
+
+impl Default for IdentifierConfig {
+    fn default() -> Self {
+        IdentifierConfig {
+            phone_country_code: "86".to_string(),
+        }
+    }
+}
+
+/**
+ * Read an environment variable and parse it
+ * @param key The name of the variable
+ * @param default The value used when the variable is missing or invalid
+ */
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
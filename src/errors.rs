@@ -1,6 +1,11 @@
 use std::fmt;
 
-use actix_web::{error, error::Error as ActixError, http::StatusCode, HttpResponse};
+use actix_web::{
+    error,
+    error::Error as ActixError,
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpResponse,
+};
 use mongodb::error::Error as MongoError;
 use serde::Serialize;
 
@@ -20,21 +25,65 @@ impl WebError {
             message: WebErrorMessages::from_string(message),
         }
     }
+
+    /**
+     * Tell the client how long to wait before retrying
+     * @param seconds The delay in seconds, sent in the Retry-After header
+     */
+    pub fn with_retry_after(mut self, seconds: i64) -> Self {
+        self.message.retry_after = Some(seconds.max(1));
+        self
+    }
+
+    /**
+     * Explain a rejected input rule by rule
+     * @param details The broken rules, sent in the `details` field
+     */
+    pub fn with_details(mut self, details: Vec<WebErrorDetail>) -> Self {
+        self.message.details = details;
+        self
+    }
+
+    /**
+     * Attach the error code of the OAuth2 specification, e.g. "invalid_grant"
+     * @param error The error code, sent in the `error` field
+     */
+    pub fn with_oauth_error(mut self, error: &str) -> Self {
+        self.message.error = Some(error.to_string());
+        self
+    }
 }
 
 // Message for the error response
 #[derive(Debug, Serialize, Clone)]
 pub struct WebErrorMessages {
     pub error_message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<i64>,
+    // error code of the OAuth2 endpoints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<WebErrorDetail>,
 }
 impl WebErrorMessages {
     pub fn from_string(message: String) -> Self {
         WebErrorMessages {
             error_message: message,
+            retry_after: None,
+            error: None,
+            details: vec![],
         }
     }
 }
 
+// A rule broken by the input, e.g. a password too short
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct WebErrorDetail {
+    pub rule: String,
+    pub message: String,
+}
+
 // Status code for the error response
 #[derive(Debug)]
 pub struct WebErrorStatus(StatusCode);
@@ -52,7 +101,7 @@ impl Serialize for WebErrorStatus {
 
 impl fmt::Display for WebError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{}", self.message.error_message)
     }
 }
 
@@ -61,7 +110,11 @@ impl error::ResponseError for WebError {
         self.code.0
     }
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-        HttpResponse::build(self.status_code()).json(self.message.clone())
+        let mut response = HttpResponse::build(self.status_code());
+        if let Some(retry_after) = self.message.retry_after {
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+        }
+        response.json(self.message.clone())
     }
 }
 
@@ -91,7 +144,7 @@ impl From<bson::de::Error> for WebError {
             },
             EndOfStream => WebError {
                 code: WebErrorStatus(StatusCode::INTERNAL_SERVER_ERROR),
-                message: WebErrorMessages::from_string(format!("BSON End of stream error")),
+                message: WebErrorMessages::from_string("BSON End of stream error".to_string()),
             },
             DeserializationError { message, .. } => WebError {
                 code: WebErrorStatus(StatusCode::INTERNAL_SERVER_ERROR),
@@ -122,6 +175,19 @@ impl From<MongoError> for WebError {
         use mongodb::error::ErrorKind::*;
         let error_kind = value.kind.as_ref();
         match error_kind {
+            // duplicate key of a unique index
+            Write(mongodb::error::WriteFailure::WriteError(err)) if err.code == 11000 => WebError {
+                code: WebErrorStatus(StatusCode::CONFLICT),
+                message: WebErrorMessages::from_string(
+                    "The record conflicts with an existing one!".to_string(),
+                ),
+            },
+            Command(err) if err.code == 11000 => WebError {
+                code: WebErrorStatus(StatusCode::CONFLICT),
+                message: WebErrorMessages::from_string(
+                    "The record conflicts with an existing one!".to_string(),
+                ),
+            },
             InvalidArgument { message, .. } => WebError {
                 code: WebErrorStatus(StatusCode::BAD_REQUEST),
                 message: WebErrorMessages::from_string(format!(
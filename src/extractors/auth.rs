@@ -0,0 +1,138 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    dev::Payload, http::header::AUTHORIZATION, http::StatusCode, web, FromRequest, HttpMessage,
+    HttpRequest,
+};
+
+use crate::{
+    app_state::AppState,
+    errors::WebError,
+    models::sessions::{AuthenticatedUser, ClientInfo},
+    services::users::serv_user_token_verify,
+};
+
+// cookie used by browsers which can not set the Authorization header,
+// only read by the authorization page of the single sign-on
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+
+/**
+ * Read the access token of a request
+ * @param req The request
+ *
+ * @return The token of the `Authorization: Bearer` header
+ *
+ * @note The cookie is not accepted here, a cross-site form could send it along
+ */
+pub fn request_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/**
+ * Read the access token cookie of a request
+ * @param req The request
+ */
+pub fn cookie_token(req: &HttpRequest) -> Option<String> {
+    req.cookie(ACCESS_TOKEN_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/**
+ * Authenticate a browser by the access token cookie
+ * @param req The request
+ * @param app_state The state of the app
+ *
+ * @return The user, none if the cookie is missing or invalid
+ *
+ * @note Only for the GET authorization page, which changes nothing by itself
+ */
+pub async fn cookie_authenticate(
+    req: &HttpRequest,
+    app_state: &AppState,
+) -> Option<AuthenticatedUser> {
+    let token = cookie_token(req)?;
+    serv_user_token_verify(&app_state.database, &app_state.config, token)
+        .await
+        .ok()
+        .map(|auth| AuthenticatedUser {
+            client: ClientInfo::from(req),
+            ..auth
+        })
+}
+
+/**
+ * Protected handlers take an `AuthenticatedUser`,
+ * the request is rejected before the handler runs if the token is missing or invalid
+ */
+impl FromRequest for AuthenticatedUser {
+    type Error = WebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // already verified by a permission guard
+        if let Some(auth) = req.extensions().get::<AuthenticatedUser>().cloned() {
+            return Box::pin(async move { Ok(auth) });
+        }
+
+        let token = request_token(req);
+        let client = ClientInfo::from(req);
+        let app_state = req.app_data::<web::Data<AppState>>().cloned();
+
+        Box::pin(async move {
+            let app_state = app_state.ok_or_else(|| {
+                WebError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "App state is not configured!".to_string(),
+                )
+            })?;
+            let token = token.ok_or_else(|| {
+                WebError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "You need to login first!".to_string(),
+                )
+            })?;
+
+            serv_user_token_verify(&app_state.database, &app_state.config, token)
+                .await
+                .map(|auth| AuthenticatedUser { client, ..auth })
+        })
+    }
+}
+
+#[cfg(test)]
+mod auth_extractor_test {
+    use actix_web::{cookie::Cookie, test::TestRequest};
+
+    use super::*;
+
+    #[test]
+    fn test_request_token_header() {
+        let req = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer abc"))
+            .to_http_request();
+        assert_eq!(request_token(&req), Some("abc".to_string()));
+    }
+
+    #[test]
+    fn test_request_token_cookie() {
+        let req = TestRequest::default()
+            .cookie(Cookie::new(ACCESS_TOKEN_COOKIE, "abc"))
+            .to_http_request();
+        assert_eq!(request_token(&req), None);
+        assert_eq!(cookie_token(&req), Some("abc".to_string()));
+    }
+
+    #[test]
+    fn test_request_token_missing() {
+        let req = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Basic abc"))
+            .to_http_request();
+        assert_eq!(request_token(&req), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    Error, FromRequest, HttpMessage,
+};
+
+use crate::{
+    errors::WebError,
+    models::{roles::Permission, sessions::AuthenticatedUser},
+};
+
+/**
+ * Reject the requests of users who lack a permission.
+ * It can wrap a single route or a whole scope:
+ *
+ * `web::get().to(handler).wrap(RequirePermission::new(Permission::UsersRead))`
+ *
+ * @note The authenticated user is kept in the request extensions,
+ *       so the `AuthenticatedUser` of the handler does not verify the token twice
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct RequirePermission {
+    permission: Permission,
+}
+
+impl RequirePermission {
+    pub fn new(permission: Permission) -> Self {
+        RequirePermission { permission }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequirePermissionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePermissionMiddleware {
+            service: Rc::new(service),
+            permission: self.permission,
+        }))
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: Rc<S>,
+    permission: Permission,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let permission = self.permission;
+
+        Box::pin(async move {
+            let auth = AuthenticatedUser::extract(req.request()).await?;
+            if !auth.has(permission) {
+                return Err(
+                    WebError::new(StatusCode::FORBIDDEN, "Permission denied!".to_string()).into(),
+                );
+            }
+
+            req.extensions_mut().insert(auth);
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,124 @@
+/**
+ * route handlers for admins, permissions are checked by the guards of the router
+ */
+use crate::{
+    app_state,
+    errors::WebError,
+    models::{
+        audit_events::AuditFilter, roles::UpdateRole, sessions::AuthenticatedUser,
+        users::UserFilter,
+    },
+    services::{audit_events::serv_audit_event_list, users::*},
+};
+
+use actix_web::{web, HttpResponse};
+
+pub async fn admin_user_list(
+    auth: AuthenticatedUser,
+    filter: web::Query<UserFilter>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_list(&app_state.database, auth, filter.into_inner())
+        .await
+        .map(|page| HttpResponse::Ok().json(page))
+}
+
+pub async fn admin_user_find(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_find(&app_state.database, auth, username.into_inner())
+        .await
+        .map(|user| HttpResponse::Ok().json(user))
+}
+
+pub async fn admin_user_logout(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_force_logout(&app_state.database, auth, username.into_inner())
+        .await
+        .map(|_| HttpResponse::Ok().json("logout success"))
+}
+
+pub async fn admin_user_suspend(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_suspend(&app_state.database, auth, username.into_inner(), true)
+        .await
+        .map(|_| HttpResponse::Ok().json("suspend success"))
+}
+
+pub async fn admin_user_unsuspend(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_suspend(&app_state.database, auth, username.into_inner(), false)
+        .await
+        .map(|_| HttpResponse::Ok().json("unsuspend success"))
+}
+
+pub async fn admin_user_restore(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_restore(&app_state.database, auth, username.into_inner())
+        .await
+        .map(|user| HttpResponse::Ok().json(user))
+}
+
+pub async fn admin_user_purge(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_purge(&app_state.database, auth, username.into_inner())
+        .await
+        .map(|_| HttpResponse::Ok().json("purge success"))
+}
+
+pub async fn admin_user_impersonate(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_impersonate_start(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        username.into_inner(),
+    )
+    .await
+    .map(|token| HttpResponse::Created().json(token))
+}
+
+pub async fn admin_audit_list(
+    filter: web::Query<AuditFilter>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_audit_event_list(&app_state.database, filter.into_inner())
+        .await
+        .map(|page| HttpResponse::Ok().json(page))
+}
+
+pub async fn admin_user_role_update(
+    auth: AuthenticatedUser,
+    username: web::Path<String>,
+    role: web::Json<UpdateRole>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_role_update(
+        &app_state.database,
+        auth,
+        username.into_inner(),
+        role.into_inner(),
+    )
+    .await
+    .map(|user| HttpResponse::Ok().json(user))
+}
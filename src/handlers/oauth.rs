@@ -0,0 +1,304 @@
+/**
+ * route handlers for the single sign-on (OAuth2 / OpenID Connect provider)
+ */
+use crate::{
+    app_state,
+    errors::WebError,
+    extractors::{auth::cookie_authenticate, service::request_basic_credentials},
+    models::{
+        oauth::{AuthorizeDecision, AuthorizeRequest, OAuthTokenRequest, RegisterClient},
+        sessions::{AuthenticatedUser, ClientInfo},
+    },
+    services::oauth::*,
+};
+
+use actix_web::{
+    http::header::{CACHE_CONTROL, LOCATION},
+    web, HttpRequest, HttpResponse,
+};
+
+pub async fn oauth_configuration(app_state: web::Data<app_state::AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serv_oauth_configuration(&app_state.config))
+}
+
+pub async fn oauth_jwks(app_state: web::Data<app_state::AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serv_oauth_jwks(&app_state.config))
+}
+
+// the browser is redirected, the user does not have to be logged in yet
+// and may be logged in by the access token cookie
+pub async fn oauth_authorize(
+    req: HttpRequest,
+    auth: Option<AuthenticatedUser>,
+    request: web::Query<AuthorizeRequest>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    let auth = match auth {
+        Some(auth) => Some(auth),
+        None => cookie_authenticate(&req, &app_state).await,
+    };
+    serv_oauth_authorize(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        request.into_inner(),
+    )
+    .await
+    .map(|location| {
+        HttpResponse::Found()
+            .insert_header((LOCATION, location))
+            .finish()
+    })
+}
+
+pub async fn oauth_authorize_decide(
+    auth: AuthenticatedUser,
+    decision: web::Json<AuthorizeDecision>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_oauth_authorize_decide(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        decision.into_inner(),
+    )
+    .await
+    .map(|redirect| HttpResponse::Ok().json(redirect))
+}
+
+pub async fn oauth_token(
+    req: HttpRequest,
+    request: web::Form<OAuthTokenRequest>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_oauth_token(
+        &app_state.database,
+        &app_state.config,
+        request_basic_credentials(&req),
+        request.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|tokens| {
+        HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .json(tokens)
+    })
+}
+
+pub async fn oauth_userinfo(auth: AuthenticatedUser) -> Result<HttpResponse, WebError> {
+    serv_oauth_userinfo(auth).map(|info| HttpResponse::Ok().json(info))
+}
+
+pub async fn oauth_client_register(
+    register: web::Json<RegisterClient>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_oauth_client_register(
+        &app_state.database,
+        &app_state.config,
+        register.into_inner(),
+    )
+    .await
+    .map(|client| HttpResponse::Created().json(client))
+}
+
+pub async fn oauth_client_list(
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_oauth_client_list(&app_state.database)
+        .await
+        .map(|clients| HttpResponse::Ok().json(clients))
+}
+
+pub async fn oauth_client_remove(
+    client_id: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_oauth_client_remove(&app_state.database, client_id.into_inner())
+        .await
+        .map(|_| HttpResponse::Ok().json("client removed"))
+}
+
+#[cfg(test)]
+mod oauth_handler_test {
+    use actix_web::{http::StatusCode, test, web, App};
+    use data_encoding::BASE64;
+    use percent_encoding::percent_decode_str;
+    use tokio::sync::Mutex;
+
+    use crate::{
+        mailers::memory::MemoryMailer,
+        models::{
+            oauth::{
+                AuthorizeDecision, AuthorizeRedirect, AuthorizeRequest, OAuthTokenResponse,
+                RegisterClient, UserInfo,
+            },
+            users::CreateUser,
+        },
+        routers::oauth::oauth_routers,
+        services::{oauth::serv_oauth_client_register, users::serv_user_register},
+        sms::memory::MemorySmsSender,
+        stores::login_attempts::MemoryLoginAttemptStore,
+        utils::oauth::url_with_query,
+    };
+
+    async fn create_app_state() -> crate::app_state::AppState {
+        let database = mongodb::Client::with_uri_str("mongodb://localhost:27017")
+            .await
+            .unwrap();
+        crate::app_state::AppState {
+            database,
+            visit_count: Mutex::new(0),
+            health_check_response: "I'm fine".to_string(),
+            config: crate::config::Config::default(),
+            login_attempts: Box::new(MemoryLoginAttemptStore::default()),
+            mailer: Box::new(MemoryMailer::default()),
+            sms_sender: Box::new(MemorySmsSender::default()),
+            identity_providers: Default::default(),
+        }
+    }
+
+    fn query_param(url: &str, key: &str) -> Option<String> {
+        let (_, query) = url.split_once('?')?;
+        query.split('&').find_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            (name == key).then(|| percent_decode_str(value).decode_utf8_lossy().to_string())
+        })
+    }
+
+    // a local client going through the whole authorization-code flow
+    #[tokio::test]
+    async fn test_oauth_authorization_code_flow() {
+        let app_state = web::Data::new(create_app_state().await);
+        let username = format!("sso_{}", nanoid::nanoid!(8));
+        let login = serv_user_register(
+            &app_state.database,
+            &app_state.config,
+            app_state.mailer.as_ref(),
+            CreateUser {
+                username: username.clone(),
+                password: "Tide-pool-lantern-42".into(),
+                phone: "".into(),
+                email: "".into(),
+            },
+            Default::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let redirect_uri = "http://localhost:9000/callback";
+        let client = serv_oauth_client_register(
+            &app_state.database,
+            &app_state.config,
+            RegisterClient {
+                name: "test client".into(),
+                redirect_uris: vec![redirect_uri.into()],
+                public: false,
+            },
+        )
+        .await
+        .unwrap();
+        let client_id = client.info.client_id;
+        let client_secret = client.client_secret.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .configure(oauth_routers),
+        )
+        .await;
+        let bearer = ("Authorization", format!("Bearer {}", login.access_token));
+        // example of RFC 7636 appendix B
+        let authorize = AuthorizeRequest {
+            response_type: "code".into(),
+            client_id: client_id.clone(),
+            redirect_uri: redirect_uri.into(),
+            scope: "profile".into(),
+            state: Some("xyz".into()),
+            code_challenge: Some("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".into()),
+            code_challenge_method: Some("S256".into()),
+            nonce: Some("n-0S6_WzA2Mj".into()),
+        };
+
+        // the user approves on the consent page
+        let request = test::TestRequest::post()
+            .uri("/oauth/authorize")
+            .insert_header(bearer.clone())
+            .set_json(AuthorizeDecision {
+                request: authorize.clone(),
+                approve: true,
+            })
+            .to_request();
+        let redirect: AuthorizeRedirect = test::call_and_read_body_json(&app, request).await;
+        assert!(redirect.redirect_to.starts_with(redirect_uri));
+        assert_eq!(
+            query_param(&redirect.redirect_to, "state").as_deref(),
+            Some("xyz")
+        );
+        let code = query_param(&redirect.redirect_to, "code").unwrap();
+
+        // the client exchanges the code
+        let credentials = BASE64.encode(format!("{}:{}", client_id, client_secret).as_bytes());
+        let request = test::TestRequest::post()
+            .uri("/oauth/token")
+            .insert_header(("Authorization", format!("Basic {}", credentials)))
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", redirect_uri),
+                (
+                    "code_verifier",
+                    "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+                ),
+            ])
+            .to_request();
+        let tokens: OAuthTokenResponse = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(tokens.scope, "profile");
+        // ID tokens are only signed with the EdDSA key pair
+        assert!(tokens.id_token.is_none());
+
+        // a code is only used once
+        let request = test::TestRequest::post()
+            .uri("/oauth/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", redirect_uri),
+                (
+                    "code_verifier",
+                    "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+                ),
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+            ])
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = test::TestRequest::get()
+            .uri("/oauth/userinfo")
+            .insert_header(("Authorization", format!("Bearer {}", tokens.access_token)))
+            .to_request();
+        let info: UserInfo = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(info.preferred_username, Some(username));
+
+        // the consent is remembered, the browser goes straight back to the client
+        let request = test::TestRequest::get()
+            .uri(&url_with_query(
+                "/oauth/authorize",
+                &authorize.query_pairs(),
+            ))
+            .insert_header(bearer)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(location.starts_with(redirect_uri));
+    }
+}
@@ -4,39 +4,91 @@
 use crate::{
     app_state,
     errors::WebError,
-    models::users::{CertificateUser, CreateUser, QueryUserName, User},
+    models::{
+        access_tokens::CreateAccessToken,
+        email_verifications::VerifyEmail,
+        introspection::{IntrospectToken, ServiceClient},
+        linked_identities::{ExternalCallback, ExternalLoginStart},
+        login_challenges::LoginTwoFactor,
+        password_resets::{ForgotPassword, ResetPassword},
+        phone_codes::{LoginWithCode, SendLoginCode, VerifyPhone},
+        sessions::{AuthenticatedUser, ClientInfo, RefreshToken},
+        two_factors::TwoFactorCode,
+        users::{ChangePassword, CreateUser, LoginUser, QueryUserName, User},
+    },
     services::users::*,
 };
 
 use actix_web::{web, HttpRequest, HttpResponse};
 
 pub async fn user_register(
+    req: HttpRequest,
     user_info: web::Json<CreateUser>,
     app_state: web::Data<app_state::AppState>,
 ) -> Result<HttpResponse, WebError> {
-    serv_user_register(&app_state.database, user_info.into_inner())
-        .await
-        .map(|token| HttpResponse::Ok().json(token))
+    serv_user_register(
+        &app_state.database,
+        &app_state.config,
+        app_state.mailer.as_ref(),
+        user_info.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|token| match token {
+        Some(token) => HttpResponse::Ok().json(token),
+        None => HttpResponse::Accepted().json("verification mail sent"),
+    })
 }
 
 pub async fn user_login(
-    user_info: web::Json<CreateUser>,
+    req: HttpRequest,
+    user_info: web::Json<LoginUser>,
     app_state: web::Data<app_state::AppState>,
 ) -> Result<HttpResponse, WebError> {
-    serv_user_login(&app_state.database, user_info.into_inner())
-        .await
-        .map(|token| HttpResponse::Ok().json(token))
+    serv_user_login(
+        &app_state.database,
+        &app_state.config,
+        app_state.login_attempts.as_ref(),
+        user_info.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|token| HttpResponse::Ok().json(token))
+}
+
+pub async fn user_token_refresh(
+    req: HttpRequest,
+    refresh_token: web::Json<RefreshToken>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_token_refresh(
+        &app_state.database,
+        &app_state.config,
+        refresh_token.into_inner().refresh_token,
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|token| HttpResponse::Ok().json(token))
 }
 
 pub async fn user_logout(
-    token_: web::Json<String>,
+    auth: AuthenticatedUser,
     app_state: web::Data<app_state::AppState>,
 ) -> Result<HttpResponse, WebError> {
-    serv_user_logout(&app_state.database, token_.into_inner())
+    serv_user_logout(&app_state.database, auth)
         .await
         .map(|_| HttpResponse::Ok().json("logout success"))
 }
 
+pub async fn user_impersonate_stop(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_impersonate_stop(&app_state.database, auth)
+        .await
+        .map(|_| HttpResponse::Ok().json("impersonation stopped"))
+}
+
 // Use request -> param instead of web::json
 pub async fn user_profile(
     req: HttpRequest,
@@ -49,50 +101,497 @@ pub async fn user_profile(
 }
 
 pub async fn user_update(
+    auth: AuthenticatedUser,
     user_info: web::Json<User>,
     app_state: web::Data<app_state::AppState>,
 ) -> Result<HttpResponse, WebError> {
-    serv_user_update(&app_state.database, user_info.into_inner())
-        .await
-        .map(|user| HttpResponse::Ok().json(user))
+    serv_user_update(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        user_info.into_inner(),
+    )
+    .await
+    .map(|user| HttpResponse::Ok().json(user))
 }
 
 pub async fn user_delete(
-    certification: web::Json<CertificateUser>,
+    auth: AuthenticatedUser,
     app_state: web::Data<app_state::AppState>,
 ) -> Result<HttpResponse, WebError> {
-    serv_user_delete(&app_state.database, certification.into_inner())
+    serv_user_delete(&app_state.database, auth)
         .await
         .map(|_| HttpResponse::Ok().json("delete success"))
 }
 
-pub async fn user_verify(
-    user_info: web::Json<CertificateUser>,
+pub async fn user_password_change(
+    auth: AuthenticatedUser,
+    change: web::Json<ChangePassword>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_password_change(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        change.into_inner(),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("password changed"))
+}
+
+pub async fn user_password_forgot(
+    req: HttpRequest,
+    forgot: web::Json<ForgotPassword>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_password_forgot(
+        &app_state.database,
+        &app_state.config,
+        app_state.mailer.as_ref(),
+        forgot.into_inner().email,
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("reset mail sent"))
+}
+
+pub async fn user_password_reset(
+    req: HttpRequest,
+    reset: web::Json<ResetPassword>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_password_reset(
+        &app_state.database,
+        &app_state.config,
+        reset.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("reset success"))
+}
+
+pub async fn user_email_verify(
+    req: HttpRequest,
+    verify: web::Json<VerifyEmail>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_email_verify(
+        &app_state.database,
+        &app_state.config,
+        verify.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("email verified"))
+}
+
+pub async fn user_email_resend(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_email_resend(
+        &app_state.database,
+        &app_state.config,
+        app_state.mailer.as_ref(),
+        auth,
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("verification mail sent"))
+}
+
+pub async fn user_phone_send(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_phone_send(
+        &app_state.database,
+        &app_state.config,
+        app_state.sms_sender.as_ref(),
+        auth,
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("code sent"))
+}
+
+pub async fn user_phone_verify(
+    auth: AuthenticatedUser,
+    verify: web::Json<VerifyPhone>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_phone_verify(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        verify.into_inner(),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("phone verified"))
+}
+
+pub async fn user_login_code_send(
+    req: HttpRequest,
+    send: web::Json<SendLoginCode>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_login_code_send(
+        &app_state.database,
+        &app_state.config,
+        app_state.sms_sender.as_ref(),
+        send.into_inner().phone,
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("code sent"))
+}
+
+pub async fn user_login_code(
+    req: HttpRequest,
+    login: web::Json<LoginWithCode>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_login_code(
+        &app_state.database,
+        &app_state.config,
+        app_state.login_attempts.as_ref(),
+        login.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|token| HttpResponse::Ok().json(token))
+}
+
+pub async fn user_login_two_factor(
+    req: HttpRequest,
+    login: web::Json<LoginTwoFactor>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_login_two_factor(
+        &app_state.database,
+        &app_state.config,
+        app_state.login_attempts.as_ref(),
+        login.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|token| HttpResponse::Ok().json(token))
+}
+
+pub async fn user_two_factor_setup(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_two_factor_setup(&app_state.database, &app_state.config, auth)
+        .await
+        .map(|setup| HttpResponse::Ok().json(setup))
+}
+
+pub async fn user_two_factor_enable(
+    auth: AuthenticatedUser,
+    code: web::Json<TwoFactorCode>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_two_factor_enable(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        code.into_inner(),
+    )
+    .await
+    .map(|codes| HttpResponse::Ok().json(codes))
+}
+
+pub async fn user_two_factor_disable(
+    auth: AuthenticatedUser,
+    code: web::Json<TwoFactorCode>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_two_factor_disable(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        code.into_inner(),
+    )
+    .await
+    .map(|_| HttpResponse::Ok().json("two-factor authentication disabled"))
+}
+
+pub async fn user_two_factor_recovery(
+    auth: AuthenticatedUser,
+    code: web::Json<TwoFactorCode>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_two_factor_recovery(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        code.into_inner(),
+    )
+    .await
+    .map(|codes| HttpResponse::Ok().json(codes))
+}
+
+pub async fn user_access_token_create(
+    auth: AuthenticatedUser,
+    create: web::Json<CreateAccessToken>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_access_token_create(
+        &app_state.database,
+        &app_state.config,
+        auth,
+        create.into_inner(),
+    )
+    .await
+    .map(|token| HttpResponse::Created().json(token))
+}
+
+pub async fn user_access_token_list(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_access_token_list(&app_state.database, auth)
+        .await
+        .map(|tokens| HttpResponse::Ok().json(tokens))
+}
+
+pub async fn user_access_token_revoke(
+    auth: AuthenticatedUser,
+    token_id: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_access_token_revoke(&app_state.database, auth, token_id.into_inner())
+        .await
+        .map(|_| HttpResponse::Ok().json("token revoked"))
+}
+
+pub async fn user_session_list(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_session_list(&app_state.database, auth)
+        .await
+        .map(|sessions| HttpResponse::Ok().json(sessions))
+}
+
+pub async fn user_session_revoke(
+    auth: AuthenticatedUser,
+    session_id: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_session_revoke(&app_state.database, auth, session_id.into_inner())
+        .await
+        .map(|_| HttpResponse::Ok().json("session revoked"))
+}
+
+pub async fn user_session_revoke_others(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_session_revoke_others(&app_state.database, auth)
+        .await
+        .map(|_| HttpResponse::Ok().json("other sessions revoked"))
+}
+
+pub async fn user_identity_providers(
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    Ok(HttpResponse::Ok().json(app_state.identity_providers.names()))
+}
+
+pub async fn user_external_login_start(
+    provider: web::Path<String>,
+    start: Option<web::Json<ExternalLoginStart>>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_external_login_start(
+        &app_state.database,
+        &app_state.config,
+        &app_state.identity_providers,
+        provider.into_inner(),
+        start.map(|start| start.into_inner()).unwrap_or_default(),
+    )
+    .await
+    .map(|authorization| HttpResponse::Ok().json(authorization))
+}
+
+pub async fn user_external_login(
+    req: HttpRequest,
+    provider: web::Path<String>,
+    callback: web::Json<ExternalCallback>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_external_login(
+        &app_state.database,
+        &app_state.config,
+        app_state.login_attempts.as_ref(),
+        &app_state.identity_providers,
+        provider.into_inner(),
+        callback.into_inner(),
+        ClientInfo::from(&req),
+    )
+    .await
+    .map(|token| HttpResponse::Ok().json(token))
+}
+
+pub async fn user_identity_list(
+    auth: AuthenticatedUser,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_identity_list(&app_state.database, auth)
+        .await
+        .map(|identities| HttpResponse::Ok().json(identities))
+}
+
+pub async fn user_identity_link_start(
+    auth: AuthenticatedUser,
+    provider: web::Path<String>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_identity_link_start(
+        &app_state.database,
+        &app_state.config,
+        &app_state.identity_providers,
+        auth,
+        provider.into_inner(),
+    )
+    .await
+    .map(|authorization| HttpResponse::Ok().json(authorization))
+}
+
+pub async fn user_identity_link(
+    auth: AuthenticatedUser,
+    provider: web::Path<String>,
+    callback: web::Json<ExternalCallback>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_identity_link(
+        &app_state.database,
+        &app_state.config,
+        &app_state.identity_providers,
+        auth,
+        provider.into_inner(),
+        callback.into_inner(),
+    )
+    .await
+    .map(|identities| HttpResponse::Ok().json(identities))
+}
+
+pub async fn user_identity_unlink(
+    auth: AuthenticatedUser,
+    provider: web::Path<String>,
     app_state: web::Data<app_state::AppState>,
 ) -> Result<HttpResponse, WebError> {
-    serv_user_verify(&app_state.database, user_info.into_inner())
+    serv_user_identity_unlink(&app_state.database, auth, provider.into_inner())
         .await
-        .map(|_| HttpResponse::Ok().json("certificate success"))
+        .map(|_| HttpResponse::Ok().json("identity unlinked"))
+}
+
+// the service credentials have been verified by the extractor
+pub async fn user_token_introspect(
+    _client: ServiceClient,
+    introspect: web::Form<IntrospectToken>,
+    app_state: web::Data<app_state::AppState>,
+) -> Result<HttpResponse, WebError> {
+    serv_user_token_introspect(
+        &app_state.database,
+        &app_state.config,
+        introspect.into_inner(),
+    )
+    .await
+    .map(|introspection| HttpResponse::Ok().json(introspection))
+}
+
+// the token has been verified by the extractor
+pub async fn user_verify(_auth: AuthenticatedUser) -> Result<HttpResponse, WebError> {
+    Ok(HttpResponse::Ok().json("certificate success"))
 }
 
 #[cfg(test)]
 mod user_handler_test {
-    use actix_web::{body::MessageBody, web};
+    use actix_web::{body::MessageBody, http::StatusCode, web, FromRequest, ResponseError};
     use tokio::sync::Mutex;
 
-    use crate::models::users::{CertificateUser, CreateUser, User};
+    use crate::{
+        identity_providers::{memory::MemoryIdentityProvider, ExternalIdentity, IdentityProviders},
+        mailers::memory::MemoryMailer,
+        models::{
+            linked_identities::{ExternalAuthorization, ExternalCallback},
+            roles::Role,
+            sessions::{AuthenticatedUser, ImpersonationToken, TokenPair},
+            users::{ChangePassword, CreateUser, LoginUser, User},
+        },
+        services::users::{serv_user_database, serv_user_register},
+        sms::memory::MemorySmsSender,
+        stores::login_attempts::MemoryLoginAttemptStore,
+    };
 
     async fn create_app_state() -> crate::app_state::AppState {
         let database = mongodb::Client::with_uri_str("mongodb://localhost:27017")
             .await
             .unwrap();
+        // every app state must sign tokens with the same key
+        let mut config = crate::config::Config::default();
+        config.token.secret = "mlum test secret".to_string();
         crate::app_state::AppState {
             database,
             visit_count: Mutex::new(0),
             health_check_response: "I'm fine".to_string(),
+            config,
+            login_attempts: Box::new(MemoryLoginAttemptStore::default()),
+            mailer: Box::new(MemoryMailer::default()),
+            sms_sender: Box::new(MemorySmsSender::default()),
+            identity_providers: IdentityProviders::new(vec![Box::new(
+                MemoryIdentityProvider::new("mock").with_identity(
+                    "mock-code",
+                    ExternalIdentity {
+                        subject: "mock-42".to_string(),
+                        username: Some("octocat".to_string()),
+                        email: None,
+                        email_verified: false,
+                    },
+                ),
+            )]),
         }
     }
 
+    const TEST_PASSWORD: &str = "Tide-pool-lantern-42";
+
+    // a new user for every test, so they do not depend on the data already in the database
+    async fn create_user(app_state: &crate::app_state::AppState, role: Role) -> String {
+        let username = format!("test_{}", nanoid::nanoid!(8));
+        serv_user_register(
+            &app_state.database,
+            &app_state.config,
+            app_state.mailer.as_ref(),
+            CreateUser {
+                username: username.clone(),
+                password: TEST_PASSWORD.into(),
+                phone: "".into(),
+                email: "".into(),
+            },
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        serv_user_database(&app_state.database)
+            .update_one(
+                mongodb::bson::doc! {"username": &username, "is_deprecated": false},
+                mongodb::bson::doc! {"$set": {"role": role}},
+                None,
+            )
+            .await
+            .unwrap();
+        username
+    }
+
+    async fn authenticate(token: String) -> AuthenticatedUser {
+        let request = actix_web::test::TestRequest::default()
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .app_data(web::Data::new(create_app_state().await))
+            .to_http_request();
+        AuthenticatedUser::extract(&request).await.unwrap()
+    }
+
     #[tokio::test]
     #[ignore = "username is not unique"]
     async fn test_user_register() {
@@ -106,7 +605,12 @@ mod user_handler_test {
             email: "".into(),
         });
         // call user_register
-        let result = super::user_register(user_info, web::Data::new(app_state)).await;
+        let result = super::user_register(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         // assert
         assert!(result.is_ok());
     }
@@ -114,54 +618,67 @@ mod user_handler_test {
     #[tokio::test]
     async fn test_user_login() {
         let app_state = create_app_state().await;
-        let user_info = web::Json(CreateUser {
-            username: "dessera".into(),
-            password: "123456".into(),
-            phone: "".into(),
-            email: "".into(),
+        let username = create_user(&app_state, Role::User).await;
+        let user_info = web::Json(LoginUser {
+            identifier: username,
+            password: TEST_PASSWORD.into(),
+            remember_me: false,
         });
-        let result = super::user_login(user_info, web::Data::new(app_state)).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
+    #[allow(clippy::nonminimal_bool)]
     async fn test_user_login_fail() {
         let app_state = create_app_state().await;
-        let user_info = web::Json(CreateUser {
-            username: "dessera".into(),
+        let user_info = web::Json(LoginUser {
+            identifier: "dessera".into(),
             password: "123456".into(),
-            phone: "".into(),
-            email: "".into(),
+            remember_me: false,
         });
-        let result = super::user_login(user_info, web::Data::new(app_state)).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(!result.is_ok());
     }
 
     #[tokio::test]
     async fn test_user_logout() {
         let app_state = create_app_state().await;
-        let user_info = web::Json(CreateUser {
-            username: "dessera".into(),
-            password: "123456".into(),
-            phone: "".into(),
-            email: "".into(),
+        let username = create_user(&app_state, Role::User).await;
+        let user_info = web::Json(LoginUser {
+            identifier: username,
+            password: TEST_PASSWORD.into(),
+            remember_me: false,
         });
 
         // login first
-        let result = super::user_login(user_info, web::Data::new(app_state)).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(result.is_ok());
 
         // get token
-        let token = result.unwrap().into_body().try_into_bytes().unwrap().into();
-        let token = String::from_utf8(token).unwrap();
-
-        // remove the first and last "
-        let token = token[1..token.len() - 1].to_string();
+        let tokens = result.unwrap().into_body().try_into_bytes().unwrap();
+        let tokens: TokenPair = serde_json::from_slice(&tokens).unwrap();
+        let token = tokens.access_token;
 
         // logout
         let app_state = create_app_state().await;
-        let token = web::Json(token);
-        let result = super::user_logout(token, web::Data::new(app_state)).await;
+        let auth = authenticate(token).await;
+        let result = super::user_logout(auth, web::Data::new(app_state)).await;
         assert!(result.is_ok());
     }
 
@@ -180,20 +697,23 @@ mod user_handler_test {
     async fn test_user_update() {
         // login first
         let app_state = create_app_state().await;
-        let user_info = web::Json(CreateUser {
-            username: "dessera".into(),
+        let user_info = web::Json(LoginUser {
+            identifier: "dessera".into(),
             password: "123456".into(),
-            phone: "".into(),
-            email: "".into(),
+            remember_me: false,
         });
-        let result = super::user_login(user_info, web::Data::new(app_state)).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(result.is_ok());
 
         // get token
-        let token = result.unwrap().into_body().try_into_bytes().unwrap().into();
-        let token = String::from_utf8(token).unwrap();
-        // remove the first and last "
-        let token = token[1..token.len() - 1].to_string();
+        let tokens = result.unwrap().into_body().try_into_bytes().unwrap();
+        let tokens: TokenPair = serde_json::from_slice(&tokens).unwrap();
+        let token = tokens.access_token;
 
         // get user info
         let app_state = create_app_state().await;
@@ -210,13 +730,16 @@ mod user_handler_test {
         let user = result.unwrap().into_body().try_into_bytes().unwrap().into();
         let user = String::from_utf8(user).unwrap();
         let mut user: User = serde_json::from_str(&user).unwrap();
-        user.token = token;
-        user.password = String::from("123456");
         user.description = String::from("C++ programmer");
 
         // update user info
         let app_state = create_app_state().await;
-        let result = super::user_update(web::Json(user), web::Data::new(app_state)).await;
+        let result = super::user_update(
+            authenticate(token).await,
+            web::Json(user),
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(result.is_ok());
     }
 
@@ -225,28 +748,28 @@ mod user_handler_test {
     async fn test_user_delete() {
         // login first
         let app_state = create_app_state().await;
-        let user_info = web::Json(CreateUser {
-            username: "dessera".into(),
+        let user_info = web::Json(LoginUser {
+            identifier: "dessera".into(),
             password: "123456".into(),
-            phone: "".into(),
-            email: "".into(),
+            remember_me: false,
         });
-        let result = super::user_login(user_info, web::Data::new(app_state)).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(result.is_ok());
 
         // get token
-        let token = result.unwrap().into_body().try_into_bytes().unwrap().into();
-        let token = String::from_utf8(token).unwrap();
-        // remove the first and last "
-        let token = token[1..token.len() - 1].to_string();
+        let tokens = result.unwrap().into_body().try_into_bytes().unwrap();
+        let tokens: TokenPair = serde_json::from_slice(&tokens).unwrap();
+        let token = tokens.access_token;
 
         let app_state = create_app_state().await;
-        let certification = web::Json(CertificateUser {
-            username: String::from("dessera"),
-            token,
-        });
+        let auth = authenticate(token).await;
 
-        let result = super::user_delete(certification, web::Data::new(app_state)).await;
+        let result = super::user_delete(auth, web::Data::new(app_state)).await;
         assert!(result.is_ok());
     }
 
@@ -255,28 +778,137 @@ mod user_handler_test {
     async fn test_user_certificate() {
         // login first
         let app_state = create_app_state().await;
-        let user_info = web::Json(CreateUser {
-            username: "dessera".into(),
+        let user_info = web::Json(LoginUser {
+            identifier: "dessera".into(),
             password: "123456".into(),
-            phone: "".into(),
-            email: "".into(),
+            remember_me: false,
         });
-        let result = super::user_login(user_info, web::Data::new(app_state)).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            user_info,
+            web::Data::new(app_state),
+        )
+        .await;
         assert!(result.is_ok());
 
         // get token
-        let token = result.unwrap().into_body().try_into_bytes().unwrap().into();
-        let token = String::from_utf8(token).unwrap();
-        // remove the first and last "
-        let token = token[1..token.len() - 1].to_string();
+        let tokens = result.unwrap().into_body().try_into_bytes().unwrap();
+        let tokens: TokenPair = serde_json::from_slice(&tokens).unwrap();
+        let token = tokens.access_token;
 
         // certificate
-        let app_state = create_app_state().await;
-        let certificate = web::Json(CertificateUser {
-            username: String::from("dessera"),
-            token,
-        });
-        let result = super::user_verify(certificate, web::Data::new(app_state)).await;
+        let auth = authenticate(token).await;
+        let result = super::user_verify(auth).await;
+        assert!(result.is_ok());
+    }
+
+    // the first login with the mock provider creates the account, the next ones reuse it
+    #[tokio::test]
+    async fn test_user_external_login() {
+        let app_state = web::Data::new(create_app_state().await);
+        let mut users = vec![];
+        for _ in 0..2 {
+            let result = super::user_external_login_start(
+                web::Path::from("mock".to_string()),
+                None,
+                app_state.clone(),
+            )
+            .await;
+            let body = result.unwrap().into_body().try_into_bytes().unwrap();
+            let authorization: ExternalAuthorization = serde_json::from_slice(&body).unwrap();
+
+            let result = super::user_external_login(
+                actix_web::test::TestRequest::default().to_http_request(),
+                web::Path::from("mock".to_string()),
+                web::Json(ExternalCallback {
+                    code: "mock-code".to_string(),
+                    state: authorization.state,
+                }),
+                app_state.clone(),
+            )
+            .await;
+            let body = result.unwrap().into_body().try_into_bytes().unwrap();
+            let tokens: TokenPair = serde_json::from_slice(&body).unwrap();
+            users.push(authenticate(tokens.access_token).await.user._id);
+        }
+        assert_eq!(users[0], users[1]);
+    }
+
+    // the password of an impersonated user stays as it is, even without a current password
+    #[tokio::test]
+    async fn test_user_impersonate_password_change() {
+        let app_state = web::Data::new(create_app_state().await);
+
+        // octocat is created by the mock provider and has no password
+        let result = super::user_external_login_start(
+            web::Path::from("mock".to_string()),
+            None,
+            app_state.clone(),
+        )
+        .await;
+        let body = result.unwrap().into_body().try_into_bytes().unwrap();
+        let authorization: ExternalAuthorization = serde_json::from_slice(&body).unwrap();
+        let result = super::user_external_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            web::Path::from("mock".to_string()),
+            web::Json(ExternalCallback {
+                code: "mock-code".to_string(),
+                state: authorization.state,
+            }),
+            app_state.clone(),
+        )
+        .await;
+        assert!(result.is_ok());
+        let stored = || async {
+            serv_user_database(&app_state.database)
+                .find_one(
+                    mongodb::bson::doc! {"username": "octocat", "is_deprecated": false},
+                    None,
+                )
+                .await
+                .unwrap()
+                .unwrap()
+                .password
+        };
+        let password = stored().await;
+
+        // the route guard checks the permission of the admin
+        let admin = create_user(&app_state, Role::Admin).await;
+        let result = super::user_login(
+            actix_web::test::TestRequest::default().to_http_request(),
+            web::Json(LoginUser {
+                identifier: admin,
+                password: TEST_PASSWORD.into(),
+                remember_me: false,
+            }),
+            app_state.clone(),
+        )
+        .await;
+        let body = result.unwrap().into_body().try_into_bytes().unwrap();
+        let tokens: TokenPair = serde_json::from_slice(&body).unwrap();
+        let result = crate::handlers::admin::admin_user_impersonate(
+            authenticate(tokens.access_token).await,
+            web::Path::from("octocat".to_string()),
+            app_state.clone(),
+        )
+        .await;
+        let body = result.unwrap().into_body().try_into_bytes().unwrap();
+        let token: ImpersonationToken = serde_json::from_slice(&body).unwrap();
+
+        let result = super::user_password_change(
+            authenticate(token.access_token.clone()).await,
+            web::Json(ChangePassword {
+                current_password: String::new(),
+                new_password: "a brand new password 42".to_string(),
+            }),
+            app_state.clone(),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(stored().await, password);
+
+        let result =
+            super::user_impersonate_stop(authenticate(token.access_token).await, app_state).await;
         assert!(result.is_ok());
     }
 }
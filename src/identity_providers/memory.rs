@@ -0,0 +1,90 @@
+use std::sync::Mutex;
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+
+use crate::{
+    errors::WebError,
+    identity_providers::{ExternalIdentity, IdentityProvider},
+    utils::oauth::{pkce_verify, url_with_query},
+};
+
+/**
+ * Test provider, hands out the identities registered for its codes
+ */
+pub struct MemoryIdentityProvider {
+    name: String,
+    // (code, identity)
+    identities: Vec<(String, ExternalIdentity)>,
+    // PKCE challenges of the authorization urls built so far
+    challenges: Mutex<Vec<String>>,
+}
+
+impl MemoryIdentityProvider {
+    pub fn new(name: &str) -> Self {
+        MemoryIdentityProvider {
+            name: name.to_string(),
+            identities: vec![],
+            challenges: Mutex::new(vec![]),
+        }
+    }
+
+    /**
+     * Register the identity returned for a code
+     * @param code The authorization code the test passes to the callback
+     * @param identity The identity of the user
+     */
+    pub fn with_identity(mut self, code: &str, identity: ExternalIdentity) -> Self {
+        self.identities.push((code.to_string(), identity));
+        self
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for MemoryIdentityProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn authorize_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> String {
+        self.challenges
+            .lock()
+            .unwrap()
+            .push(code_challenge.to_string());
+        url_with_query(
+            &format!("http://localhost/{}/authorize", self.name),
+            &[
+                ("redirect_uri", redirect_uri),
+                ("state", state),
+                ("code_challenge", code_challenge),
+            ],
+        )
+    }
+
+    async fn identity(
+        &self,
+        _redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<ExternalIdentity, WebError> {
+        let rejected = || {
+            WebError::new(
+                StatusCode::BAD_GATEWAY,
+                "Identity provider rejected the login!".to_string(),
+            )
+        };
+        // the verifier must belong to an authorization url, each one is used once
+        let mut challenges = self.challenges.lock().unwrap();
+        let index = challenges
+            .iter()
+            .position(|challenge| pkce_verify(code_verifier, challenge))
+            .ok_or_else(rejected)?;
+        challenges.remove(index);
+
+        self.identities
+            .iter()
+            .find(|(known, _)| known == code)
+            .map(|(_, identity)| identity.clone())
+            .ok_or_else(rejected)
+    }
+}
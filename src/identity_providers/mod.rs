@@ -0,0 +1,110 @@
+pub mod memory;
+pub mod oauth2;
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+
+use crate::{config::IdentityProvidersConfig, errors::WebError};
+
+/**
+ * A user as known by an external provider
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalIdentity {
+    // stable id of the user at the provider
+    pub subject: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    // whether the provider vouches for the email
+    pub email_verified: bool,
+}
+
+/**
+ * An external OAuth2 / OpenID Connect provider the users can sign in with
+ */
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    /**
+     * Get the name of the provider in the urls, e.g. "github"
+     */
+    fn name(&self) -> &str;
+
+    /**
+     * Build the url of the provider the browser is sent to
+     * @param redirect_uri The callback url of the frontend
+     * @param state The opaque value echoed back to the callback
+     * @param code_challenge The PKCE challenge (S256)
+     */
+    fn authorize_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> String;
+
+    /**
+     * Exchange an authorization code for the identity of the user
+     * @param redirect_uri The callback url used by the authorization request
+     * @param code The authorization code given to the callback
+     * @param code_verifier The PKCE verifier of the challenge
+     */
+    async fn identity(
+        &self,
+        redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<ExternalIdentity, WebError>;
+}
+
+/**
+ * The configured identity providers, by name
+ */
+#[derive(Default)]
+pub struct IdentityProviders {
+    providers: Vec<Box<dyn IdentityProvider>>,
+}
+
+impl IdentityProviders {
+    pub fn new(providers: Vec<Box<dyn IdentityProvider>>) -> Self {
+        IdentityProviders { providers }
+    }
+
+    /**
+     * Create an OAuth2 client for every configured provider
+     * @param config The identity provider configuration
+     */
+    pub fn from_config(config: &IdentityProvidersConfig) -> Self {
+        IdentityProviders::new(
+            config
+                .providers
+                .iter()
+                .map(|provider| {
+                    Box::new(oauth2::OAuth2Provider::new(provider.clone()))
+                        as Box<dyn IdentityProvider>
+                })
+                .collect(),
+        )
+    }
+
+    /**
+     * Get the names of the providers
+     */
+    pub fn names(&self) -> Vec<String> {
+        self.providers
+            .iter()
+            .map(|provider| provider.name().to_string())
+            .collect()
+    }
+
+    /**
+     * Find a provider by name
+     * @param name The name of the provider
+     */
+    pub fn get(&self, name: &str) -> Result<&dyn IdentityProvider, WebError> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name() == name)
+            .map(|provider| provider.as_ref())
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::NOT_FOUND,
+                    "Identity provider not found!".to_string(),
+                )
+            })
+    }
+}
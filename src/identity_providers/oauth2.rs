@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{
+    config::IdentityProviderConfig,
+    errors::WebError,
+    identity_providers::{ExternalIdentity, IdentityProvider},
+    utils::oauth::url_with_query,
+};
+
+/**
+ * Token response of the provider, only the access token is used
+ */
+#[derive(Debug, Deserialize)]
+struct ProviderToken {
+    access_token: String,
+}
+
+/**
+ * A provider speaking the authorization-code flow with PKCE,
+ * the identity is read from its userinfo endpoint
+ */
+pub struct OAuth2Provider {
+    config: IdentityProviderConfig,
+    client: reqwest::Client,
+}
+
+fn provider_error(message: &str) -> WebError {
+    WebError::new(StatusCode::BAD_GATEWAY, message.to_string())
+}
+
+/**
+ * Read a field of the userinfo response as a string, providers like GitHub use numeric ids
+ * @param userinfo The userinfo response
+ * @param field The name of the field
+ */
+fn userinfo_string(userinfo: &serde_json::Value, field: &str) -> Option<String> {
+    match userinfo.get(field)? {
+        serde_json::Value::String(value) if !value.is_empty() => Some(value.clone()),
+        serde_json::Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+impl OAuth2Provider {
+    pub fn new(config: IdentityProviderConfig) -> Self {
+        OAuth2Provider {
+            config,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .user_agent("mlum")
+                .build()
+                .expect("Failed to create the HTTP client"),
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for OAuth2Provider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn authorize_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> String {
+        url_with_query(
+            &self.config.authorize_url,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", redirect_uri),
+                ("scope", &self.config.scopes),
+                ("state", state),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+    }
+
+    async fn identity(
+        &self,
+        redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<ExternalIdentity, WebError> {
+        // GitHub answers with a form unless json is asked for
+        let token: ProviderToken = self
+            .client
+            .post(&self.config.token_url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("client_id", &self.config.client_id),
+                ("client_secret", &self.config.client_secret),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|_| provider_error("Identity provider rejected the login!"))?
+            .json()
+            .await
+            .map_err(|_| provider_error("Identity provider rejected the login!"))?;
+
+        let userinfo: serde_json::Value = self
+            .client
+            .get(&self.config.userinfo_url)
+            .header("Accept", "application/json")
+            .bearer_auth(token.access_token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|_| provider_error("Failed to read the identity from the provider!"))?
+            .json()
+            .await
+            .map_err(|_| provider_error("Failed to read the identity from the provider!"))?;
+
+        Ok(ExternalIdentity {
+            subject: userinfo_string(&userinfo, &self.config.subject_field)
+                .ok_or_else(|| provider_error("Identity provider returned no subject!"))?,
+            username: userinfo_string(&userinfo, &self.config.username_field),
+            email: userinfo_string(&userinfo, &self.config.email_field),
+            email_verified: userinfo
+                .get(&self.config.email_verified_field)
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+#[cfg(test)]
+mod oauth2_test {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, ResponseError};
+
+    use super::*;
+
+    // a local provider which accepts the code "good-code" with the verifier "good-verifier"
+    async fn mock_token(form: web::Form<Vec<(String, String)>>) -> HttpResponse {
+        let field = |key: &str| {
+            form.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        if field("code") == Some("good-code") && field("code_verifier") == Some("good-verifier") {
+            HttpResponse::Ok().json(serde_json::json!({"access_token": "mock-token"}))
+        } else {
+            HttpResponse::BadRequest().json(serde_json::json!({"error": "invalid_grant"}))
+        }
+    }
+
+    async fn mock_userinfo(req: HttpRequest) -> HttpResponse {
+        match req.headers().get("Authorization") {
+            Some(value) if value == "Bearer mock-token" => HttpResponse::Ok()
+                .json(serde_json::json!({"id": 42, "login": "octocat", "email": "octo@mlum.com"})),
+            _ => HttpResponse::Unauthorized().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_oauth2_provider_identity() {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/token", web::post().to(mock_token))
+                .route("/userinfo", web::get().to(mock_userinfo))
+        })
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let handle = server.run();
+        let server_handle = handle.handle();
+        actix_web::rt::spawn(handle);
+
+        let provider = OAuth2Provider::new(IdentityProviderConfig {
+            name: "mock".to_string(),
+            client_id: "mlum".to_string(),
+            authorize_url: format!("http://{}/authorize", address),
+            token_url: format!("http://{}/token", address),
+            userinfo_url: format!("http://{}/userinfo", address),
+            subject_field: "id".to_string(),
+            username_field: "login".to_string(),
+            email_field: "email".to_string(),
+            email_verified_field: "email_verified".to_string(),
+            ..Default::default()
+        });
+
+        let authorize_url = provider.authorize_url("http://localhost:3000/cb", "st", "ch");
+        assert!(authorize_url.starts_with(&format!("http://{}/authorize?", address)));
+        assert!(authorize_url.contains("&state=st&"));
+        assert!(authorize_url.ends_with("=S256"));
+        assert_eq!(
+            provider
+                .identity("http://localhost:3000/cb", "good-code", "good-verifier")
+                .await
+                .unwrap(),
+            ExternalIdentity {
+                subject: "42".to_string(),
+                username: Some("octocat".to_string()),
+                email: Some("octo@mlum.com".to_string()),
+                email_verified: false,
+            }
+        );
+        let rejected = provider
+            .identity("http://localhost:3000/cb", "good-code", "bad-verifier")
+            .await
+            .unwrap_err();
+        assert_eq!(rejected.status_code(), StatusCode::BAD_GATEWAY);
+
+        server_handle.stop(true).await;
+    }
+}
@@ -1,7 +1,14 @@
 pub mod app_state;
+pub mod config;
+pub mod extractors;
+pub mod guards;
 pub mod routers;
 pub mod handlers;
+pub mod identity_providers;
+pub mod mailers;
 pub mod models;
 pub mod errors;
 pub mod services;
+pub mod sms;
+pub mod stores;
 pub mod utils;
@@ -0,0 +1,79 @@
+use std::{io::Write, path::PathBuf};
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::{
+    errors::WebError,
+    mailers::{Mail, Mailer},
+};
+
+/**
+ * Development mailer, appends the mails to a file or prints them to stdout
+ */
+pub struct FileMailer {
+    // stdout when empty
+    path: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        FileMailer { path }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), WebError> {
+        let text = format!(
+            "Date: {}\nTo: {}\nSubject: {}\n\n{}\n\n",
+            Utc::now().to_rfc2822(),
+            mail.to,
+            mail.subject,
+            mail.body
+        );
+
+        match &self.path {
+            Some(path) => std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(text.as_bytes()))
+                .map_err(|err| {
+                    WebError::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Mail file error: {}", err),
+                    )
+                }),
+            None => {
+                print!("{}", text);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod file_mailer_test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_mailer() {
+        let path = std::env::temp_dir().join(format!("mlum_mail_{}.txt", nanoid::nanoid!(8)));
+        let mailer = FileMailer::new(Some(path.clone()));
+        mailer
+            .send(Mail {
+                to: "dessera@mlum.com".to_string(),
+                subject: "Hello".to_string(),
+                body: "reset token".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.contains("To: dessera@mlum.com"));
+        assert!(text.contains("reset token"));
+    }
+}
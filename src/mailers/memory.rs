@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::{
+    errors::WebError,
+    mailers::{Mail, Mailer},
+};
+
+/**
+ * Test mailer, keeps every sent mail in memory
+ */
+#[derive(Default)]
+pub struct MemoryMailer {
+    mails: Mutex<Vec<Mail>>,
+}
+
+impl MemoryMailer {
+    /**
+     * Get the mails sent so far
+     */
+    pub async fn mails(&self) -> Vec<Mail> {
+        self.mails.lock().await.clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, mail: Mail) -> Result<(), WebError> {
+        self.mails.lock().await.push(mail);
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+pub mod file;
+pub mod memory;
+pub mod smtp;
+
+use async_trait::async_trait;
+
+use crate::errors::WebError;
+
+/**
+ * A plain text mail
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/**
+ * Sends the mails of the service (password reset, verification...)
+ */
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), WebError>;
+}
@@ -0,0 +1,62 @@
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::{
+    config::SmtpConfig,
+    errors::WebError,
+    mailers::{Mail, Mailer},
+};
+
+fn smtp_error<E: std::fmt::Display>(err: E) -> WebError {
+    WebError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("SMTP error: {}", err),
+    )
+}
+
+/**
+ * Sends the mails through an SMTP relay
+ */
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /**
+     * Connect to the SMTP relay
+     * @param config The SMTP configuration
+     */
+    pub fn new(config: &SmtpConfig) -> Result<Self, WebError> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+            .map_err(smtp_error)?
+            .port(config.port)
+            .credentials(Credentials::new(
+                config.username.clone(),
+                config.password.clone(),
+            ))
+            .build();
+        Ok(SmtpMailer {
+            transport,
+            from: config.from.parse().map_err(smtp_error)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> Result<(), WebError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse().map_err(smtp_error)?)
+            .subject(mail.subject)
+            .body(mail.body)
+            .map_err(smtp_error)?;
+        self.transport.send(message).await.map_err(smtp_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use bson::Bson;
+use serde::{Deserialize, Serialize};
+
+use crate::models::roles::Permission;
+
+/**
+ * Scope of a personal access token, a token never grants more than the role of its owner
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum AccessTokenScope {
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+    #[serde(rename = "profile:write")]
+    ProfileWrite,
+    #[serde(rename = "discussions:write")]
+    DiscussionsWrite,
+}
+
+impl AccessTokenScope {
+    /**
+     * Get the permission granted by the scope
+     */
+    pub fn permission(&self) -> Permission {
+        match self {
+            AccessTokenScope::ProfileRead => Permission::ProfileRead,
+            AccessTokenScope::ProfileWrite => Permission::ProfileWrite,
+            AccessTokenScope::DiscussionsWrite => Permission::DiscussionsWrite,
+        }
+    }
+}
+
+/**
+ * A long-lived token of a user for scripts and bots, only its digest is stored
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PersonalAccessToken {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    pub name: String,
+    pub token_digest: String,
+    // first characters of the token, to recognize it in the list
+    pub token_prefix: String,
+    pub scopes: Vec<AccessTokenScope>,
+    pub create_time: i64,
+    // none if the token never expires
+    pub expire_time: Option<i64>,
+    #[serde(default)]
+    pub last_used_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CreateAccessToken {
+    pub name: String,
+    pub scopes: Vec<AccessTokenScope>,
+    // lifetime in seconds, none for a token which never expires
+    pub expires_in: Option<i64>,
+}
+
+/**
+ * A personal access token as listed to its owner, without the digest
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccessTokenInfo {
+    pub id: String,
+    pub name: String,
+    pub token_prefix: String,
+    pub scopes: Vec<AccessTokenScope>,
+    pub create_time: i64,
+    pub expire_time: Option<i64>,
+    pub last_used_time: Option<i64>,
+}
+
+/**
+ * Returned once when a token is created, the token can not be read again
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CreatedAccessToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: AccessTokenInfo,
+}
+
+impl std::convert::From<PersonalAccessToken> for AccessTokenInfo {
+    fn from(value: PersonalAccessToken) -> Self {
+        AccessTokenInfo {
+            id: value._id.unwrap_or_default().to_hex(),
+            name: value.name,
+            token_prefix: value.token_prefix,
+            scopes: value.scopes,
+            create_time: value.create_time,
+            expire_time: value.expire_time,
+            last_used_time: value.last_used_time,
+        }
+    }
+}
+
+impl std::fmt::Display for AccessTokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessTokenScope::ProfileRead => write!(f, "profile:read"),
+            AccessTokenScope::ProfileWrite => write!(f, "profile:write"),
+            AccessTokenScope::DiscussionsWrite => write!(f, "discussions:write"),
+        }
+    }
+}
+
+impl std::convert::From<AccessTokenScope> for Bson {
+    fn from(value: AccessTokenScope) -> Self {
+        value.to_string().into()
+    }
+}
+
+#[cfg(test)]
+mod access_token_scope_test {
+    use super::*;
+
+    #[test]
+    fn test_access_token_scope() {
+        let scope: AccessTokenScope = serde_json::from_str("\"profile:write\"").unwrap();
+        assert_eq!(scope, AccessTokenScope::ProfileWrite);
+        assert_eq!(scope.permission(), Permission::ProfileWrite);
+        assert_eq!(
+            Bson::from(AccessTokenScope::DiscussionsWrite),
+            Bson::String("discussions:write".to_string())
+        );
+        assert!(serde_json::from_str::<AccessTokenScope>("\"users:manage\"").is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use bson::Bson;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    sessions::{AuthenticatedUser, ClientInfo},
+    users::User,
+};
+
+/**
+ * What was done to an account
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Register,
+    Login,
+    LoginTwoFactor,
+    LoginCodeSend,
+    LoginCode,
+    TokenRefresh,
+    Logout,
+    ProfileUpdate,
+    Delete,
+    PasswordForgot,
+    PasswordReset,
+    PasswordChange,
+    EmailVerify,
+    EmailResend,
+    PhoneSend,
+    PhoneVerify,
+    TwoFactorSetup,
+    TwoFactorEnable,
+    TwoFactorDisable,
+    TwoFactorRecovery,
+    AccessTokenCreate,
+    AccessTokenRevoke,
+    SessionRevoke,
+    SessionRevokeOthers,
+    ExternalLogin,
+    IdentityLink,
+    IdentityUnlink,
+    // done by an admin, or by the service itself
+    RoleUpdate,
+    RoleBootstrap,
+    UserList,
+    UserView,
+    ForceLogout,
+    Suspend,
+    Unsuspend,
+    Restore,
+    Purge,
+    Anonymize,
+    ImpersonationStart,
+    ImpersonationStop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/**
+ * An entry of the audit log, entries are only appended and dropped after the retention
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditEvent {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub action: AuditAction,
+    pub outcome: AuditOutcome,
+    // who did it, none for an anonymous client or the service itself
+    pub actor: Option<String>,
+    pub actor_id: Option<bson::oid::ObjectId>,
+    // the account concerned, a typed identifier when no account matched
+    pub target: Option<String>,
+    pub target_id: Option<bson::oid::ObjectId>,
+    // the admin behind the actor, set for everything done while impersonating
+    #[serde(default)]
+    pub impersonator: Option<String>,
+    #[serde(default)]
+    pub impersonator_id: Option<bson::oid::ObjectId>,
+    // the error message of a failure
+    pub detail: Option<String>,
+
+    // client info
+    pub user_agent: String,
+    pub ip: String,
+
+    pub time: i64,
+}
+
+impl AuditEvent {
+    /**
+     * Start an event of an anonymous client, e.g. a login
+     * @param action The action
+     * @param client The client which does it
+     */
+    pub fn new(action: AuditAction, client: &ClientInfo) -> Self {
+        AuditEvent {
+            _id: Some(bson::oid::ObjectId::new()),
+            action,
+            outcome: AuditOutcome::Success,
+            actor: None,
+            actor_id: None,
+            target: None,
+            target_id: None,
+            impersonator: None,
+            impersonator_id: None,
+            detail: None,
+            user_agent: client.user_agent.clone(),
+            ip: client.ip.clone(),
+            time: Utc::now().timestamp(),
+        }
+    }
+
+    /**
+     * Start an event of a user about the own account
+     * @param action The action
+     * @param auth The authenticated user
+     *
+     * @note The admin impersonating the user is recorded along
+     */
+    pub fn by(action: AuditAction, auth: &AuthenticatedUser) -> Self {
+        let mut event = AuditEvent::new(action, &auth.client);
+        event.set_actor(&auth.user);
+        event.set_target(&auth.user);
+        if let Some(session) = auth.impersonation() {
+            event.impersonator = session.impersonator.clone();
+            event.impersonator_id = session.impersonator_id;
+        }
+        event
+    }
+
+    pub fn set_actor(&mut self, user: &User) {
+        self.actor = Some(user.username.clone());
+        self.actor_id = user._id;
+    }
+
+    pub fn set_target(&mut self, user: &User) {
+        self.target = Some(user.username.clone());
+        self.target_id = user._id;
+    }
+
+    pub fn set_target_name(&mut self, name: &str) {
+        self.target = Some(name.to_string());
+        self.target_id = None;
+    }
+}
+
+/**
+ * Filter of the audit log, every field is optional
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub impersonator: Option<String>,
+    pub action: Option<AuditAction>,
+    pub outcome: Option<AuditOutcome>,
+    pub ip: Option<String>,
+    // time range, in timestamps
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+    // pages start at 1
+    pub page: Option<u64>,
+    pub page_size: Option<u64>,
+}
+
+/**
+ * A page of the audit log
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    pub total: u64,
+    pub page: u64,
+    pub page_size: u64,
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditAction::Register => write!(f, "register"),
+            AuditAction::Login => write!(f, "login"),
+            AuditAction::LoginTwoFactor => write!(f, "login_two_factor"),
+            AuditAction::LoginCodeSend => write!(f, "login_code_send"),
+            AuditAction::LoginCode => write!(f, "login_code"),
+            AuditAction::TokenRefresh => write!(f, "token_refresh"),
+            AuditAction::Logout => write!(f, "logout"),
+            AuditAction::ProfileUpdate => write!(f, "profile_update"),
+            AuditAction::Delete => write!(f, "delete"),
+            AuditAction::PasswordForgot => write!(f, "password_forgot"),
+            AuditAction::PasswordReset => write!(f, "password_reset"),
+            AuditAction::PasswordChange => write!(f, "password_change"),
+            AuditAction::EmailVerify => write!(f, "email_verify"),
+            AuditAction::EmailResend => write!(f, "email_resend"),
+            AuditAction::PhoneSend => write!(f, "phone_send"),
+            AuditAction::PhoneVerify => write!(f, "phone_verify"),
+            AuditAction::TwoFactorSetup => write!(f, "two_factor_setup"),
+            AuditAction::TwoFactorEnable => write!(f, "two_factor_enable"),
+            AuditAction::TwoFactorDisable => write!(f, "two_factor_disable"),
+            AuditAction::TwoFactorRecovery => write!(f, "two_factor_recovery"),
+            AuditAction::AccessTokenCreate => write!(f, "access_token_create"),
+            AuditAction::AccessTokenRevoke => write!(f, "access_token_revoke"),
+            AuditAction::SessionRevoke => write!(f, "session_revoke"),
+            AuditAction::SessionRevokeOthers => write!(f, "session_revoke_others"),
+            AuditAction::ExternalLogin => write!(f, "external_login"),
+            AuditAction::IdentityLink => write!(f, "identity_link"),
+            AuditAction::IdentityUnlink => write!(f, "identity_unlink"),
+            AuditAction::RoleUpdate => write!(f, "role_update"),
+            AuditAction::RoleBootstrap => write!(f, "role_bootstrap"),
+            AuditAction::UserList => write!(f, "user_list"),
+            AuditAction::UserView => write!(f, "user_view"),
+            AuditAction::ForceLogout => write!(f, "force_logout"),
+            AuditAction::Suspend => write!(f, "suspend"),
+            AuditAction::Unsuspend => write!(f, "unsuspend"),
+            AuditAction::Restore => write!(f, "restore"),
+            AuditAction::Purge => write!(f, "purge"),
+            AuditAction::Anonymize => write!(f, "anonymize"),
+            AuditAction::ImpersonationStart => write!(f, "impersonation_start"),
+            AuditAction::ImpersonationStop => write!(f, "impersonation_stop"),
+        }
+    }
+}
+
+impl std::fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditOutcome::Success => write!(f, "success"),
+            AuditOutcome::Failure => write!(f, "failure"),
+        }
+    }
+}
+
+impl std::convert::From<AuditAction> for Bson {
+    fn from(value: AuditAction) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl std::convert::From<AuditOutcome> for Bson {
+    fn from(value: AuditOutcome) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl std::convert::From<&AuditFilter> for bson::Document {
+    fn from(value: &AuditFilter) -> Self {
+        let mut doc = bson::Document::new();
+        if let Some(actor) = &value.actor {
+            doc.insert("actor", actor);
+        }
+        if let Some(target) = &value.target {
+            doc.insert("target", target);
+        }
+        if let Some(impersonator) = &value.impersonator {
+            doc.insert("impersonator", impersonator);
+        }
+        if let Some(action) = value.action {
+            doc.insert("action", action);
+        }
+        if let Some(outcome) = value.outcome {
+            doc.insert("outcome", outcome);
+        }
+        if let Some(ip) = &value.ip {
+            doc.insert("ip", ip);
+        }
+        let mut time = bson::Document::new();
+        if let Some(after) = value.after {
+            time.insert("$gte", after);
+        }
+        if let Some(before) = value.before {
+            time.insert("$lt", before);
+        }
+        if !time.is_empty() {
+            doc.insert("time", time);
+        }
+        doc
+    }
+}
+
+#[cfg(test)]
+mod audit_filter_test {
+    use super::*;
+
+    #[test]
+    fn test_audit_filter_document() {
+        let filter = AuditFilter {
+            target: Some("alice".to_string()),
+            action: Some(AuditAction::LoginTwoFactor),
+            outcome: Some(AuditOutcome::Failure),
+            after: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            bson::Document::from(&filter),
+            bson::doc! {
+                "target": "alice",
+                "action": "login_two_factor",
+                "outcome": "failure",
+                "time": {"$gte": 100_i64},
+            }
+        );
+    }
+}
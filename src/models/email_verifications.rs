@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * A code proving that the user owns an email
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmailVerification {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    // the email the code was sent to
+    pub email: String,
+    // keyed digest of the code sent in the link
+    pub code_digest: String,
+    pub create_time: i64,
+    pub expire_time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VerifyEmail {
+    pub code: String,
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::token::TokenActor;
+
+/**
+ * An internal service authenticated by its client credentials
+ */
+#[derive(Debug, Clone)]
+pub struct ServiceClient {
+    pub client_id: String,
+}
+
+/**
+ * Introspection request (RFC 7662), sent as a form
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IntrospectToken {
+    pub token: String,
+    // access_token or refresh_token, only a hint
+    pub token_type_hint: Option<String>,
+}
+
+/**
+ * Introspection response (RFC 7662), only `active` is set for an inactive token
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Introspection {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
+    // space separated permissions granted to the token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    // OAuth client of the single sign-on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    // the admin impersonating the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<TokenActor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * An account of an external identity provider linked to a user
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LinkedIdentity {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    // name of the provider, e.g. "github"
+    pub provider: String,
+    // stable id of the user at the provider
+    pub subject: String,
+    // as last reported by the provider, only shown to the user
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub create_time: i64,
+    #[serde(default)]
+    pub last_login_time: Option<i64>,
+}
+
+/**
+ * A sign-in sent to a provider and waiting for its callback
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExternalLoginState {
+    pub _id: Option<bson::oid::ObjectId>,
+    // keyed digest of the state echoed by the provider
+    pub state_digest: String,
+    pub provider: String,
+    pub code_verifier: String,
+    pub redirect_uri: String,
+    // the user linking the provider, none for a login
+    pub user_id: Option<bson::oid::ObjectId>,
+    #[serde(default)]
+    pub remember_me: bool,
+    pub expire_time: i64,
+    // the same moment as a date, the database drops the state once it is reached
+    #[serde(default)]
+    pub expire_date: Option<bson::DateTime>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ExternalLoginStart {
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+/**
+ * Returned when a sign-in starts, the browser is sent to the url
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExternalAuthorization {
+    pub authorize_url: String,
+    pub state: String,
+    // lifetime of the state in seconds
+    pub expires_in: i64,
+}
+
+/**
+ * Forwarded by the callback page of the frontend
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExternalCallback {
+    pub code: String,
+    pub state: String,
+}
+
+/**
+ * A linked identity as listed to its owner
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LinkedIdentityInfo {
+    pub provider: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub create_time: i64,
+    pub last_login_time: Option<i64>,
+}
+
+impl From<LinkedIdentity> for LinkedIdentityInfo {
+    fn from(value: LinkedIdentity) -> Self {
+        LinkedIdentityInfo {
+            provider: value.provider,
+            username: value.username,
+            email: value.email,
+            create_time: value.create_time,
+            last_login_time: value.last_login_time,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * Failed logins of a username or of a client ip
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LoginAttempt {
+    // "user:<username>" or "ip:<address>"
+    pub _id: String,
+    pub failures: u32,
+    pub last_failure: i64,
+    pub locked_until: i64,
+}
+
+impl LoginAttempt {
+    /**
+     * Count a failure, starting over once the window or the previous lockout is over
+     * @param now The current timestamp
+     * @param window_start Failures before this timestamp are forgotten
+     */
+    pub fn count_failure(&mut self, now: i64, window_start: i64) {
+        if self.last_failure < window_start || (self.locked_until != 0 && self.locked_until <= now)
+        {
+            self.failures = 0;
+            self.locked_until = 0;
+        }
+        self.failures += 1;
+        self.last_failure = now;
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * A login waiting for the second factor, the password has already been checked
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoginChallenge {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    // keyed digest of the challenge token
+    pub token_digest: String,
+    // wrong codes typed for this challenge
+    pub attempts: u32,
+    // passed on to the session opened by the second factor
+    #[serde(default)]
+    pub remember_me: bool,
+    pub create_time: i64,
+    pub expire_time: i64,
+}
+
+/**
+ * Returned instead of the tokens when the user has enabled 2FA
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+    // lifetime of the challenge in seconds
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoginTwoFactor {
+    pub challenge_token: String,
+    // a TOTP code or a recovery code
+    pub code: String,
+}
@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::roles::Permission;
+
+// scopes a client can request, "openid" turns the request into an OpenID Connect one
+pub const OAUTH_SCOPES: &[&str] = &["openid", "profile", "email", "phone"];
+
+/**
+ * A front-end or a tool which signs its users in with mlum
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuthClient {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub client_id: String,
+    // none for a public client (single page or native app), which only relies on PKCE
+    pub client_secret_digest: Option<String>,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub create_time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegisterClient {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    // a public client gets no secret
+    #[serde(default)]
+    pub public: bool,
+}
+
+/**
+ * A client as listed to the admins, without the secret
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuthClientInfo {
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub public: bool,
+    pub create_time: i64,
+}
+
+/**
+ * Returned once when a client is registered, the secret can not be read again
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegisteredClient {
+    #[serde(flatten)]
+    pub info: OAuthClientInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+/**
+ * The scopes a user granted to a client, asked again only for new scopes
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuthConsent {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub create_time: i64,
+}
+
+/**
+ * A one-time authorization code, only its digest is stored
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthorizationCode {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub code_digest: String,
+    pub client_id: String,
+    pub user_id: bson::oid::ObjectId,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    // PKCE challenge (S256)
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+    // when the user logged in
+    pub auth_time: i64,
+    pub expire_time: i64,
+}
+
+/**
+ * The client and the scopes behind a session opened by the single sign-on
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OAuthGrant {
+    pub client_id: String,
+    pub scopes: Vec<String>,
+}
+
+impl OAuthGrant {
+    /**
+     * Check whether a scope has been granted
+     * @param scope The scope
+     */
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /**
+     * Check whether the grant allows a permission, clients can only read the profile
+     * @param permission The permission required
+     */
+    pub fn has(&self, permission: Permission) -> bool {
+        permission == Permission::ProfileRead && self.has_scope("profile")
+    }
+}
+
+/**
+ * Authorization request of the authorization-code flow, sent as query
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthorizeRequest {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scope: String,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+}
+
+impl AuthorizeRequest {
+    /**
+     * Get the parameters of the request, to pass it on to the consent page
+     */
+    pub fn query_pairs(&self) -> Vec<(&str, &str)> {
+        let mut pairs = vec![
+            ("response_type", self.response_type.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("scope", self.scope.as_str()),
+        ];
+        let optional = [
+            ("state", &self.state),
+            ("code_challenge", &self.code_challenge),
+            ("code_challenge_method", &self.code_challenge_method),
+            ("nonce", &self.nonce),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                pairs.push((key, value.as_str()));
+            }
+        }
+        pairs
+    }
+}
+
+/**
+ * Answer of the user on the consent page
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthorizeDecision {
+    #[serde(flatten)]
+    pub request: AuthorizeRequest,
+    pub approve: bool,
+}
+
+/**
+ * Where the consent page sends the browser next
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthorizeRedirect {
+    pub redirect_to: String,
+}
+
+/**
+ * Token request (RFC 6749), sent as a form
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuthTokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    // client credentials, when they are not sent with basic authentication
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuthTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+/**
+ * Standard claims of a user, limited by the granted scopes
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UserInfo {
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone_number_verified: Option<bool>,
+}
+
+/**
+ * Claims of an OpenID Connect id token
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    // client id
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub user: UserInfo,
+}
+
+/**
+ * OpenID Connect discovery document
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+/**
+ * Public signing key (RFC 8037 for Ed25519)
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    pub kid: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl std::convert::From<OAuthClient> for OAuthClientInfo {
+    fn from(value: OAuthClient) -> Self {
+        OAuthClientInfo {
+            client_id: value.client_id,
+            name: value.name,
+            redirect_uris: value.redirect_uris,
+            public: value.client_secret_digest.is_none(),
+            create_time: value.create_time,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * A single use token which allows to set a new password
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PasswordReset {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    // keyed digest of the token sent to the user
+    pub token_digest: String,
+    pub create_time: i64,
+    pub expire_time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
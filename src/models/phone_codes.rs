@@ -0,0 +1,59 @@
+use bson::Bson;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PhoneCodePurpose {
+    Verify,
+    Login,
+}
+
+impl std::fmt::Display for PhoneCodePurpose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PhoneCodePurpose::Verify => write!(f, "Verify"),
+            PhoneCodePurpose::Login => write!(f, "Login"),
+        }
+    }
+}
+
+impl std::convert::From<PhoneCodePurpose> for Bson {
+    fn from(value: PhoneCodePurpose) -> Self {
+        value.to_string().into()
+    }
+}
+
+/**
+ * A one-time code sent to a phone number
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PhoneCode {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub phone: String,
+    pub purpose: PhoneCodePurpose,
+    // keyed digest of the code sent to the phone
+    pub code_digest: String,
+    // codes entered so far, counted before they are compared
+    pub attempts: u32,
+    pub used: bool,
+    pub create_time: i64,
+    pub expire_time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VerifyPhone {
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SendLoginCode {
+    pub phone: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoginWithCode {
+    pub phone: String,
+    pub code: String,
+    // keep the session open longer
+    #[serde(default)]
+    pub remember_me: bool,
+}
@@ -0,0 +1,140 @@
+use bson::Bson;
+use serde::{Deserialize, Serialize};
+
+/**
+ * Role of a user, every role holds a fixed set of permissions
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+/**
+ * Permission declared by a protected route
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // own profile
+    ProfileRead,
+    ProfileWrite,
+    // discussions
+    DiscussionsWrite,
+    DiscussionsModerate,
+    // other accounts
+    UsersRead,
+    UsersManage,
+    RolesManage,
+    // act as another user, for the support staff
+    UsersImpersonate,
+    // OAuth clients of the single sign-on
+    ClientsManage,
+    // audit log
+    AuditRead,
+}
+
+const USER_PERMISSIONS: &[Permission] = &[
+    Permission::ProfileRead,
+    Permission::ProfileWrite,
+    Permission::DiscussionsWrite,
+];
+
+const MODERATOR_PERMISSIONS: &[Permission] = &[
+    Permission::ProfileRead,
+    Permission::ProfileWrite,
+    Permission::DiscussionsWrite,
+    Permission::DiscussionsModerate,
+];
+
+const ADMIN_PERMISSIONS: &[Permission] = &[
+    Permission::ProfileRead,
+    Permission::ProfileWrite,
+    Permission::DiscussionsWrite,
+    Permission::DiscussionsModerate,
+    Permission::UsersRead,
+    Permission::UsersManage,
+    Permission::RolesManage,
+    Permission::UsersImpersonate,
+    Permission::ClientsManage,
+    Permission::AuditRead,
+];
+
+impl Role {
+    /**
+     * Get the permissions granted to the role
+     */
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::User => USER_PERMISSIONS,
+            Role::Moderator => MODERATOR_PERMISSIONS,
+            Role::Admin => ADMIN_PERMISSIONS,
+        }
+    }
+
+    /**
+     * Check whether the role grants a permission
+     * @param permission The permission required
+     */
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UpdateRole {
+    pub role: Role,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::User => write!(f, "user"),
+            Role::Moderator => write!(f, "moderator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::ProfileRead => write!(f, "profile_read"),
+            Permission::ProfileWrite => write!(f, "profile_write"),
+            Permission::DiscussionsWrite => write!(f, "discussions_write"),
+            Permission::DiscussionsModerate => write!(f, "discussions_moderate"),
+            Permission::UsersRead => write!(f, "users_read"),
+            Permission::UsersManage => write!(f, "users_manage"),
+            Permission::RolesManage => write!(f, "roles_manage"),
+            Permission::UsersImpersonate => write!(f, "users_impersonate"),
+            Permission::ClientsManage => write!(f, "clients_manage"),
+            Permission::AuditRead => write!(f, "audit_read"),
+        }
+    }
+}
+
+impl std::convert::From<Role> for Bson {
+    fn from(value: Role) -> Self {
+        value.to_string().into()
+    }
+}
+
+#[cfg(test)]
+mod role_test {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(Role::User.has(Permission::ProfileWrite));
+        assert!(!Role::User.has(Permission::UsersRead));
+        assert!(!Role::Moderator.has(Permission::UsersRead));
+        assert!(!Role::Moderator.has(Permission::UsersManage));
+        assert!(Role::Admin.has(Permission::RolesManage));
+        assert!(Role::Admin.has(Permission::UsersImpersonate));
+        assert!(!Role::Moderator.has(Permission::UsersImpersonate));
+        assert_eq!(Role::default().to_string(), "user");
+    }
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SecurityEventKind {
+    // an already rotated refresh token was presented again
+    RefreshTokenReuse,
+}
+
+/**
+ * A suspicious event about the account of a user
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SecurityEvent {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    pub username: String,
+    pub kind: SecurityEventKind,
+    pub detail: String,
+
+    // client info
+    pub user_agent: String,
+    pub ip: String,
+
+    pub time: i64,
+}
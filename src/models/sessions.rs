@@ -0,0 +1,258 @@
+use actix_web::{
+    http::{header::USER_AGENT, StatusCode},
+    web, HttpRequest,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    errors::WebError,
+    models::{
+        access_tokens::PersonalAccessToken, login_challenges::TwoFactorChallenge,
+        oauth::OAuthGrant, roles::Permission, users::User,
+    },
+    utils::{token::AccessClaims, user_agent::user_agent_describe},
+};
+
+/**
+ * A login session of a user, one user can hold several sessions at the same time
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Session {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    pub username: String,
+    // keyed digest of the opaque refresh token, access tokens are signed and never stored
+    pub refresh_token_digest: String,
+    // digests of the refresh tokens already rotated out of this session (the token family of the login)
+    #[serde(default)]
+    pub used_refresh_token_digests: Vec<String>,
+
+    // client info
+    pub user_agent: String,
+    pub ip: String,
+    // set when the session was opened by the single sign-on of another client
+    #[serde(default)]
+    pub grant: Option<OAuthGrant>,
+
+    // session time, the expiry slides forward on use up to the absolute expiry
+    pub create_time: i64,
+    pub expire_time: i64,
+    // none for the sessions opened before the expiry could slide, they end at expire_time
+    #[serde(default)]
+    pub absolute_expire_time: Option<i64>,
+    // the login asked for the longer lifetimes
+    #[serde(default)]
+    pub remember_me: bool,
+    // recorded at most once a minute, none until the first use after the login
+    #[serde(default)]
+    pub last_used_time: Option<i64>,
+    // the admin impersonating the user, such a session can not be refreshed
+    #[serde(default)]
+    pub impersonator: Option<String>,
+    #[serde(default)]
+    pub impersonator_id: Option<bson::oid::ObjectId>,
+}
+
+/**
+ * A session as listed to its user, without the refresh tokens
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    // short description of the user agent, e.g. "Firefox on Linux"
+    pub device: String,
+    pub user_agent: String,
+    pub ip: String,
+    // the OAuth client of a single sign-on session
+    pub client_id: Option<String>,
+    pub create_time: i64,
+    pub last_used_time: i64,
+    pub expire_time: i64,
+    pub remember_me: bool,
+    // the admin impersonating the user
+    pub impersonator: Option<String>,
+    // the session of the request
+    pub current: bool,
+}
+
+/**
+ * What a request was authenticated with
+ */
+#[derive(Debug, Clone)]
+pub enum Credential {
+    // an access token issued by a login
+    Session {
+        session: Box<Session>,
+        claims: AccessClaims,
+    },
+    PersonalAccessToken(PersonalAccessToken),
+}
+
+/**
+ * The user and the credential behind a verified token
+ */
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user: User,
+    pub credential: Credential,
+    // the client of the request, for the audit log
+    pub client: ClientInfo,
+}
+
+impl AuthenticatedUser {
+    /**
+     * Get the login session of the request
+     *
+     * @note Sensitive operations call it, so they can not be done with a personal access token
+     *       or with the token of an OAuth client
+     */
+    pub fn session(&self) -> Result<&Session, WebError> {
+        match &self.credential {
+            Credential::Session { session, .. } if session.grant.is_none() => Ok(session),
+            _ => Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "This operation needs a login session!".to_string(),
+            )),
+        }
+    }
+
+    /**
+     * Get the session of an impersonation, none when the user acts in person
+     */
+    pub fn impersonation(&self) -> Option<&Session> {
+        match &self.credential {
+            Credential::Session { session, .. } if session.impersonator_id.is_some() => {
+                Some(session)
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * Get the login session of the request, refusing an impersonation
+     *
+     * @note Changes only the user may make call it, e.g. the password or the deletion of the account
+     */
+    pub fn own_session(&self) -> Result<&Session, WebError> {
+        if self.impersonation().is_some() {
+            return Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "This operation is not allowed while impersonating!".to_string(),
+            ));
+        }
+        self.session()
+    }
+
+    /**
+     * Check whether the request is allowed a permission,
+     * personal access tokens and OAuth clients are limited by their scopes
+     * @param permission The permission required
+     */
+    pub fn has(&self, permission: Permission) -> bool {
+        let granted = match &self.credential {
+            Credential::Session { session, .. } => session
+                .grant
+                .as_ref()
+                .is_none_or(|grant| grant.has(permission)),
+            Credential::PersonalAccessToken(token) => token
+                .scopes
+                .iter()
+                .any(|scope| scope.permission() == permission),
+        };
+        granted && self.user.role.has(permission)
+    }
+}
+
+/**
+ * Tokens returned to the client after a login or a refresh
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    // lifetime of the access token in seconds
+    pub expires_in: i64,
+    // time left before the session expires without use, in seconds
+    pub session_expires_in: i64,
+}
+
+/**
+ * Returned to the admin who starts an impersonation, there is no refresh token
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImpersonationToken {
+    pub access_token: String,
+    pub token_type: String,
+    // lifetime of the access token in seconds, the impersonation ends with it
+    pub expires_in: i64,
+    // the impersonated user
+    pub username: String,
+}
+
+/**
+ * Result of a login, the tokens or the challenge of the second factor
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Token(TokenPair),
+    Challenge(TwoFactorChallenge),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RefreshToken {
+    pub refresh_token: String,
+}
+
+impl std::convert::From<Session> for SessionInfo {
+    fn from(value: Session) -> Self {
+        SessionInfo {
+            id: value._id.unwrap_or_default().to_hex(),
+            device: user_agent_describe(&value.user_agent),
+            user_agent: value.user_agent,
+            ip: value.ip,
+            client_id: value.grant.map(|grant| grant.client_id),
+            create_time: value.create_time,
+            last_used_time: value.last_used_time.unwrap_or(value.create_time),
+            expire_time: value.expire_time,
+            remember_me: value.remember_me,
+            impersonator: value.impersonator,
+            current: false,
+        }
+    }
+}
+
+/**
+ * Information about the client which opens a session
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ClientInfo {
+    pub user_agent: String,
+    pub ip: String,
+}
+
+impl From<&HttpRequest> for ClientInfo {
+    fn from(value: &HttpRequest) -> Self {
+        // the forwarding headers are set by the client unless a trusted proxy overwrites them
+        let trust_proxy_headers = value
+            .app_data::<web::Data<AppState>>()
+            .is_some_and(|state| state.config.login_throttle.trust_proxy_headers);
+        let connection = value.connection_info();
+        let ip = if trust_proxy_headers {
+            connection.realip_remote_addr().map(str::to_string)
+        } else {
+            value.peer_addr().map(|addr| addr.ip().to_string())
+        };
+        ClientInfo {
+            user_agent: value
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|agent| agent.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            ip: ip.unwrap_or_default(),
+        }
+    }
+}
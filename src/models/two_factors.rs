@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * The TOTP second factor of a user, kept out of the user document so it never leaks in a profile
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TwoFactor {
+    pub _id: Option<bson::oid::ObjectId>,
+    pub user_id: bson::oid::ObjectId,
+    // base32 encoded TOTP secret, encrypted with TWO_FACTOR_SECRET_KEY
+    pub secret: String,
+    // false until the first code is confirmed
+    pub enabled: bool,
+    // Argon2id hashes of the single use recovery codes
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    // last accepted time step, a code can not be replayed
+    #[serde(default)]
+    pub last_used_step: i64,
+    pub create_time: i64,
+}
+
+/**
+ * Returned when the user starts the enrollment
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TwoFactorCode {
+    // a TOTP code, or a recovery code where allowed
+    pub code: String,
+}
+
+/**
+ * Recovery codes in plaintext, only shown once
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
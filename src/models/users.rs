@@ -3,6 +3,8 @@ use bson::Bson;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
+use crate::models::roles::Role;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum Gender {
     Male,
@@ -33,7 +35,11 @@ pub struct User {
     pub school: String,
     pub major: String,
     pub phone: String,
+    #[serde(default)]
+    pub phone_verified: bool,
     pub email: String,
+    #[serde(default)]
+    pub email_verified: bool,
 
     // list info
     pub following: Vec<String>,
@@ -44,12 +50,21 @@ pub struct User {
     // register time
     pub register_time: i64,
 
-    // token
-    pub token: String,
-    pub valid_token_time: i64,
-
-    // is deprecated
+    // is deprecated, the deletion was requested by the user
     pub is_deprecated: bool,
+    // when the deletion was requested, the account can be restored during the grace period
+    #[serde(default)]
+    pub delete_time: Option<i64>,
+    // the personal data of a deleted account has been erased
+    #[serde(default)]
+    pub is_anonymized: bool,
+    // suspended by an admin, the user can not login
+    #[serde(default)]
+    pub is_suspended: bool,
+
+    // access control
+    #[serde(default)]
+    pub role: Role,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -61,14 +76,71 @@ pub struct CreateUser {
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct CertificateUser {
+pub struct QueryUserName {
     pub username: String,
-    pub token: String,
 }
 
+/**
+ * Login request, the identifier is a username, an email or a phone number
+ */
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct QueryUserName {
-    pub username: String,
+pub struct LoginUser {
+    // older clients send the username field
+    #[serde(alias = "username")]
+    pub identifier: String,
+    pub password: String,
+    // keep the session open longer
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChangePassword {
+    // may be left empty by a user who only signs in with an identity provider
+    #[serde(default)]
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/**
+ * Filter of the admin user list, every field is optional
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UserFilter {
+    pub school: Option<String>,
+    pub major: Option<String>,
+    pub education: Option<Education>,
+    // registration time range, in timestamps
+    pub registered_after: Option<i64>,
+    pub registered_before: Option<i64>,
+    pub deprecated: Option<bool>,
+    pub suspended: Option<bool>,
+    // pages start at 1
+    pub page: Option<u64>,
+    pub page_size: Option<u64>,
+}
+
+/**
+ * A page of the admin user list
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    pub total: u64,
+    pub page: u64,
+    pub page_size: u64,
+}
+
+/**
+ * A user as seen by an admin, secrets are blanked
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminUser {
+    #[serde(flatten)]
+    pub user: User,
+    pub two_factor_enabled: bool,
+    // number of open sessions
+    pub sessions: u64,
 }
 
 impl From<web::Json<CreateUser>> for CreateUser {
@@ -95,15 +167,19 @@ impl From<CreateUser> for User {
             school: String::new(),
             major: String::new(),
             phone: value.phone,
+            phone_verified: false,
             email: value.email,
+            email_verified: false,
             following: vec![],
             participated: vec![],
             published: vec![],
             collection: vec![],
             register_time: Utc::now().timestamp(),
-            token: String::new(),
-            valid_token_time: Utc::now().timestamp(),
             is_deprecated: false,
+            delete_time: None,
+            is_anonymized: false,
+            is_suspended: false,
+            role: Role::User,
         }
     }
 }
@@ -141,7 +217,7 @@ impl std::convert::From<Education> for Bson {
     }
 }
 
-impl std::convert::From<User> for Bson {
+impl std::convert::From<User> for bson::Document {
     fn from(value: User) -> Self {
         let mut doc = bson::Document::new();
         doc.insert("username", value.username);
@@ -153,15 +229,86 @@ impl std::convert::From<User> for Bson {
         doc.insert("school", value.school);
         doc.insert("major", value.major);
         doc.insert("phone", value.phone);
+        doc.insert("phone_verified", value.phone_verified);
         doc.insert("email", value.email);
+        doc.insert("email_verified", value.email_verified);
         doc.insert("following", value.following);
         doc.insert("participated", value.participated);
         doc.insert("published", value.published);
         doc.insert("collection", value.collection);
         doc.insert("register_time", value.register_time);
-        doc.insert("token", value.token);
-        doc.insert("valid_token_time", value.valid_token_time);
         doc.insert("is_deprecated", value.is_deprecated);
-        Bson::Document(doc)
+        doc.insert("is_suspended", value.is_suspended);
+        doc.insert("role", value.role);
+        doc
+    }
+}
+
+impl std::convert::From<User> for Bson {
+    fn from(value: User) -> Self {
+        Bson::Document(value.into())
+    }
+}
+
+impl std::convert::From<&UserFilter> for bson::Document {
+    fn from(value: &UserFilter) -> Self {
+        let mut doc = bson::Document::new();
+        if let Some(school) = &value.school {
+            doc.insert("school", school);
+        }
+        if let Some(major) = &value.major {
+            doc.insert("major", major);
+        }
+        if let Some(education) = &value.education {
+            doc.insert("education", education.clone());
+        }
+        let mut register_time = bson::Document::new();
+        if let Some(after) = value.registered_after {
+            register_time.insert("$gte", after);
+        }
+        if let Some(before) = value.registered_before {
+            register_time.insert("$lt", before);
+        }
+        if !register_time.is_empty() {
+            doc.insert("register_time", register_time);
+        }
+        if let Some(deprecated) = value.deprecated {
+            doc.insert("is_deprecated", deprecated);
+        }
+        // users created before the flag existed are not suspended
+        if let Some(suspended) = value.suspended {
+            if suspended {
+                doc.insert("is_suspended", true);
+            } else {
+                doc.insert("is_suspended", bson::doc! {"$ne": true});
+            }
+        }
+        doc
+    }
+}
+
+#[cfg(test)]
+mod user_filter_test {
+    use super::*;
+
+    #[test]
+    fn test_user_filter_document() {
+        let filter = UserFilter {
+            school: Some("mlum".to_string()),
+            education: Some(Education::Master),
+            registered_after: Some(100),
+            deprecated: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            bson::Document::from(&filter),
+            bson::doc! {
+                "school": "mlum",
+                "education": "Master",
+                "register_time": {"$gte": 100_i64},
+                "is_deprecated": false,
+            }
+        );
+        assert!(bson::Document::from(&UserFilter::default()).is_empty());
     }
 }
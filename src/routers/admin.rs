@@ -0,0 +1,50 @@
+use actix_web::web;
+
+use crate::{guards::permission::RequirePermission, handlers::admin::*, models::roles::Permission};
+
+pub fn admin_routers(cfg: &mut web::ServiceConfig) {
+    let manage = RequirePermission::new(Permission::UsersManage);
+    cfg.service(
+        web::scope("/admin/users")
+            .wrap(RequirePermission::new(Permission::UsersRead))
+            .route("", web::get().to(admin_user_list))
+            .route("/{username}", web::get().to(admin_user_find))
+            .route(
+                "/{username}",
+                web::delete().to(admin_user_purge).wrap(manage),
+            )
+            .route(
+                "/{username}/logout",
+                web::post().to(admin_user_logout).wrap(manage),
+            )
+            .route(
+                "/{username}/suspend",
+                web::post().to(admin_user_suspend).wrap(manage),
+            )
+            .route(
+                "/{username}/unsuspend",
+                web::post().to(admin_user_unsuspend).wrap(manage),
+            )
+            .route(
+                "/{username}/restore",
+                web::post().to(admin_user_restore).wrap(manage),
+            )
+            .route(
+                "/{username}/impersonate",
+                web::post()
+                    .to(admin_user_impersonate)
+                    .wrap(RequirePermission::new(Permission::UsersImpersonate)),
+            )
+            .route(
+                "/{username}/role",
+                web::put()
+                    .to(admin_user_role_update)
+                    .wrap(RequirePermission::new(Permission::RolesManage)),
+            ),
+    )
+    .service(
+        web::scope("/admin/audit")
+            .wrap(RequirePermission::new(Permission::AuditRead))
+            .route("", web::get().to(admin_audit_list)),
+    );
+}
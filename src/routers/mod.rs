@@ -1,2 +1,4 @@
+pub mod admin;
 pub mod general;
+pub mod oauth;
 pub mod users;
\ No newline at end of file
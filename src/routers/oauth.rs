@@ -0,0 +1,29 @@
+use actix_web::web;
+
+use crate::{guards::permission::RequirePermission, handlers::oauth::*, models::roles::Permission};
+
+pub fn oauth_routers(cfg: &mut web::ServiceConfig) {
+    let manage = RequirePermission::new(Permission::ClientsManage);
+    cfg.route(
+        "/.well-known/openid-configuration",
+        web::get().to(oauth_configuration),
+    )
+    .service(
+        web::scope("/oauth")
+            .route("/authorize", web::get().to(oauth_authorize))
+            .route("/authorize", web::post().to(oauth_authorize_decide))
+            .route("/token", web::post().to(oauth_token))
+            .route("/userinfo", web::get().to(oauth_userinfo))
+            .route("/userinfo", web::post().to(oauth_userinfo))
+            .route("/jwks", web::get().to(oauth_jwks))
+            .route("/clients", web::get().to(oauth_client_list).wrap(manage))
+            .route(
+                "/clients",
+                web::post().to(oauth_client_register).wrap(manage),
+            )
+            .route(
+                "/clients/{client_id}",
+                web::delete().to(oauth_client_remove).wrap(manage),
+            ),
+    );
+}
@@ -1,16 +1,76 @@
 use actix_web::web;
 
-use crate::handlers::users::*;
+use crate::{guards::permission::RequirePermission, handlers::users::*, models::roles::Permission};
 
 pub fn user_routers(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
             .route("/register", web::post().to(user_register))
             .route("/login", web::post().to(user_login))
+            .route("/login/sms/send", web::post().to(user_login_code_send))
+            .route("/login/sms", web::post().to(user_login_code))
+            .route("/login/2fa", web::post().to(user_login_two_factor))
+            .route("/login/providers", web::get().to(user_identity_providers))
+            .route(
+                "/login/providers/{provider}",
+                web::post().to(user_external_login_start),
+            )
+            .route(
+                "/login/providers/{provider}/callback",
+                web::post().to(user_external_login),
+            )
             .route("/logout", web::post().to(user_logout))
+            .route("/impersonation/stop", web::post().to(user_impersonate_stop))
+            .route("/token/refresh", web::post().to(user_token_refresh))
+            .route("/token/introspect", web::post().to(user_token_introspect))
+            .route(
+                "/password/change",
+                web::post()
+                    .to(user_password_change)
+                    .wrap(RequirePermission::new(Permission::ProfileWrite)),
+            )
+            .route("/password/forgot", web::post().to(user_password_forgot))
+            .route("/password/reset", web::post().to(user_password_reset))
+            .route("/email/verify", web::post().to(user_email_verify))
+            .route("/email/resend", web::post().to(user_email_resend))
+            .route("/phone/send", web::post().to(user_phone_send))
+            .route("/phone/verify", web::post().to(user_phone_verify))
+            .route("/2fa/setup", web::post().to(user_two_factor_setup))
+            .route("/2fa/enable", web::post().to(user_two_factor_enable))
+            .route("/2fa/disable", web::post().to(user_two_factor_disable))
+            .route("/2fa/recovery", web::post().to(user_two_factor_recovery))
+            .route("/tokens", web::get().to(user_access_token_list))
+            .route("/tokens", web::post().to(user_access_token_create))
+            .route("/tokens/{id}", web::delete().to(user_access_token_revoke))
+            .route("/sessions", web::get().to(user_session_list))
+            .route("/sessions", web::delete().to(user_session_revoke_others))
+            .route("/sessions/{id}", web::delete().to(user_session_revoke))
+            .route("/identities", web::get().to(user_identity_list))
+            .route(
+                "/identities/{provider}",
+                web::post().to(user_identity_link_start),
+            )
+            .route(
+                "/identities/{provider}",
+                web::delete().to(user_identity_unlink),
+            )
+            .route(
+                "/identities/{provider}/callback",
+                web::post().to(user_identity_link),
+            )
             .route("/profile", web::get().to(user_profile))
-            .route("/update", web::put().to(user_update))
-            .route("/delete", web::delete().to(user_delete))
+            .route(
+                "/update",
+                web::put()
+                    .to(user_update)
+                    .wrap(RequirePermission::new(Permission::ProfileWrite)),
+            )
+            .route(
+                "/delete",
+                web::delete()
+                    .to(user_delete)
+                    .wrap(RequirePermission::new(Permission::ProfileWrite)),
+            )
             .route("/verify", web::post().to(user_verify)),
     );
 }
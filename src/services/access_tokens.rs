@@ -0,0 +1,220 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOptions, IndexOptions},
+    Client, IndexModel,
+};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    models::access_tokens::{
+        AccessTokenInfo, CreateAccessToken, CreatedAccessToken, PersonalAccessToken,
+    },
+    utils::token::{personal_access_token_generator, token_digest, token_digest_legacy},
+};
+
+/**
+ * Get the personal access token collection from the database
+ * @param database The database client
+ */
+pub fn serv_access_token_database(database: &Client) -> mongodb::Collection<PersonalAccessToken> {
+    database.database("test").collection("access_tokens")
+}
+
+/**
+ * Create the indexes of the personal access token collection
+ * @param database The database client
+ */
+pub async fn serv_access_token_indexes(database: &Client) -> Result<(), WebError> {
+    serv_access_token_database(database)
+        .create_indexes(
+            vec![
+                IndexModel::builder()
+                    .keys(doc! {"token_digest": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder().keys(doc! {"user_id": 1}).build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Create a personal access token
+ * @param database The database client
+ * @param config The service configuration
+ * @param user_id The id of the owner
+ * @param create The name, the scopes and the lifetime of the token
+ *
+ * @return The token, only returned this time
+ */
+pub async fn serv_access_token_create(
+    database: &Client,
+    config: &Config,
+    user_id: bson::oid::ObjectId,
+    create: CreateAccessToken,
+) -> Result<CreatedAccessToken, WebError> {
+    let name = create.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(WebError::new(
+            StatusCode::BAD_REQUEST,
+            "Token name must be 1 to 64 characters long!".to_string(),
+        ));
+    }
+    if create.scopes.is_empty() {
+        return Err(WebError::new(
+            StatusCode::BAD_REQUEST,
+            "Token needs at least one scope!".to_string(),
+        ));
+    }
+    if create.expires_in.is_some_and(|expires_in| expires_in <= 0) {
+        return Err(WebError::new(
+            StatusCode::BAD_REQUEST,
+            "Token lifetime must be positive!".to_string(),
+        ));
+    }
+
+    let now = Utc::now().timestamp();
+    let token = personal_access_token_generator();
+    let mut scopes = create.scopes;
+    scopes.sort_unstable();
+    scopes.dedup();
+    let access_token = PersonalAccessToken {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id,
+        name,
+        token_digest: token_digest(&config.token, &token),
+        token_prefix: token.chars().take(13).collect(),
+        scopes,
+        create_time: now,
+        expire_time: create.expires_in.map(|expires_in| now + expires_in),
+        last_used_time: None,
+    };
+    serv_access_token_database(database)
+        .insert_one(&access_token, None)
+        .await?;
+
+    Ok(CreatedAccessToken {
+        token,
+        info: access_token.into(),
+    })
+}
+
+/**
+ * List the personal access tokens of a user
+ * @param database The database client
+ * @param user_id The id of the owner
+ *
+ * @return The tokens, newest first
+ */
+pub async fn serv_access_token_list(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<Vec<AccessTokenInfo>, WebError> {
+    let tokens: Vec<PersonalAccessToken> = serv_access_token_database(database)
+        .find(
+            doc! {"user_id": user_id},
+            FindOptions::builder()
+                .sort(doc! {"create_time": -1})
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(tokens.into_iter().map(AccessTokenInfo::from).collect())
+}
+
+/**
+ * Find the personal access token presented by a request
+ * @param database The database client
+ * @param config The service configuration
+ * @param token The token
+ *
+ * @return The token, expired tokens are rejected
+ *
+ * @note A token stored before the digests were keyed is upgraded by its first use
+ */
+pub async fn serv_access_token_find(
+    database: &Client,
+    config: &Config,
+    token: &str,
+) -> Result<PersonalAccessToken, WebError> {
+    let now = Utc::now().timestamp();
+    let digest = token_digest(&config.token, token);
+    let legacy_digest = token_digest_legacy(token);
+    let access_token = serv_access_token_database(database)
+        .find_one(
+            doc! {"token_digest": {"$in": [&digest, &legacy_digest]}},
+            None,
+        )
+        .await?
+        .filter(|access_token| access_token.expire_time.is_none_or(|expire| expire >= now))
+        .ok_or_else(|| WebError::new(StatusCode::UNAUTHORIZED, "Token error!".to_string()))?;
+
+    if access_token.token_digest == legacy_digest {
+        serv_access_token_database(database)
+            .update_one(
+                doc! {"_id": access_token._id, "token_digest": &legacy_digest},
+                doc! {"$set": {"token_digest": &digest}},
+                None,
+            )
+            .await?;
+    }
+
+    // the last use is only recorded once a minute, not on every request
+    if access_token
+        .last_used_time
+        .is_none_or(|used| now - used >= 60)
+    {
+        serv_access_token_database(database)
+            .update_one(
+                doc! {"_id": access_token._id},
+                doc! {"$set": {"last_used_time": now}},
+                None,
+            )
+            .await?;
+    }
+    Ok(access_token)
+}
+
+/**
+ * Revoke a personal access token
+ * @param database The database client
+ * @param user_id The id of the owner
+ * @param token_id The id of the token
+ */
+pub async fn serv_access_token_revoke(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+    token_id: &str,
+) -> Result<(), WebError> {
+    let not_found = || WebError::new(StatusCode::NOT_FOUND, "Token not found!".to_string());
+    let token_id = bson::oid::ObjectId::parse_str(token_id).map_err(|_| not_found())?;
+    let res = serv_access_token_database(database)
+        .delete_one(doc! {"_id": token_id, "user_id": user_id}, None)
+        .await?;
+    if res.deleted_count == 0 {
+        return Err(not_found());
+    }
+    Ok(())
+}
+
+/**
+ * Revoke every personal access token of a user
+ * @param database The database client
+ * @param user_id The id of the owner
+ */
+pub async fn serv_access_token_revoke_all(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    serv_access_token_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
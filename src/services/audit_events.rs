@@ -0,0 +1,130 @@
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{bson::doc, options::FindOptions, Client, IndexModel};
+
+use crate::{
+    config::AuditConfig,
+    errors::WebError,
+    models::audit_events::{AuditEvent, AuditFilter, AuditOutcome, AuditPage},
+};
+
+/**
+ * Get the audit event collection from the database
+ * @param database The database client
+ */
+pub fn serv_audit_event_database(database: &Client) -> mongodb::Collection<AuditEvent> {
+    database.database("test").collection("audit_events")
+}
+
+/**
+ * Create the indexes of the audit event collection
+ * @param database The database client
+ */
+pub async fn serv_audit_event_indexes(database: &Client) -> Result<(), WebError> {
+    serv_audit_event_database(database)
+        .create_indexes(
+            vec![
+                IndexModel::builder().keys(doc! {"time": -1}).build(),
+                IndexModel::builder()
+                    .keys(doc! {"actor": 1, "time": -1})
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! {"target": 1, "time": -1})
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! {"action": 1, "time": -1})
+                    .build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Append an event to the audit log
+ * @param database The database client
+ * @param event The event
+ * @param result The result of the audited operation, a failure is recorded with its message
+ *
+ * @note Called after the operation, which can not be undone anymore,
+ *       so an event that can not be written is logged instead of failing the request
+ */
+pub async fn serv_audit_record<T>(
+    database: &Client,
+    mut event: AuditEvent,
+    result: &Result<T, WebError>,
+) {
+    if let Err(err) = result {
+        event.outcome = AuditOutcome::Failure;
+        event.detail = Some(err.to_string());
+    }
+
+    if let Err(err) = serv_audit_event_database(database)
+        .insert_one(&event, None)
+        .await
+    {
+        eprintln!("Failed to record the audit event {:?}: {}", event, err);
+    }
+}
+
+/**
+ * List the audit events matching a filter
+ * @param database The database client
+ * @param filter The filter and the page
+ *
+ * @return A page of events, newest first
+ */
+pub async fn serv_audit_event_list(
+    database: &Client,
+    filter: AuditFilter,
+) -> Result<AuditPage, WebError> {
+    let events = serv_audit_event_database(database);
+
+    let page = filter.page.unwrap_or(1).max(1);
+    let page_size = filter.page_size.unwrap_or(20).clamp(1, 100);
+    let query = bson::Document::from(&filter);
+
+    let total = events.count_documents(query.clone(), None).await?;
+    let list: Vec<AuditEvent> = events
+        .find(
+            query,
+            FindOptions::builder()
+                .sort(doc! {"time": -1})
+                .skip((page - 1) * page_size)
+                .limit(page_size as i64)
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+
+    Ok(AuditPage {
+        events: list,
+        total,
+        page,
+        page_size,
+    })
+}
+
+/**
+ * Drop the events older than the retention
+ * @param database The database client
+ * @param config The audit configuration
+ *
+ * @return The number of dropped events
+ */
+pub async fn serv_audit_event_prune(
+    database: &Client,
+    config: &AuditConfig,
+) -> Result<u64, WebError> {
+    if config.retention_days <= 0 {
+        return Ok(0);
+    }
+
+    let oldest = Utc::now().timestamp() - config.retention_days * 24 * 3600;
+    let res = serv_audit_event_database(database)
+        .delete_many(doc! {"time": {"$lt": oldest}}, None)
+        .await?;
+    Ok(res.deleted_count)
+}
@@ -0,0 +1,157 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use mongodb::{bson::doc, options::FindOneOptions, options::IndexOptions, Client, IndexModel};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    mailers::{Mail, Mailer},
+    models::{email_verifications::EmailVerification, users::User},
+    utils::token::{token_digest, token_generator},
+};
+
+/**
+ * Get the email verification collection from the database
+ * @param database The database client
+ */
+pub fn serv_email_verification_database(
+    database: &Client,
+) -> mongodb::Collection<EmailVerification> {
+    database.database("test").collection("email_verifications")
+}
+
+/**
+ * Create the indexes of the email verification collection
+ * @param database The database client
+ */
+pub async fn serv_email_verification_indexes(database: &Client) -> Result<(), WebError> {
+    serv_email_verification_database(database)
+        .create_indexes(
+            vec![
+                IndexModel::builder()
+                    .keys(doc! {"code_digest": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder().keys(doc! {"user_id": 1}).build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Send a verification code to the email of a user
+ * @param database The database client
+ * @param config The service configuration
+ * @param mailer The mailer which sends the code
+ * @param user The user to verify
+ *
+ * @note A new code can only be sent once per resend interval, older codes stay valid
+ */
+pub async fn serv_email_verification_send(
+    database: &Client,
+    config: &Config,
+    mailer: &dyn Mailer,
+    user: &User,
+) -> Result<(), WebError> {
+    let verifications = serv_email_verification_database(database);
+    let user_id = user._id.unwrap_or_default();
+    let now = Utc::now().timestamp();
+
+    let last = verifications
+        .find_one(
+            doc! {"user_id": user_id},
+            FindOneOptions::builder()
+                .sort(doc! {"create_time": -1})
+                .build(),
+        )
+        .await?;
+    if let Some(last) = last {
+        let next = last.create_time + config.email_verification.resend_interval;
+        if next > now {
+            return Err(WebError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Verification mail has been sent recently, try again later!".to_string(),
+            )
+            .with_retry_after(next - now));
+        }
+    }
+
+    let code = token_generator();
+    let verification = EmailVerification {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id,
+        email: user.email.clone(),
+        code_digest: token_digest(&config.token, &code),
+        create_time: now,
+        expire_time: now + config.email_verification.code_lifetime,
+    };
+    verifications.insert_one(&verification, None).await?;
+
+    mailer
+        .send(Mail {
+            to: user.email.clone(),
+            subject: "Verify your mlum email".to_string(),
+            body: format!(
+                "Hi {},\n\nOpen the link below to verify your email:\n{}\n\nThe link expires in {} hours.",
+                user.username,
+                config
+                    .email_verification
+                    .url
+                    .replace("{code}", &code),
+                config.email_verification.code_lifetime / 3600
+            ),
+        })
+        .await
+}
+
+/**
+ * Consume a verification code
+ * @param database The database client
+ * @param config The service configuration
+ * @param code The verification code
+ *
+ * @return The verification, every code of the user is dropped
+ */
+pub async fn serv_email_verification_consume(
+    database: &Client,
+    config: &Config,
+    code: String,
+) -> Result<EmailVerification, WebError> {
+    let verifications = serv_email_verification_database(database);
+    let verification = verifications
+        .find_one(
+            doc! {"code_digest": token_digest(&config.token, &code)},
+            None,
+        )
+        .await?
+        .filter(|verification| verification.expire_time >= Utc::now().timestamp())
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Verification code is invalid or expired!".to_string(),
+            )
+        })?;
+
+    verifications
+        .delete_many(doc! {"user_id": verification.user_id}, None)
+        .await?;
+
+    Ok(verification)
+}
+
+/**
+ * Drop every verification code of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_email_verification_revoke_all(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    serv_email_verification_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
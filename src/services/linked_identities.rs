@@ -0,0 +1,313 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, IndexModel,
+};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    identity_providers::{ExternalIdentity, IdentityProvider},
+    models::linked_identities::{
+        ExternalAuthorization, ExternalLoginState, LinkedIdentity, LinkedIdentityInfo,
+    },
+    utils::{
+        oauth::pkce_challenge,
+        token::{token_digest, token_generator},
+    },
+};
+
+/**
+ * Get the linked identity collection from the database
+ * @param database The database client
+ */
+pub fn serv_linked_identity_database(database: &Client) -> mongodb::Collection<LinkedIdentity> {
+    database.database("test").collection("linked_identities")
+}
+
+/**
+ * Get the pending external sign-in collection from the database
+ * @param database The database client
+ */
+pub fn serv_external_login_state_database(
+    database: &Client,
+) -> mongodb::Collection<ExternalLoginState> {
+    database
+        .database("test")
+        .collection("external_login_states")
+}
+
+/**
+ * Create the indexes of the linked identity and external sign-in collections
+ * @param database The database client
+ *
+ * @note An account of a provider is linked to one user, a user links one account per provider.
+ *       Sign-ins which never came back are dropped once they expire
+ */
+pub async fn serv_linked_identity_indexes(database: &Client) -> Result<(), WebError> {
+    let unique = |keys| {
+        IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().unique(true).build())
+            .build()
+    };
+    serv_linked_identity_database(database)
+        .create_indexes(
+            vec![
+                unique(doc! {"provider": 1, "subject": 1}),
+                unique(doc! {"user_id": 1, "provider": 1}),
+            ],
+            None,
+        )
+        .await?;
+    serv_external_login_state_database(database)
+        .create_indexes(
+            vec![
+                unique(doc! {"state_digest": 1}),
+                IndexModel::builder()
+                    .keys(doc! {"expire_date": 1})
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(std::time::Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Start a sign-in with a provider
+ * @param database The database client
+ * @param config The service configuration
+ * @param provider The identity provider
+ * @param user_id The user linking the provider, none for a login
+ * @param remember_me Whether the session opened by the login gets the longer timeouts
+ *
+ * @return The url of the provider the browser is sent to
+ */
+pub async fn serv_external_login_start(
+    database: &Client,
+    config: &Config,
+    provider: &dyn IdentityProvider,
+    user_id: Option<ObjectId>,
+    remember_me: bool,
+) -> Result<ExternalAuthorization, WebError> {
+    let lifetime = config.identity_providers.state_lifetime;
+    let expire_time = Utc::now().timestamp() + lifetime;
+    let token = token_generator();
+    let state = ExternalLoginState {
+        _id: Some(ObjectId::new()),
+        state_digest: token_digest(&config.token, &token),
+        provider: provider.name().to_string(),
+        // 64 unreserved characters
+        code_verifier: format!("{}{}", token_generator(), token_generator()),
+        redirect_uri: config.identity_providers.redirect_uri(provider.name()),
+        user_id,
+        remember_me,
+        expire_time,
+        expire_date: Some(DateTime::from_millis(expire_time * 1000)),
+    };
+    serv_external_login_state_database(database)
+        .insert_one(&state, None)
+        .await?;
+
+    Ok(ExternalAuthorization {
+        authorize_url: provider.authorize_url(
+            &state.redirect_uri,
+            &token,
+            &pkce_challenge(&state.code_verifier),
+        ),
+        state: token,
+        expires_in: lifetime,
+    })
+}
+
+/**
+ * Consume a pending sign-in when the provider calls back
+ * @param database The database client
+ * @param config The service configuration
+ * @param provider The name of the provider
+ * @param state The state echoed by the provider
+ *
+ * @note A state can be used once, whatever the outcome of the sign-in
+ */
+pub async fn serv_external_login_consume(
+    database: &Client,
+    config: &Config,
+    provider: &str,
+    state: &str,
+) -> Result<ExternalLoginState, WebError> {
+    serv_external_login_state_database(database)
+        .find_one_and_delete(
+            doc! {
+                "state_digest": token_digest(&config.token, state),
+                "provider": provider,
+                "expire_time": {"$gte": Utc::now().timestamp()},
+            },
+            None,
+        )
+        .await?
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Sign-in is invalid or expired!".to_string(),
+            )
+        })
+}
+
+/**
+ * Find the user linked to an account of a provider
+ * @param database The database client
+ * @param provider The name of the provider
+ * @param subject The id of the account at the provider
+ */
+pub async fn serv_linked_identity_find(
+    database: &Client,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<LinkedIdentity>, WebError> {
+    Ok(serv_linked_identity_database(database)
+        .find_one(doc! {"provider": provider, "subject": subject}, None)
+        .await?)
+}
+
+/**
+ * Link an account of a provider to a user
+ * @param database The database client
+ * @param user_id The id of the user
+ * @param provider The name of the provider
+ * @param identity The account at the provider
+ *
+ * @return The linked identity
+ */
+pub async fn serv_linked_identity_link(
+    database: &Client,
+    user_id: ObjectId,
+    provider: &str,
+    identity: &ExternalIdentity,
+) -> Result<LinkedIdentity, WebError> {
+    let identities = serv_linked_identity_database(database);
+
+    if let Some(linked) = serv_linked_identity_find(database, provider, &identity.subject).await? {
+        if linked.user_id != user_id {
+            return Err(WebError::new(
+                StatusCode::CONFLICT,
+                "This account is already linked to another user!".to_string(),
+            ));
+        }
+        return Ok(linked);
+    }
+    if identities
+        .find_one(doc! {"user_id": user_id, "provider": provider}, None)
+        .await?
+        .is_some()
+    {
+        return Err(WebError::new(
+            StatusCode::CONFLICT,
+            "Another account of this provider is already linked!".to_string(),
+        ));
+    }
+
+    let linked = LinkedIdentity {
+        _id: Some(ObjectId::new()),
+        user_id,
+        provider: provider.to_string(),
+        subject: identity.subject.clone(),
+        username: identity.username.clone(),
+        email: identity.email.clone(),
+        create_time: Utc::now().timestamp(),
+        last_login_time: None,
+    };
+    identities.insert_one(&linked, None).await?;
+    Ok(linked)
+}
+
+/**
+ * Record a login with a linked identity, the name and email shown to the user are refreshed
+ * @param database The database client
+ * @param linked The linked identity
+ * @param identity The account as reported by the provider
+ */
+pub async fn serv_linked_identity_touch(
+    database: &Client,
+    linked: &LinkedIdentity,
+    identity: &ExternalIdentity,
+) -> Result<(), WebError> {
+    serv_linked_identity_database(database)
+        .update_one(
+            doc! {"_id": linked._id},
+            doc! {"$set": {
+                "username": identity.username.clone(),
+                "email": identity.email.clone(),
+                "last_login_time": Utc::now().timestamp(),
+            }},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * List the identities linked to a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_linked_identity_list(
+    database: &Client,
+    user_id: ObjectId,
+) -> Result<Vec<LinkedIdentityInfo>, WebError> {
+    let identities: Vec<LinkedIdentity> = serv_linked_identity_database(database)
+        .find(doc! {"user_id": user_id}, None)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(identities.into_iter().map(Into::into).collect())
+}
+
+/**
+ * Unlink a provider from a user
+ * @param database The database client
+ * @param user_id The id of the user
+ * @param provider The name of the provider
+ */
+pub async fn serv_linked_identity_unlink(
+    database: &Client,
+    user_id: ObjectId,
+    provider: &str,
+) -> Result<(), WebError> {
+    let res = serv_linked_identity_database(database)
+        .delete_one(doc! {"user_id": user_id, "provider": provider}, None)
+        .await?;
+    if res.deleted_count == 0 {
+        return Err(WebError::new(
+            StatusCode::NOT_FOUND,
+            "Identity not found!".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/**
+ * Unlink every provider and drop the pending sign-ins of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_linked_identity_revoke_all(
+    database: &Client,
+    user_id: ObjectId,
+) -> Result<(), WebError> {
+    serv_linked_identity_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    serv_external_login_state_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
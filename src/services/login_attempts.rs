@@ -0,0 +1,340 @@
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use chrono::Utc;
+
+use crate::{
+    config::LoginThrottleConfig, errors::WebError, models::login_attempts::LoginAttempt,
+    stores::login_attempts::LoginAttemptStore,
+};
+
+fn user_key(username: &str) -> String {
+    format!("user:{}", username)
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+/**
+ * The attempts of a login, counted before its credentials are checked
+ */
+pub struct LoginAttemptReservation {
+    user: LoginAttempt,
+    ip: Option<LoginAttempt>,
+}
+
+/**
+ * Count an attempt of a key before the credentials are checked
+ * @param store The login attempt store
+ * @param key The username or ip key
+ * @param max_failures The number of failures which locks the key
+ * @param config The throttle configuration
+ * @param now The current timestamp
+ *
+ * @return The attempts including this one, with the lock if it is one too many
+ *
+ * @note Parallel attempts are all counted before any of them is checked,
+ *       so a wave of guesses can not slip through before the first failure locks the key
+ */
+async fn login_attempt_reserve(
+    store: &dyn LoginAttemptStore,
+    key: String,
+    max_failures: u32,
+    config: &LoginThrottleConfig,
+    now: i64,
+) -> Result<LoginAttempt, WebError> {
+    let mut attempt = store
+        .increment(&key, now, now - config.failure_window)
+        .await?;
+    if attempt.failures > max_failures && attempt.locked_until <= now {
+        attempt.locked_until = now + config.lockout_duration;
+        store.lock(&key, attempt.locked_until).await?;
+    }
+    Ok(attempt)
+}
+
+/**
+ * Lock a key whose reserved attempt failed and used up the last allowed one
+ * @param store The login attempt store
+ * @param attempt The reserved attempts of the key
+ * @param max_failures The number of failures which locks the key
+ * @param config The throttle configuration
+ * @param now The current timestamp
+ */
+async fn login_attempt_lock(
+    store: &dyn LoginAttemptStore,
+    attempt: &mut LoginAttempt,
+    max_failures: u32,
+    config: &LoginThrottleConfig,
+    now: i64,
+) -> Result<(), WebError> {
+    if attempt.failures >= max_failures && attempt.locked_until <= now {
+        attempt.locked_until = now + config.lockout_duration;
+        store.lock(&attempt._id, attempt.locked_until).await?;
+    }
+    Ok(())
+}
+
+/**
+ * Delay applied to a failed login, doubled by every failure
+ * @param failures The number of failures
+ * @param config The throttle configuration
+ */
+pub fn login_attempt_delay(failures: u32, config: &LoginThrottleConfig) -> Duration {
+    let factor = 1u64 << failures.saturating_sub(1).min(16);
+    Duration::from_millis(
+        config
+            .base_delay
+            .saturating_mul(factor)
+            .min(config.max_delay),
+    )
+}
+
+fn locked_error(attempt: &LoginAttempt, by_ip: bool, now: i64) -> WebError {
+    let error = if by_ip {
+        WebError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many failed logins, try again later!".to_string(),
+        )
+    } else {
+        WebError::new(
+            StatusCode::LOCKED,
+            "Account is locked, try again later!".to_string(),
+        )
+    };
+    error.with_retry_after(attempt.locked_until - now)
+}
+
+/**
+ * Count a login attempt before its credentials are checked,
+ * reject it if the username or the client ip is locked
+ * @param store The login attempt store
+ * @param config The throttle configuration
+ * @param username The username of the login
+ * @param ip The ip of the client
+ *
+ * @return The reservation, passed to `serv_login_attempt_fail` or `serv_login_attempt_release`
+ */
+pub async fn serv_login_attempt_reserve(
+    store: &dyn LoginAttemptStore,
+    config: &LoginThrottleConfig,
+    username: &str,
+    ip: &str,
+) -> Result<LoginAttemptReservation, WebError> {
+    let now = Utc::now().timestamp();
+
+    let user = login_attempt_reserve(
+        store,
+        user_key(username),
+        config.max_user_failures,
+        config,
+        now,
+    )
+    .await?;
+    if user.locked_until > now {
+        return Err(locked_error(&user, false, now));
+    }
+
+    let ip = if ip.is_empty() {
+        None
+    } else {
+        Some(login_attempt_reserve(store, ip_key(ip), config.max_ip_failures, config, now).await?)
+    };
+    if let Some(attempt) = ip.as_ref().filter(|attempt| attempt.locked_until > now) {
+        // the attempt is refused before it is checked, the user is not charged for it
+        store.release(&user._id).await?;
+        return Err(locked_error(attempt, true, now));
+    }
+
+    Ok(LoginAttemptReservation { user, ip })
+}
+
+/**
+ * Keep a reserved attempt as a failure and slow the client down
+ * @param store The login attempt store
+ * @param config The throttle configuration
+ * @param reservation The reserved attempt
+ *
+ * @return The error to answer the login with
+ */
+pub async fn serv_login_attempt_fail(
+    store: &dyn LoginAttemptStore,
+    config: &LoginThrottleConfig,
+    reservation: LoginAttemptReservation,
+) -> Result<WebError, WebError> {
+    let now = Utc::now().timestamp();
+    let LoginAttemptReservation {
+        user: mut user_attempt,
+        ip: mut ip_attempt,
+    } = reservation;
+
+    login_attempt_lock(
+        store,
+        &mut user_attempt,
+        config.max_user_failures,
+        config,
+        now,
+    )
+    .await?;
+    if let Some(attempt) = ip_attempt.as_mut() {
+        login_attempt_lock(store, attempt, config.max_ip_failures, config, now).await?;
+    }
+
+    let failures = ip_attempt
+        .as_ref()
+        .map_or(user_attempt.failures, |attempt| {
+            attempt.failures.max(user_attempt.failures)
+        });
+    tokio::time::sleep(login_attempt_delay(failures, config)).await;
+
+    if user_attempt.locked_until > now {
+        return Ok(locked_error(&user_attempt, false, now));
+    }
+    if let Some(attempt) = ip_attempt.filter(|attempt| attempt.locked_until > now) {
+        return Ok(locked_error(&attempt, true, now));
+    }
+    Ok(WebError::new(
+        StatusCode::UNAUTHORIZED,
+        "Username or password error!".to_string(),
+    ))
+}
+
+/**
+ * Give back a reserved attempt whose credentials were right
+ * @param store The login attempt store
+ * @param reservation The reserved attempt
+ *
+ * @note The earlier failures stay counted until `serv_login_attempt_success`
+ */
+pub async fn serv_login_attempt_release(
+    store: &dyn LoginAttemptStore,
+    reservation: LoginAttemptReservation,
+) -> Result<(), WebError> {
+    store.release(&reservation.user._id).await?;
+    if let Some(attempt) = reservation.ip {
+        store.release(&attempt._id).await?;
+    }
+    Ok(())
+}
+
+/**
+ * Forget the failed logins of a username after a successful login
+ * @param store The login attempt store
+ * @param username The username of the login
+ *
+ * @note The failures of the ip are kept, an attacker can not reset them with their own account
+ */
+pub async fn serv_login_attempt_success(
+    store: &dyn LoginAttemptStore,
+    username: &str,
+) -> Result<(), WebError> {
+    store.remove(&user_key(username)).await
+}
+
+#[cfg(test)]
+mod login_attempt_test {
+    use actix_web::ResponseError;
+
+    use super::*;
+    use crate::stores::login_attempts::MemoryLoginAttemptStore;
+
+    fn test_config() -> LoginThrottleConfig {
+        LoginThrottleConfig {
+            max_user_failures: 3,
+            max_ip_failures: 5,
+            base_delay: 0,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_attempt_window() {
+        let config = test_config();
+        let store = MemoryLoginAttemptStore::default();
+        let attempt = login_attempt_reserve(&store, "user:a".to_string(), 3, &config, 100)
+            .await
+            .unwrap();
+        assert_eq!(attempt.failures, 1);
+        let attempt = login_attempt_reserve(&store, "user:a".to_string(), 3, &config, 200)
+            .await
+            .unwrap();
+        assert_eq!(attempt.failures, 2);
+        let attempt = login_attempt_reserve(
+            &store,
+            "user:a".to_string(),
+            3,
+            &config,
+            200 + config.failure_window + 1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(attempt.failures, 1);
+    }
+
+    // a wave of guesses is counted before any of them is checked
+    #[tokio::test]
+    async fn test_login_attempt_parallel() {
+        let config = test_config();
+        let store = MemoryLoginAttemptStore::default();
+        let reservations = futures::future::join_all(
+            (0..10).map(|_| serv_login_attempt_reserve(&store, &config, "dessera", "")),
+        )
+        .await;
+        let allowed = reservations.iter().filter(|res| res.is_ok()).count();
+        assert_eq!(allowed, config.max_user_failures as usize);
+        let attempt = store.get(&user_key("dessera")).await.unwrap().unwrap();
+        assert!(attempt.locked_until > 0);
+    }
+
+    #[test]
+    fn test_login_attempt_delay() {
+        let config = LoginThrottleConfig::default();
+        assert_eq!(login_attempt_delay(1, &config), Duration::from_millis(250));
+        assert_eq!(login_attempt_delay(3, &config), Duration::from_millis(1000));
+        assert_eq!(
+            login_attempt_delay(30, &config),
+            Duration::from_millis(4000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_attempt_lockout() {
+        let config = test_config();
+        let store = MemoryLoginAttemptStore::default();
+
+        for _ in 0..2 {
+            let reservation = serv_login_attempt_reserve(&store, &config, "dessera", "127.0.0.1")
+                .await
+                .unwrap();
+            let error = serv_login_attempt_fail(&store, &config, reservation)
+                .await
+                .unwrap();
+            assert_eq!(error.status_code(), StatusCode::UNAUTHORIZED);
+        }
+        let reservation = serv_login_attempt_reserve(&store, &config, "dessera", "127.0.0.1")
+            .await
+            .unwrap();
+        let error = serv_login_attempt_fail(&store, &config, reservation)
+            .await
+            .unwrap();
+        assert_eq!(error.status_code(), StatusCode::LOCKED);
+        assert!(error.message.retry_after.is_some());
+
+        let error = serv_login_attempt_reserve(&store, &config, "dessera", "127.0.0.2")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.status_code(), StatusCode::LOCKED);
+
+        // a right password gives its attempt back
+        let reservation = serv_login_attempt_reserve(&store, &config, "other", "127.0.0.1")
+            .await
+            .unwrap();
+        serv_login_attempt_release(&store, reservation)
+            .await
+            .unwrap();
+        let attempt = store.get(&ip_key("127.0.0.1")).await.unwrap().unwrap();
+        assert_eq!(attempt.failures, 3);
+    }
+}
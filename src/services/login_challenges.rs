@@ -0,0 +1,171 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use mongodb::{
+    bson::doc,
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument},
+    Client, IndexModel,
+};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    models::login_challenges::{LoginChallenge, TwoFactorChallenge},
+    utils::token::{token_digest, token_generator},
+};
+
+/**
+ * Get the login challenge collection from the database
+ * @param database The database client
+ */
+pub fn serv_login_challenge_database(database: &Client) -> mongodb::Collection<LoginChallenge> {
+    database.database("test").collection("login_challenges")
+}
+
+/**
+ * Create the indexes of the login challenge collection
+ * @param database The database client
+ */
+pub async fn serv_login_challenge_indexes(database: &Client) -> Result<(), WebError> {
+    serv_login_challenge_database(database)
+        .create_indexes(
+            vec![IndexModel::builder()
+                .keys(doc! {"token_digest": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build()],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Create the challenge of a login waiting for the second factor
+ * @param database The database client
+ * @param config The service configuration
+ * @param user_id The id of the user
+ * @param remember_me Whether the login asked for a long session
+ *
+ * @return The challenge sent to the client
+ */
+pub async fn serv_login_challenge_create(
+    database: &Client,
+    config: &Config,
+    user_id: bson::oid::ObjectId,
+    remember_me: bool,
+) -> Result<TwoFactorChallenge, WebError> {
+    let now = Utc::now().timestamp();
+    let token = token_generator();
+    let challenge = LoginChallenge {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id,
+        token_digest: token_digest(&config.token, &token),
+        attempts: 0,
+        remember_me,
+        create_time: now,
+        expire_time: now + config.two_factor.challenge_lifetime,
+    };
+    serv_login_challenge_database(database)
+        .insert_one(&challenge, None)
+        .await?;
+
+    Ok(TwoFactorChallenge {
+        two_factor_required: true,
+        challenge_token: token,
+        expires_in: config.two_factor.challenge_lifetime,
+    })
+}
+
+fn invalid_challenge() -> WebError {
+    WebError::new(
+        StatusCode::UNAUTHORIZED,
+        "Challenge is invalid or expired!".to_string(),
+    )
+}
+
+/**
+ * Find a pending challenge and count an attempt on it
+ * @param database The database client
+ * @param config The service configuration
+ * @param token The challenge token
+ *
+ * @note The attempt is counted before the code is checked, so parallel requests
+ *       can not try more codes than allowed
+ */
+pub async fn serv_login_challenge_attempt(
+    database: &Client,
+    config: &Config,
+    token: String,
+) -> Result<LoginChallenge, WebError> {
+    serv_login_challenge_database(database)
+        .find_one_and_update(
+            doc! {
+                "token_digest": token_digest(&config.token, &token),
+                "attempts": {"$lt": config.two_factor.max_attempts},
+                "expire_time": {"$gte": Utc::now().timestamp()},
+            },
+            doc! {"$inc": {"attempts": 1}},
+            FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .await?
+        .ok_or_else(invalid_challenge)
+}
+
+/**
+ * Drop a challenge whose attempts are used up
+ * @param database The database client
+ * @param config The service configuration
+ * @param challenge The challenge
+ */
+pub async fn serv_login_challenge_fail(
+    database: &Client,
+    config: &Config,
+    challenge: &LoginChallenge,
+) -> Result<(), WebError> {
+    serv_login_challenge_database(database)
+        .delete_one(
+            doc! {
+                "_id": challenge._id,
+                "attempts": {"$gte": config.two_factor.max_attempts},
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Drop a challenge once the login is complete
+ * @param database The database client
+ * @param challenge The challenge
+ *
+ * @note Only one of parallel requests with a right code removes it, the others are refused
+ */
+pub async fn serv_login_challenge_remove(
+    database: &Client,
+    challenge: &LoginChallenge,
+) -> Result<(), WebError> {
+    let res = serv_login_challenge_database(database)
+        .delete_one(doc! {"_id": challenge._id}, None)
+        .await?;
+    if res.deleted_count == 0 {
+        return Err(invalid_challenge());
+    }
+    Ok(())
+}
+
+/**
+ * Drop every pending challenge of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_login_challenge_revoke_all(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    serv_login_challenge_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
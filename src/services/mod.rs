@@ -1 +1,14 @@
-pub mod users;
\ No newline at end of file
+pub mod access_tokens;
+pub mod audit_events;
+pub mod email_verifications;
+pub mod linked_identities;
+pub mod login_attempts;
+pub mod login_challenges;
+pub mod oauth;
+pub mod password_resets;
+pub mod phone_codes;
+pub mod security_events;
+pub mod sessions;
+pub mod token_digests;
+pub mod two_factors;
+pub mod users;
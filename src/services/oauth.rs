@@ -0,0 +1,791 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use data_encoding::BASE64URL_NOPAD;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOptions, IndexOptions, UpdateOptions},
+    Client, IndexModel,
+};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    config::{Config, TokenAlgorithm},
+    errors::WebError,
+    models::{
+        oauth::{
+            AuthorizationCode, AuthorizeDecision, AuthorizeRedirect, AuthorizeRequest,
+            IdTokenClaims, Jwk, JwkSet, OAuthClient, OAuthClientInfo, OAuthConsent, OAuthGrant,
+            OAuthTokenRequest, OAuthTokenResponse, OpenIdConfiguration, RegisterClient,
+            RegisteredClient, UserInfo, OAUTH_SCOPES,
+        },
+        sessions::{AuthenticatedUser, ClientInfo, Credential},
+        users::User,
+    },
+    services::sessions::{serv_session_create, serv_session_revoke_client, serv_session_rotate},
+    services::users::{serv_user_database, serv_user_token_issue},
+    utils::{
+        oauth::{oauth_scope_parse, pkce_verify, redirect_uri_valid, url_with_query},
+        token::{
+            token_digest, token_digest_legacy, token_digest_verify, token_generator, token_key_id,
+            token_public_key, token_sign,
+        },
+    },
+};
+
+/**
+ * Get the OAuth client collection from the database
+ * @param database The database client
+ */
+pub fn serv_oauth_client_database(database: &Client) -> mongodb::Collection<OAuthClient> {
+    database.database("test").collection("oauth_clients")
+}
+
+/**
+ * Get the OAuth consent collection from the database
+ * @param database The database client
+ */
+pub fn serv_oauth_consent_database(database: &Client) -> mongodb::Collection<OAuthConsent> {
+    database.database("test").collection("oauth_consents")
+}
+
+/**
+ * Get the authorization code collection from the database
+ * @param database The database client
+ */
+pub fn serv_oauth_code_database(database: &Client) -> mongodb::Collection<AuthorizationCode> {
+    database.database("test").collection("oauth_codes")
+}
+
+/**
+ * Create the indexes of the OAuth collections
+ * @param database The database client
+ */
+pub async fn serv_oauth_indexes(database: &Client) -> Result<(), WebError> {
+    let unique = || IndexOptions::builder().unique(true).build();
+    serv_oauth_client_database(database)
+        .create_indexes(
+            vec![IndexModel::builder()
+                .keys(doc! {"client_id": 1})
+                .options(unique())
+                .build()],
+            None,
+        )
+        .await?;
+    serv_oauth_consent_database(database)
+        .create_indexes(
+            vec![IndexModel::builder()
+                .keys(doc! {"user_id": 1, "client_id": 1})
+                .options(unique())
+                .build()],
+            None,
+        )
+        .await?;
+    serv_oauth_code_database(database)
+        .create_indexes(
+            vec![IndexModel::builder()
+                .keys(doc! {"code_digest": 1})
+                .options(unique())
+                .build()],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Build an error of the OAuth2 endpoints
+ * @param code The status code
+ * @param error The error code of the specification
+ * @param message The message shown to the developer
+ */
+fn oauth_error(code: StatusCode, error: &str, message: &str) -> WebError {
+    WebError::new(code, message.to_string()).with_oauth_error(error)
+}
+
+fn invalid_grant() -> WebError {
+    oauth_error(
+        StatusCode::BAD_REQUEST,
+        "invalid_grant",
+        "Grant is invalid or expired!",
+    )
+}
+
+/**
+ * Check whether ID tokens can be issued
+ * @param config The service configuration
+ *
+ * @note An ID token signed with the HS256 secret could only be verified by holding that secret,
+ *       so the openid scope needs the EdDSA key pair
+ */
+fn oauth_openid_supported(config: &Config) -> bool {
+    config.token.algorithm == TokenAlgorithm::EdDSA
+}
+
+/**
+ * Register a client of the single sign-on
+ * @param database The database client
+ * @param config The service configuration
+ * @param register The name and the redirect uris of the client
+ *
+ * @return The client, with its secret for a confidential client
+ */
+pub async fn serv_oauth_client_register(
+    database: &Client,
+    config: &Config,
+    register: RegisterClient,
+) -> Result<RegisteredClient, WebError> {
+    let name = register.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(WebError::new(
+            StatusCode::BAD_REQUEST,
+            "Client name must be 1 to 64 characters long!".to_string(),
+        ));
+    }
+    if register.redirect_uris.is_empty()
+        || !register
+            .redirect_uris
+            .iter()
+            .all(|uri| redirect_uri_valid(uri))
+    {
+        return Err(WebError::new(
+            StatusCode::BAD_REQUEST,
+            "Redirect uris must be https, or http on a loopback address!".to_string(),
+        ));
+    }
+
+    let client_secret = (!register.public).then(token_generator);
+    let client = OAuthClient {
+        _id: Some(bson::oid::ObjectId::new()),
+        client_id: token_generator(),
+        client_secret_digest: client_secret
+            .as_deref()
+            .map(|secret| token_digest(&config.token, secret)),
+        name,
+        redirect_uris: register.redirect_uris,
+        create_time: Utc::now().timestamp(),
+    };
+    serv_oauth_client_database(database)
+        .insert_one(&client, None)
+        .await?;
+
+    Ok(RegisteredClient {
+        info: OAuthClientInfo::from(client),
+        client_secret,
+    })
+}
+
+/**
+ * List the clients of the single sign-on
+ * @param database The database client
+ */
+pub async fn serv_oauth_client_list(database: &Client) -> Result<Vec<OAuthClientInfo>, WebError> {
+    let clients: Vec<OAuthClient> = serv_oauth_client_database(database)
+        .find(
+            None,
+            FindOptions::builder()
+                .sort(doc! {"create_time": -1})
+                .build(),
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(clients.into_iter().map(OAuthClientInfo::from).collect())
+}
+
+/**
+ * Remove a client, its consents, codes and sessions
+ * @param database The database client
+ * @param client_id The id of the client
+ */
+pub async fn serv_oauth_client_remove(
+    database: &Client,
+    client_id: String,
+) -> Result<(), WebError> {
+    let res = serv_oauth_client_database(database)
+        .delete_one(doc! {"client_id": &client_id}, None)
+        .await?;
+    if res.deleted_count == 0 {
+        return Err(WebError::new(
+            StatusCode::NOT_FOUND,
+            "Client not found!".to_string(),
+        ));
+    }
+
+    serv_oauth_consent_database(database)
+        .delete_many(doc! {"client_id": &client_id}, None)
+        .await?;
+    serv_oauth_code_database(database)
+        .delete_many(doc! {"client_id": &client_id}, None)
+        .await?;
+    serv_session_revoke_client(database, &client_id).await
+}
+
+/**
+ * Forget the consents and codes of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_oauth_revoke_all(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    serv_oauth_consent_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    serv_oauth_code_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
+
+/**
+ * Check an authorization request
+ * @param database The database client
+ * @param config The service configuration
+ * @param request The authorization request
+ *
+ * @return The client and the requested scopes
+ *
+ * @note Every client must use PKCE with S256, confidential clients as well
+ */
+async fn oauth_request_check(
+    database: &Client,
+    config: &Config,
+    request: &AuthorizeRequest,
+) -> Result<(OAuthClient, Vec<String>), WebError> {
+    let client = serv_oauth_client_database(database)
+        .find_one(doc! {"client_id": &request.client_id}, None)
+        .await?
+        .ok_or_else(|| {
+            oauth_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "Client is unknown!",
+            )
+        })?;
+    // an unregistered redirect uri is never followed, the error is shown to the user instead
+    if !client.redirect_uris.contains(&request.redirect_uri) {
+        return Err(oauth_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "Redirect uri is not registered!",
+        ));
+    }
+    if request.response_type != "code" {
+        return Err(oauth_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_response_type",
+            "Only the authorization code flow is supported!",
+        ));
+    }
+    let scopes = oauth_scope_parse(&request.scope)
+        .filter(|scopes| !scopes.is_empty())
+        .ok_or_else(|| {
+            oauth_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_scope",
+                "Scope is invalid!",
+            )
+        })?;
+    if scopes.iter().any(|scope| scope == "openid") && !oauth_openid_supported(config) {
+        return Err(oauth_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_scope",
+            "Scope openid is not supported!",
+        ));
+    }
+    if request.code_challenge.is_none() || request.code_challenge_method.as_deref() != Some("S256")
+    {
+        return Err(oauth_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "PKCE with S256 is required!",
+        ));
+    }
+
+    Ok((client, scopes))
+}
+
+/**
+ * Issue an authorization code and send the browser back to the client
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The user who authorizes the client
+ * @param request The authorization request
+ * @param scopes The granted scopes
+ *
+ * @return The redirect uri holding the code
+ */
+async fn oauth_code_issue(
+    database: &Client,
+    config: &Config,
+    auth: &AuthenticatedUser,
+    request: &AuthorizeRequest,
+    scopes: Vec<String>,
+) -> Result<String, WebError> {
+    let now = Utc::now().timestamp();
+    let code = token_generator();
+    serv_oauth_code_database(database)
+        .insert_one(
+            AuthorizationCode {
+                _id: Some(bson::oid::ObjectId::new()),
+                code_digest: token_digest(&config.token, &code),
+                client_id: request.client_id.clone(),
+                user_id: auth.user._id.unwrap_or_default(),
+                redirect_uri: request.redirect_uri.clone(),
+                scopes,
+                code_challenge: request.code_challenge.clone().unwrap_or_default(),
+                nonce: request.nonce.clone(),
+                auth_time: auth.own_session()?.create_time,
+                expire_time: now + config.oauth.code_lifetime,
+            },
+            None,
+        )
+        .await?;
+
+    let mut pairs = vec![("code", code.as_str())];
+    if let Some(state) = &request.state {
+        pairs.push(("state", state.as_str()));
+    }
+    Ok(url_with_query(&request.redirect_uri, &pairs))
+}
+
+/**
+ * Start the authorization-code flow
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The user logged in to mlum, if any
+ * @param request The authorization request
+ *
+ * @return Where to send the browser: back to the client if the scopes were granted before,
+ *         to the consent page of the frontend otherwise
+ */
+pub async fn serv_oauth_authorize(
+    database: &Client,
+    config: &Config,
+    auth: Option<AuthenticatedUser>,
+    request: AuthorizeRequest,
+) -> Result<String, WebError> {
+    let (client, scopes) = oauth_request_check(database, config, &request).await?;
+
+    // only a login to mlum itself can authorize a client, an impersonation can not
+    if let Some(auth) = auth.filter(|auth| auth.own_session().is_ok()) {
+        let consent = serv_oauth_consent_database(database)
+            .find_one(
+                doc! {"user_id": auth.user._id, "client_id": &client.client_id},
+                None,
+            )
+            .await?;
+        let granted = consent
+            .is_some_and(|consent| scopes.iter().all(|scope| consent.scopes.contains(scope)));
+        if granted {
+            return oauth_code_issue(database, config, &auth, &request, scopes).await;
+        }
+    }
+
+    let mut pairs = request.query_pairs();
+    pairs.push(("client_name", client.name.as_str()));
+    Ok(url_with_query(&config.oauth.consent_url, &pairs))
+}
+
+/**
+ * Answer an authorization request on the consent page
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param decision The authorization request and the answer of the user
+ *
+ * @return Where to send the browser back to the client
+ */
+pub async fn serv_oauth_authorize_decide(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    decision: AuthorizeDecision,
+) -> Result<AuthorizeRedirect, WebError> {
+    auth.own_session()?;
+    let request = decision.request;
+    let (client, scopes) = oauth_request_check(database, config, &request).await?;
+
+    if !decision.approve {
+        let mut pairs = vec![("error", "access_denied")];
+        if let Some(state) = &request.state {
+            pairs.push(("state", state.as_str()));
+        }
+        return Ok(AuthorizeRedirect {
+            redirect_to: url_with_query(&request.redirect_uri, &pairs),
+        });
+    }
+
+    serv_oauth_consent_database(database)
+        .update_one(
+            doc! {"user_id": auth.user._id, "client_id": &client.client_id},
+            doc! {
+                "$addToSet": {"scopes": {"$each": &scopes}},
+                "$setOnInsert": {"create_time": Utc::now().timestamp()},
+            },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(AuthorizeRedirect {
+        redirect_to: oauth_code_issue(database, config, &auth, &request, scopes).await?,
+    })
+}
+
+/**
+ * Authenticate the client calling the token endpoint
+ * @param database The database client
+ * @param config The service configuration
+ * @param credentials The client id and secret of the basic authentication
+ * @param request The token request, which may hold the credentials instead
+ *
+ * @note A secret digest stored before the digests were keyed is upgraded by a successful login
+ */
+async fn oauth_client_authenticate(
+    database: &Client,
+    config: &Config,
+    credentials: Option<(String, String)>,
+    request: &OAuthTokenRequest,
+) -> Result<OAuthClient, WebError> {
+    let invalid_client = || {
+        oauth_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "Client authentication failed!",
+        )
+    };
+    let (client_id, client_secret) = match credentials {
+        Some((id, secret)) => (id, Some(secret)),
+        None => (
+            request.client_id.clone().ok_or_else(invalid_client)?,
+            request.client_secret.clone(),
+        ),
+    };
+
+    let client = serv_oauth_client_database(database)
+        .find_one(doc! {"client_id": client_id}, None)
+        .await?
+        .ok_or_else(invalid_client)?;
+    if let Some(digest) = &client.client_secret_digest {
+        let secret = client_secret.ok_or_else(invalid_client)?;
+        if token_digest_verify(&config.token, &secret, digest) {
+            return Ok(client);
+        }
+        if !bool::from(
+            token_digest_legacy(&secret)
+                .as_bytes()
+                .ct_eq(digest.as_bytes()),
+        ) {
+            return Err(invalid_client());
+        }
+        serv_oauth_client_database(database)
+            .update_one(
+                doc! {"_id": client._id, "client_secret_digest": digest},
+                doc! {"$set": {"client_secret_digest": token_digest(&config.token, &secret)}},
+                None,
+            )
+            .await?;
+    }
+    Ok(client)
+}
+
+/**
+ * Get the claims of a user which the grant allows
+ * @param user The user
+ * @param grant The scopes granted to the client
+ */
+fn oauth_user_info(user: &User, grant: &OAuthGrant) -> UserInfo {
+    let mut info = UserInfo {
+        sub: user._id.unwrap_or_default().to_hex(),
+        ..Default::default()
+    };
+    if grant.has_scope("profile") {
+        info.preferred_username = Some(user.username.clone());
+        info.picture = Some(user.avatar.clone()).filter(|avatar| !avatar.is_empty());
+    }
+    if grant.has_scope("email") && !user.email.is_empty() {
+        info.email = Some(user.email.clone());
+        info.email_verified = Some(user.email_verified);
+    }
+    if grant.has_scope("phone") && !user.phone.is_empty() {
+        info.phone_number = Some(user.phone.clone());
+        info.phone_number_verified = Some(user.phone_verified);
+    }
+    info
+}
+
+/**
+ * Exchange a grant for tokens, the token endpoint of the single sign-on
+ * @param database The database client
+ * @param config The service configuration
+ * @param credentials The client id and secret of the basic authentication
+ * @param request The token request
+ * @param client_info The client which calls the endpoint
+ *
+ * @note An authorization code opens a session bound to the client, its refresh token
+ *       is rotated like the one of a login
+ */
+pub async fn serv_oauth_token(
+    database: &Client,
+    config: &Config,
+    credentials: Option<(String, String)>,
+    request: OAuthTokenRequest,
+    client_info: ClientInfo,
+) -> Result<OAuthTokenResponse, WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+    let client = oauth_client_authenticate(database, config, credentials, &request).await?;
+    let find_user = |user_id: bson::oid::ObjectId| {
+        users.find_one(
+            doc! {"_id": user_id, "is_deprecated": false, "is_suspended": {"$ne": true}},
+            None,
+        )
+    };
+
+    match request.grant_type.as_str() {
+        "authorization_code" => {
+            let now = Utc::now().timestamp();
+            let code = request.code.as_deref().ok_or_else(invalid_grant)?;
+            // a code is deleted by its first use, codes issued before the digests were keyed
+            // are still accepted until they expire
+            let code = serv_oauth_code_database(database)
+                .find_one_and_delete(
+                    doc! {
+                        "code_digest": {"$in": [token_digest(&config.token, code), token_digest_legacy(code)]},
+                        "client_id": &client.client_id,
+                    },
+                    None,
+                )
+                .await?
+                .filter(|code| code.expire_time >= now)
+                .ok_or_else(invalid_grant)?;
+            let verifier = request.code_verifier.as_deref().unwrap_or_default();
+            if request.redirect_uri.as_deref() != Some(code.redirect_uri.as_str())
+                || !pkce_verify(verifier, &code.code_challenge)
+            {
+                return Err(invalid_grant());
+            }
+
+            let user = find_user(code.user_id).await?.ok_or_else(invalid_grant)?;
+            let grant = OAuthGrant {
+                client_id: client.client_id.clone(),
+                scopes: code.scopes,
+            };
+            // a client keeps its refresh token like a remembered login
+            let (session, refresh_token) = serv_session_create(
+                database,
+                config,
+                &user,
+                client_info,
+                Some(grant.clone()),
+                true,
+            )
+            .await?;
+            let tokens = serv_user_token_issue(config, &session, refresh_token, &user)?;
+
+            let id_token = if grant.has_scope("openid") && oauth_openid_supported(config) {
+                let claims = IdTokenClaims {
+                    iss: config.oauth.issuer_url.clone(),
+                    aud: client.client_id,
+                    iat: now,
+                    exp: now + config.oauth.id_token_lifetime,
+                    auth_time: code.auth_time,
+                    nonce: code.nonce,
+                    user: oauth_user_info(&user, &grant),
+                };
+                Some(token_sign(&claims, &config.token)?)
+            } else {
+                None
+            };
+            Ok(OAuthTokenResponse {
+                access_token: tokens.access_token,
+                token_type: tokens.token_type,
+                expires_in: tokens.expires_in,
+                refresh_token: tokens.refresh_token,
+                scope: grant.scopes.join(" "),
+                id_token,
+            })
+        }
+        "refresh_token" => {
+            let refresh_token = request.refresh_token.ok_or_else(invalid_grant)?;
+            // the token of another client is refused before it is rotated
+            let (session, refresh_token) = serv_session_rotate(
+                database,
+                config,
+                refresh_token,
+                Some(&client.client_id),
+                client_info,
+            )
+            .await
+            .map_err(|_| invalid_grant())?;
+            let grant = session
+                .grant
+                .clone()
+                .filter(|grant| grant.client_id == client.client_id)
+                .ok_or_else(invalid_grant)?;
+
+            let user = find_user(session.user_id)
+                .await?
+                .ok_or_else(invalid_grant)?;
+            let tokens = serv_user_token_issue(config, &session, refresh_token, &user)?;
+            Ok(OAuthTokenResponse {
+                access_token: tokens.access_token,
+                token_type: tokens.token_type,
+                expires_in: tokens.expires_in,
+                refresh_token: tokens.refresh_token,
+                scope: grant.scopes.join(" "),
+                id_token: None,
+            })
+        }
+        _ => Err(oauth_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "Grant type is not supported!",
+        )),
+    }
+}
+
+/**
+ * Get the claims of the user behind an access token of the single sign-on
+ * @param auth The authenticated user
+ *
+ * @note The token must have been granted the openid scope
+ */
+pub fn serv_oauth_userinfo(auth: AuthenticatedUser) -> Result<UserInfo, WebError> {
+    let grant = match &auth.credential {
+        Credential::Session { session, .. } => session.grant.as_ref(),
+        Credential::PersonalAccessToken(_) => None,
+    };
+    grant
+        .filter(|grant| grant.has_scope("openid"))
+        .map(|grant| oauth_user_info(&auth.user, grant))
+        .ok_or_else(|| {
+            oauth_error(
+                StatusCode::FORBIDDEN,
+                "insufficient_scope",
+                "The token is not allowed to read the user info!",
+            )
+        })
+}
+
+/**
+ * Build the OpenID Connect discovery document
+ * @param config The service configuration
+ */
+pub fn serv_oauth_configuration(config: &Config) -> OpenIdConfiguration {
+    let issuer = config.oauth.issuer_url.trim_end_matches('/');
+    let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+    let (scopes, algorithms): (Vec<&str>, &[&str]) = if oauth_openid_supported(config) {
+        (OAUTH_SCOPES.to_vec(), &["EdDSA"])
+    } else {
+        let scopes = OAUTH_SCOPES.iter().copied();
+        (scopes.filter(|scope| *scope != "openid").collect(), &[])
+    };
+
+    OpenIdConfiguration {
+        issuer: issuer.to_string(),
+        authorization_endpoint: format!("{}/oauth/authorize", issuer),
+        token_endpoint: format!("{}/oauth/token", issuer),
+        userinfo_endpoint: format!("{}/oauth/userinfo", issuer),
+        jwks_uri: format!("{}/oauth/jwks", issuer),
+        scopes_supported: strings(&scopes),
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&["authorization_code", "refresh_token"]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: strings(algorithms),
+        token_endpoint_auth_methods_supported: strings(&[
+            "client_secret_basic",
+            "client_secret_post",
+            "none",
+        ]),
+        code_challenge_methods_supported: strings(&["S256"]),
+        claims_supported: strings(&[
+            "sub",
+            "iss",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "preferred_username",
+            "picture",
+            "email",
+            "email_verified",
+            "phone_number",
+            "phone_number_verified",
+        ]),
+    }
+}
+
+/**
+ * Build the JWKS document holding the public signing key
+ * @param config The service configuration
+ *
+ * @note Tokens signed with HS256 can not be verified by others, the set is empty then
+ */
+pub fn serv_oauth_jwks(config: &Config) -> JwkSet {
+    let keys = token_public_key(&config.token)
+        .zip(token_key_id(&config.token))
+        .map(|(key, kid)| Jwk {
+            kty: "OKP".to_string(),
+            crv: "Ed25519".to_string(),
+            x: BASE64URL_NOPAD.encode(&key),
+            alg: "EdDSA".to_string(),
+            key_use: "sig".to_string(),
+            kid,
+        });
+    JwkSet {
+        keys: keys.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod oauth_service_test {
+    use super::*;
+
+    #[test]
+    fn test_oauth_jwks() {
+        let mut config = Config::default();
+        assert!(serv_oauth_jwks(&config).keys.is_empty());
+        let discovery = serv_oauth_configuration(&config);
+        assert!(discovery.id_token_signing_alg_values_supported.is_empty());
+        assert!(!discovery.scopes_supported.contains(&"openid".to_string()));
+
+        config.token.algorithm = TokenAlgorithm::EdDSA;
+        config.token.public_key = "-----BEGIN PUBLIC KEY-----\n\
+            MCowBQYDK2VwAyEAb7GdwNgWgmie4Q13rQNxfNdlYGLU6/SUw7HYnPhLqm8=\n\
+            -----END PUBLIC KEY-----\n"
+            .to_string();
+        let jwks = serv_oauth_jwks(&config);
+        assert_eq!(jwks.keys.len(), 1);
+        assert_eq!(
+            jwks.keys[0].x,
+            "b7GdwNgWgmie4Q13rQNxfNdlYGLU6_SUw7HYnPhLqm8"
+        );
+        assert_eq!(
+            serv_oauth_configuration(&config).id_token_signing_alg_values_supported,
+            vec!["EdDSA".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_oauth_user_info() {
+        let mut user = User::from(crate::models::users::CreateUser {
+            username: "dessera".to_string(),
+            password: String::new(),
+            phone: "+8613800138000".to_string(),
+            email: "dessera@mlum.com".to_string(),
+        });
+        user.email_verified = true;
+        let grant = OAuthGrant {
+            client_id: "forum".to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+        };
+        let info = oauth_user_info(&user, &grant);
+        assert_eq!(info.email.as_deref(), Some("dessera@mlum.com"));
+        assert_eq!(info.email_verified, Some(true));
+        assert!(info.preferred_username.is_none());
+        assert!(info.phone_number.is_none());
+    }
+}
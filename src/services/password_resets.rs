@@ -0,0 +1,143 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use mongodb::{bson::doc, options::IndexOptions, Client, IndexModel};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    models::password_resets::PasswordReset,
+    utils::token::{token_digest, token_generator},
+};
+
+/**
+ * Get the password reset collection from the database
+ * @param database The database client
+ */
+pub fn serv_password_reset_database(database: &Client) -> mongodb::Collection<PasswordReset> {
+    database.database("test").collection("password_resets")
+}
+
+/**
+ * Create the indexes of the password reset collection
+ * @param database The database client
+ */
+pub async fn serv_password_reset_indexes(database: &Client) -> Result<(), WebError> {
+    serv_password_reset_database(database)
+        .create_indexes(
+            vec![
+                IndexModel::builder()
+                    .keys(doc! {"token_digest": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder().keys(doc! {"user_id": 1}).build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Issue a reset token for a user
+ * @param database The database client
+ * @param config The service configuration
+ * @param user_id The id of the user
+ *
+ * @return The reset token, only its digest is stored
+ */
+pub async fn serv_password_reset_create(
+    database: &Client,
+    config: &Config,
+    user_id: bson::oid::ObjectId,
+) -> Result<String, WebError> {
+    let now = Utc::now().timestamp();
+    let token = token_generator();
+    let reset = PasswordReset {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id,
+        token_digest: token_digest(&config.token, &token),
+        create_time: now,
+        expire_time: now + config.password_reset.token_lifetime,
+    };
+
+    serv_password_reset_database(database)
+        .insert_one(&reset, None)
+        .await?;
+
+    Ok(token)
+}
+
+/**
+ * Find the user of a reset token without consuming it
+ * @param database The database client
+ * @param config The service configuration
+ * @param token The reset token
+ *
+ * @return The id of the user who requested the reset
+ */
+pub async fn serv_password_reset_find(
+    database: &Client,
+    config: &Config,
+    token: &str,
+) -> Result<bson::oid::ObjectId, WebError> {
+    let reset = serv_password_reset_database(database)
+        .find_one(
+            doc! {"token_digest": token_digest(&config.token, token)},
+            None,
+        )
+        .await?
+        .filter(|reset| reset.expire_time >= Utc::now().timestamp())
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Reset token is invalid or expired!".to_string(),
+            )
+        })?;
+
+    Ok(reset.user_id)
+}
+
+/**
+ * Consume a reset token, it can not be used again
+ * @param database The database client
+ * @param config The service configuration
+ * @param token The reset token
+ *
+ * @return The id of the user who requested the reset
+ */
+pub async fn serv_password_reset_consume(
+    database: &Client,
+    config: &Config,
+    token: String,
+) -> Result<bson::oid::ObjectId, WebError> {
+    let reset = serv_password_reset_database(database)
+        .find_one_and_delete(
+            doc! {"token_digest": token_digest(&config.token, &token)},
+            None,
+        )
+        .await?
+        .filter(|reset| reset.expire_time >= Utc::now().timestamp())
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Reset token is invalid or expired!".to_string(),
+            )
+        })?;
+
+    Ok(reset.user_id)
+}
+
+/**
+ * Drop every reset token of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_password_reset_revoke_all(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    serv_password_reset_database(database)
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use mongodb::{
+    bson::doc,
+    options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument},
+    Client, IndexModel,
+};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    models::phone_codes::{PhoneCode, PhoneCodePurpose},
+    sms::{Sms, SmsSender},
+    utils::token::{code_generator, token_digest, token_digest_verify},
+};
+
+/**
+ * Get the phone code collection from the database
+ * @param database The database client
+ */
+pub fn serv_phone_code_database(database: &Client) -> mongodb::Collection<PhoneCode> {
+    database.database("test").collection("phone_codes")
+}
+
+/**
+ * Create the indexes of the phone code collection
+ * @param database The database client
+ */
+pub async fn serv_phone_code_indexes(database: &Client) -> Result<(), WebError> {
+    serv_phone_code_database(database)
+        .create_indexes(
+            vec![IndexModel::builder()
+                .keys(doc! {"phone": 1, "create_time": -1})
+                .build()],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+fn invalid_code() -> WebError {
+    WebError::new(
+        StatusCode::BAD_REQUEST,
+        "Verification code is invalid or expired!".to_string(),
+    )
+}
+
+/**
+ * Send a one-time code to a phone number
+ * @param database The database client
+ * @param config The service configuration
+ * @param sender The SMS sender
+ * @param phone The phone number
+ * @param purpose What the code can be used for
+ *
+ * @note Sending is throttled per number, a new code replaces the previous one of the same purpose
+ */
+pub async fn serv_phone_code_send(
+    database: &Client,
+    config: &Config,
+    sender: &dyn SmsSender,
+    phone: &str,
+    purpose: PhoneCodePurpose,
+) -> Result<(), WebError> {
+    let codes = serv_phone_code_database(database);
+    let now = Utc::now().timestamp();
+
+    let last = codes
+        .find_one(
+            doc! {"phone": phone},
+            FindOneOptions::builder()
+                .sort(doc! {"create_time": -1})
+                .build(),
+        )
+        .await?;
+    if let Some(last) = last {
+        let next = last.create_time + config.phone_code.resend_interval;
+        if next > now {
+            return Err(WebError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Code has been sent recently, try again later!".to_string(),
+            )
+            .with_retry_after(next - now));
+        }
+    }
+
+    let sent = codes
+        .count_documents(
+            doc! {"phone": phone, "create_time": {"$gt": now - 3600}},
+            None,
+        )
+        .await?;
+    if sent >= config.phone_code.max_sends_per_hour {
+        return Err(WebError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many codes sent to this number, try again later!".to_string(),
+        )
+        .with_retry_after(3600));
+    }
+
+    codes
+        .update_many(
+            doc! {"phone": phone, "purpose": purpose, "used": false},
+            doc! {"$set": {"used": true}},
+            None,
+        )
+        .await?;
+
+    let sent_code = code_generator();
+    let code = PhoneCode {
+        _id: Some(bson::oid::ObjectId::new()),
+        phone: phone.to_string(),
+        purpose,
+        code_digest: token_digest(&config.token, &sent_code),
+        attempts: 0,
+        used: false,
+        create_time: now,
+        expire_time: now + config.phone_code.code_lifetime,
+    };
+    codes.insert_one(&code, None).await?;
+
+    sender
+        .send(Sms {
+            to: code.phone,
+            text: format!(
+                "[mlum] Your code is {}, valid for {} minutes. Do not share it with anyone.",
+                sent_code,
+                config.phone_code.code_lifetime / 60
+            ),
+        })
+        .await
+}
+
+/**
+ * Consume a one-time code
+ * @param database The database client
+ * @param config The service configuration
+ * @param phone The phone number
+ * @param purpose What the code is used for
+ * @param code The code typed by the user
+ *
+ * @note Every attempt is counted before the code is compared, so parallel guesses
+ *       can not exceed the limit, a code is dropped after too many attempts
+ */
+pub async fn serv_phone_code_consume(
+    database: &Client,
+    config: &Config,
+    phone: &str,
+    purpose: PhoneCodePurpose,
+    code: &str,
+) -> Result<(), WebError> {
+    let codes = serv_phone_code_database(database);
+
+    let stored = codes
+        .find_one_and_update(
+            doc! {
+                "phone": phone,
+                "purpose": purpose,
+                "used": false,
+                "attempts": {"$lt": config.phone_code.max_attempts},
+                "expire_time": {"$gte": Utc::now().timestamp()},
+            },
+            doc! {"$inc": {"attempts": 1}},
+            FindOneAndUpdateOptions::builder()
+                .sort(doc! {"create_time": -1})
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .await?
+        .ok_or_else(invalid_code)?;
+
+    if !token_digest_verify(&config.token, code, &stored.code_digest) {
+        return Err(invalid_code());
+    }
+
+    // only one of parallel requests with the right code consumes it
+    let res = codes
+        .update_one(
+            doc! {"_id": stored._id, "used": false},
+            doc! {"$set": {"used": true}},
+            None,
+        )
+        .await?;
+    if res.modified_count == 0 {
+        return Err(invalid_code());
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use chrono::Utc;
+use mongodb::Client;
+
+use crate::{
+    errors::WebError,
+    models::{
+        security_events::{SecurityEvent, SecurityEventKind},
+        sessions::{ClientInfo, Session},
+    },
+};
+
+/**
+ * Get the security event collection from the database
+ * @param database The database client
+ */
+pub fn serv_security_event_database(database: &Client) -> mongodb::Collection<SecurityEvent> {
+    database.database("test").collection("security_events")
+}
+
+/**
+ * Record a security event about a session
+ * @param database The database client
+ * @param session The session concerned by the event
+ * @param kind The kind of the event
+ * @param detail A human readable description
+ * @param client The client which triggered the event
+ */
+pub async fn serv_security_event_record(
+    database: &Client,
+    session: &Session,
+    kind: SecurityEventKind,
+    detail: String,
+    client: ClientInfo,
+) -> Result<(), WebError> {
+    let events = serv_security_event_database(database);
+    let event = SecurityEvent {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id: session.user_id,
+        username: session.username.clone(),
+        kind,
+        detail,
+        user_agent: client.user_agent,
+        ip: client.ip,
+        time: Utc::now().timestamp(),
+    };
+
+    events.insert_one(event, None).await?;
+    Ok(())
+}
@@ -0,0 +1,466 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument},
+    Client, IndexModel,
+};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    models::{
+        oauth::OAuthGrant,
+        security_events::SecurityEventKind,
+        sessions::{ClientInfo, Session},
+        users::User,
+    },
+    services::security_events::serv_security_event_record,
+    utils::token::{token_digest, token_generator},
+};
+
+// seconds between two writes of the last use of a session
+const SESSION_TOUCH_INTERVAL: i64 = 60;
+
+/**
+ * Get the session collection from the database
+ * @param database The database client
+ */
+pub fn serv_session_database(database: &Client) -> mongodb::Collection<Session> {
+    database.database("test").collection("sessions")
+}
+
+/**
+ * Create the indexes of the session collection
+ * @param database The database client
+ */
+pub async fn serv_session_indexes(database: &Client) -> Result<(), WebError> {
+    let sessions = serv_session_database(database);
+    sessions
+        .create_indexes(
+            vec![
+                IndexModel::builder()
+                    .keys(doc! {"refresh_token_digest": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! {"used_refresh_token_digests": 1})
+                    .build(),
+                IndexModel::builder().keys(doc! {"user_id": 1}).build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Open a new session for the user
+ * @param database The database client
+ * @param config The service configuration
+ * @param user The user who logs in
+ * @param client The client which opens the session
+ * @param grant The OAuth client and scopes, none for a login to mlum itself
+ * @param remember_me Whether the session gets the longer timeouts
+ *
+ * @return The new session and its refresh token, only its digest is stored
+ */
+pub async fn serv_session_create(
+    database: &Client,
+    config: &Config,
+    user: &User,
+    client: ClientInfo,
+    grant: Option<OAuthGrant>,
+    remember_me: bool,
+) -> Result<(Session, String), WebError> {
+    let sessions = serv_session_database(database);
+    let now = Utc::now().timestamp();
+    let refresh_token = token_generator();
+    let (idle_timeout, absolute_timeout) = config.session.timeouts(remember_me);
+    let session = Session {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id: user._id.unwrap_or_default(),
+        username: user.username.clone(),
+        refresh_token_digest: token_digest(&config.token, &refresh_token),
+        used_refresh_token_digests: vec![],
+        user_agent: client.user_agent,
+        ip: client.ip,
+        grant,
+        create_time: now,
+        expire_time: now + idle_timeout.min(absolute_timeout),
+        absolute_expire_time: Some(now + absolute_timeout),
+        remember_me,
+        last_used_time: None,
+        impersonator: None,
+        impersonator_id: None,
+    };
+
+    sessions.insert_one(&session, None).await?;
+
+    Ok((session, refresh_token))
+}
+
+/**
+ * Open a session of a user for an admin impersonating them
+ * @param database The database client
+ * @param config The service configuration
+ * @param admin The admin who impersonates
+ * @param user The impersonated user
+ * @param client The client of the admin
+ *
+ * @return The new session, it ends after the impersonation lifetime and can not be refreshed
+ */
+pub async fn serv_session_impersonate(
+    database: &Client,
+    config: &Config,
+    admin: &User,
+    user: &User,
+    client: ClientInfo,
+) -> Result<Session, WebError> {
+    let now = Utc::now().timestamp();
+    let expire_time = now + config.admin.impersonation_lifetime;
+    let session = Session {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id: user._id.unwrap_or_default(),
+        username: user.username.clone(),
+        // the refresh token is never handed out
+        refresh_token_digest: token_digest(&config.token, &token_generator()),
+        used_refresh_token_digests: vec![],
+        user_agent: client.user_agent,
+        ip: client.ip,
+        grant: None,
+        create_time: now,
+        expire_time,
+        absolute_expire_time: Some(expire_time),
+        remember_me: false,
+        last_used_time: None,
+        impersonator: Some(admin.username.clone()),
+        impersonator_id: admin._id,
+    };
+
+    serv_session_database(database)
+        .insert_one(&session, None)
+        .await?;
+
+    Ok(session)
+}
+
+/**
+ * Check that a session is still valid, expired sessions are removed
+ * @param database The database client
+ * @param session The session found in the database
+ */
+async fn serv_session_check(
+    database: &Client,
+    session: Option<Session>,
+) -> Result<Session, WebError> {
+    let session = session.ok_or_else(|| {
+        WebError::new(
+            StatusCode::UNAUTHORIZED,
+            "You need to login first!".to_string(),
+        )
+    })?;
+
+    if session.expire_time < Utc::now().timestamp() {
+        serv_session_database(database)
+            .delete_one(doc! {"_id": session._id}, None)
+            .await?;
+        return Err(WebError::new(
+            StatusCode::UNAUTHORIZED,
+            "Token expired!".to_string(),
+        ));
+    }
+
+    Ok(session)
+}
+
+/**
+ * Find a session by its id
+ * @param database The database client
+ * @param session_id The id of the session, as carried by the access token
+ *
+ * @return The session, expired sessions are removed and rejected
+ */
+pub async fn serv_session_find(database: &Client, session_id: &str) -> Result<Session, WebError> {
+    let session_id = bson::oid::ObjectId::parse_str(session_id)
+        .map_err(|_| WebError::new(StatusCode::UNAUTHORIZED, "Token error!".to_string()))?;
+    let session = serv_session_database(database)
+        .find_one(doc! {"_id": session_id}, None)
+        .await?;
+    serv_session_check(database, session).await
+}
+
+/**
+ * Compute the expiry of a session used now
+ * @param config The service configuration
+ * @param session The session
+ * @param now The current time
+ *
+ * @return The end of the idle timeout, capped by the absolute expiry, never earlier than before
+ */
+pub fn session_expire_time(config: &Config, session: &Session, now: i64) -> i64 {
+    let (idle_timeout, _) = config.session.timeouts(session.remember_me);
+    let absolute = session.absolute_expire_time.unwrap_or(session.expire_time);
+    (now + idle_timeout).min(absolute).max(session.expire_time)
+}
+
+/**
+ * Record the use of a session and slide its expiry forward
+ * @param database The database client
+ * @param config The service configuration
+ * @param session The session used by a request
+ *
+ * @return The session with its new expiry
+ *
+ * @note The session is only written once a minute, not on every request
+ */
+pub async fn serv_session_touch(
+    database: &Client,
+    config: &Config,
+    mut session: Session,
+) -> Result<Session, WebError> {
+    let now = Utc::now().timestamp();
+    if session
+        .last_used_time
+        .is_some_and(|used| now - used < SESSION_TOUCH_INTERVAL)
+    {
+        return Ok(session);
+    }
+
+    session.last_used_time = Some(now);
+    session.expire_time = session_expire_time(config, &session, now);
+    serv_session_database(database)
+        .update_one(
+            doc! {"_id": session._id},
+            doc! {"$set": {"last_used_time": now, "expire_time": session.expire_time}},
+            None,
+        )
+        .await?;
+    Ok(session)
+}
+
+/**
+ * List the open sessions of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ *
+ * @return The sessions, the most recently used first
+ */
+pub async fn serv_session_list(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<Vec<Session>, WebError> {
+    let mut sessions: Vec<Session> = serv_session_database(database)
+        .find(
+            doc! {"user_id": user_id, "expire_time": {"$gte": Utc::now().timestamp()}},
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    sessions.sort_by_key(|session| {
+        std::cmp::Reverse(session.last_used_time.unwrap_or(session.create_time))
+    });
+    Ok(sessions)
+}
+
+/**
+ * Rotate the refresh token of a session
+ * @param database The database client
+ * @param config The service configuration
+ * @param refresh_token The refresh token presented by the client
+ * @param client_id The single sign-on client the session must be granted to, if any
+ * @param client The client which refreshes
+ *
+ * @return The session and its new refresh token
+ *
+ * @note Every refresh token can be used once. If a rotated token is presented again,
+ *       the whole session is revoked and a security event is recorded.
+ *       A refresh is a use of the session, its expiry slides forward.
+ *       The token of a session granted to another client is not rotated
+ */
+pub async fn serv_session_rotate(
+    database: &Client,
+    config: &Config,
+    refresh_token: String,
+    client_id: Option<&str>,
+    client: ClientInfo,
+) -> Result<(Session, String), WebError> {
+    let sessions = serv_session_database(database);
+    let digest = token_digest(&config.token, &refresh_token);
+
+    // swap the token atomically, so two concurrent refreshes can not both succeed
+    let now = Utc::now().timestamp();
+    let next_token = token_generator();
+    let mut filter = doc! {"refresh_token_digest": &digest, "expire_time": {"$gte": now}};
+    if let Some(client_id) = client_id {
+        filter.insert("grant.client_id", client_id);
+    }
+    let rotated = sessions
+        .find_one_and_update(
+            filter,
+            doc! {
+                "$set": {
+                    "refresh_token_digest": token_digest(&config.token, &next_token),
+                    "user_agent": client.user_agent.clone(),
+                    "ip": client.ip.clone(),
+                },
+                "$push": {"used_refresh_token_digests": &digest},
+            },
+            FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .await?;
+    if let Some(session) = rotated {
+        return Ok((
+            serv_session_touch(database, config, session).await?,
+            next_token,
+        ));
+    }
+
+    // the token is either expired, reused or unknown
+    let session = sessions
+        .find_one(doc! {"refresh_token_digest": &digest}, None)
+        .await?;
+    if session.is_some() {
+        let session = serv_session_check(database, session).await?;
+        return Ok((session, refresh_token));
+    }
+
+    let reused = sessions
+        .find_one(doc! {"used_refresh_token_digests": &digest}, None)
+        .await?;
+    if let Some(session) = reused {
+        serv_session_revoke(database, session._id.unwrap_or_default()).await?;
+        serv_security_event_record(
+            database,
+            &session,
+            SecurityEventKind::RefreshTokenReuse,
+            "A rotated refresh token was used again, the session has been revoked".to_string(),
+            client,
+        )
+        .await?;
+        return Err(WebError::new(
+            StatusCode::UNAUTHORIZED,
+            "Token has been revoked!".to_string(),
+        ));
+    }
+
+    Err(WebError::new(
+        StatusCode::UNAUTHORIZED,
+        "You need to login first!".to_string(),
+    ))
+}
+
+/**
+ * Close a session
+ * @param database The database client
+ * @param session_id The id of the session
+ */
+pub async fn serv_session_revoke(
+    database: &Client,
+    session_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    let sessions = serv_session_database(database);
+    let res = sessions.delete_one(doc! {"_id": session_id}, None).await?;
+    if res.deleted_count == 0 {
+        return Err(WebError::new(
+            StatusCode::UNAUTHORIZED,
+            "Token expired!".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/**
+ * Close a session of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ * @param session_id The id of the session
+ */
+pub async fn serv_session_revoke_own(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+    session_id: &str,
+) -> Result<(), WebError> {
+    let not_found = || WebError::new(StatusCode::NOT_FOUND, "Session not found!".to_string());
+    let session_id = bson::oid::ObjectId::parse_str(session_id).map_err(|_| not_found())?;
+    let res = serv_session_database(database)
+        .delete_one(doc! {"_id": session_id, "user_id": user_id}, None)
+        .await?;
+    if res.deleted_count == 0 {
+        return Err(not_found());
+    }
+    Ok(())
+}
+
+/**
+ * Close every session of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_session_revoke_all(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    let sessions = serv_session_database(database);
+    sessions
+        .delete_many(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
+
+/**
+ * Close every session of a user but one
+ * @param database The database client
+ * @param user_id The id of the user
+ * @param keep The id of the session which stays open
+ */
+pub async fn serv_session_revoke_others(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+    keep: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    let sessions = serv_session_database(database);
+    sessions
+        .delete_many(doc! {"user_id": user_id, "_id": {"$ne": keep}}, None)
+        .await?;
+    Ok(())
+}
+
+/**
+ * Close every session opened by the single sign-on of a client
+ * @param database The database client
+ * @param client_id The id of the OAuth client
+ */
+pub async fn serv_session_revoke_client(
+    database: &Client,
+    client_id: &str,
+) -> Result<(), WebError> {
+    let sessions = serv_session_database(database);
+    sessions
+        .delete_many(doc! {"grant.client_id": client_id}, None)
+        .await?;
+    Ok(())
+}
+
+/**
+ * Count the open sessions of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_session_count(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<u64, WebError> {
+    let sessions = serv_session_database(database);
+    let count = sessions
+        .count_documents(
+            doc! {"user_id": user_id, "expire_time": {"$gte": Utc::now().timestamp()}},
+            None,
+        )
+        .await?;
+    Ok(count)
+}
@@ -0,0 +1,121 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Client,
+};
+
+use crate::{config::Config, errors::WebError, utils::token::token_digest};
+
+/**
+ * Tokens stored raw by earlier versions, by collection: (raw field, digest field)
+ *
+ * @note The first field is present in every document still holding raw tokens
+ */
+const RAW_TOKENS: [(&str, &[(&str, &str)]); 6] = [
+    (
+        "sessions",
+        &[
+            ("refresh_token", "refresh_token_digest"),
+            ("used_refresh_tokens", "used_refresh_token_digests"),
+        ],
+    ),
+    ("login_challenges", &[("token", "token_digest")]),
+    ("password_resets", &[("token", "token_digest")]),
+    ("email_verifications", &[("code", "code_digest")]),
+    ("phone_codes", &[("code", "code_digest")]),
+    ("external_login_states", &[("state", "state_digest")]),
+];
+
+/**
+ * Digest a raw token field, a string or a list of strings
+ * @param config The service configuration
+ * @param value The raw value
+ */
+fn raw_token_digest(config: &Config, value: &Bson) -> Option<Bson> {
+    match value {
+        Bson::String(token) => Some(Bson::String(token_digest(&config.token, token))),
+        Bson::Array(tokens) => Some(Bson::Array(
+            tokens
+                .iter()
+                .filter_map(|token| raw_token_digest(config, token))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/**
+ * Replace the tokens stored raw by earlier versions with their keyed digests
+ * @param database The database client
+ * @param config The service configuration
+ *
+ * @return The number of documents migrated
+ *
+ * @note Runs before the indexes are created, the indexes of the raw fields are dropped.
+ *       The clients keep their tokens, so nobody has to log in again,
+ *       except for the token of the first versions stored in the user itself
+ */
+pub async fn serv_token_digest_migrate(
+    database: &Client,
+    config: &Config,
+) -> Result<u64, WebError> {
+    let db = database.database("test");
+    let existing = db.list_collection_names(None).await?;
+    let mut migrated = 0;
+
+    for (name, fields) in RAW_TOKENS {
+        if !existing.iter().any(|collection| collection == name) {
+            continue;
+        }
+        let collection = db.collection::<Document>(name);
+
+        let indexes = collection.list_index_names().await?;
+        for (raw, _) in fields {
+            let index = format!("{}_1", raw);
+            if indexes.contains(&index) {
+                collection.drop_index(index, None).await?;
+            }
+        }
+
+        let mut documents = collection
+            .find(doc! {fields[0].0: {"$exists": true}}, None)
+            .await?;
+        while let Some(document) = documents.try_next().await? {
+            let mut set = Document::new();
+            let mut unset = Document::new();
+            for (raw, digest) in fields {
+                if let Some(value) = document.get(raw) {
+                    if let Some(value) = raw_token_digest(config, value) {
+                        set.insert(*digest, value);
+                    }
+                    unset.insert(*raw, "");
+                }
+            }
+            collection
+                .update_one(
+                    doc! {"_id": document.get("_id")},
+                    doc! {"$set": set, "$unset": unset},
+                    None,
+                )
+                .await?;
+            migrated += 1;
+        }
+    }
+
+    // the single raw token of a user was replaced by the sessions, it is dropped without a digest
+    if existing.iter().any(|collection| collection == "users") {
+        let res = db
+            .collection::<Document>("users")
+            .update_many(
+                doc! {"$or": [
+                    {"token": {"$exists": true}},
+                    {"valid_token_time": {"$exists": true}},
+                ]},
+                doc! {"$unset": {"token": "", "valid_token_time": ""}},
+                None,
+            )
+            .await?;
+        migrated += res.modified_count;
+    }
+    Ok(migrated)
+}
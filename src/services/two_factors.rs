@@ -0,0 +1,328 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{IndexOptions, ReplaceOptions},
+    Client, IndexModel,
+};
+
+use crate::{
+    config::Config,
+    errors::WebError,
+    models::{
+        two_factors::{RecoveryCodes, TwoFactor, TwoFactorSetup},
+        users::User,
+    },
+    utils::{
+        password::{password_hash, password_verify},
+        token::recovery_code_generator,
+        totp::{
+            totp_secret_decrypt, totp_secret_encrypt, totp_secret_generate,
+            totp_secret_is_encrypted, totp_uri, totp_verify,
+        },
+    },
+};
+
+/**
+ * Get the two-factor collection from the database
+ * @param database The database client
+ */
+pub fn serv_two_factor_database(database: &Client) -> mongodb::Collection<TwoFactor> {
+    database.database("test").collection("two_factors")
+}
+
+/**
+ * Create the indexes of the two-factor collection
+ * @param database The database client
+ */
+pub async fn serv_two_factor_indexes(database: &Client) -> Result<(), WebError> {
+    serv_two_factor_database(database)
+        .create_indexes(
+            vec![IndexModel::builder()
+                .keys(doc! {"user_id": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build()],
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/**
+ * Encrypt the TOTP secrets stored in plaintext by earlier versions
+ * @param database The database client
+ * @param config The service configuration
+ *
+ * @return The number of secrets encrypted
+ */
+pub async fn serv_two_factor_secret_migrate(
+    database: &Client,
+    config: &Config,
+) -> Result<u64, WebError> {
+    let two_factors = serv_two_factor_database(database);
+    let mut stored = two_factors.find(None, None).await?;
+    let mut migrated = 0;
+    while let Some(two_factor) = stored.try_next().await? {
+        if totp_secret_is_encrypted(&two_factor.secret) {
+            continue;
+        }
+        two_factors
+            .update_one(
+                doc! {"_id": two_factor._id},
+                doc! {"$set": {"secret": totp_secret_encrypt(&config.two_factor.secret_key, &two_factor.secret)?}},
+                None,
+            )
+            .await?;
+        migrated += 1;
+    }
+    Ok(migrated)
+}
+
+fn code_error() -> WebError {
+    WebError::new(
+        StatusCode::UNAUTHORIZED,
+        "Two-factor code error!".to_string(),
+    )
+}
+
+/**
+ * Check whether a user has enabled the second factor
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_two_factor_enabled(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<bool, WebError> {
+    let two_factor = serv_two_factor_database(database)
+        .find_one(doc! {"user_id": user_id, "enabled": true}, None)
+        .await?;
+    Ok(two_factor.is_some())
+}
+
+/**
+ * Issue a new set of recovery codes
+ * @param config The service configuration
+ *
+ * @return The plaintext codes and their hashes
+ */
+fn recovery_codes_issue(config: &Config) -> Result<(Vec<String>, Vec<String>), WebError> {
+    let codes: Vec<String> = (0..config.two_factor.recovery_codes)
+        .map(|_| recovery_code_generator())
+        .collect();
+    let hashes = codes
+        .iter()
+        .map(|code| password_hash(code, &config.password_hash))
+        .collect::<Result<Vec<String>, WebError>>()?;
+    Ok((codes, hashes))
+}
+
+/**
+ * Start the enrollment of the second factor
+ * @param database The database client
+ * @param config The service configuration
+ * @param user The user who enrolls
+ *
+ * @return The secret and its otpauth URI
+ *
+ * @note A pending enrollment is replaced, it is only enabled by `serv_two_factor_enable`
+ */
+pub async fn serv_two_factor_setup(
+    database: &Client,
+    config: &Config,
+    user: &User,
+) -> Result<TwoFactorSetup, WebError> {
+    let user_id = user._id.unwrap_or_default();
+    if serv_two_factor_enabled(database, user_id).await? {
+        return Err(WebError::new(
+            StatusCode::BAD_REQUEST,
+            "Two-factor authentication is already enabled!".to_string(),
+        ));
+    }
+
+    let secret = totp_secret_generate();
+    let two_factor = TwoFactor {
+        _id: Some(bson::oid::ObjectId::new()),
+        user_id,
+        secret: totp_secret_encrypt(&config.two_factor.secret_key, &secret)?,
+        enabled: false,
+        recovery_codes: vec![],
+        last_used_step: 0,
+        create_time: Utc::now().timestamp(),
+    };
+    serv_two_factor_database(database)
+        .replace_one(
+            doc! {"user_id": user_id},
+            &two_factor,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(TwoFactorSetup {
+        otpauth_uri: totp_uri(&config.two_factor.issuer, &user.username, &secret),
+        secret,
+    })
+}
+
+/**
+ * Confirm the enrollment with a first code
+ * @param database The database client
+ * @param config The service configuration
+ * @param user_id The id of the user
+ * @param code The TOTP code
+ *
+ * @return The recovery codes, only shown once
+ */
+pub async fn serv_two_factor_enable(
+    database: &Client,
+    config: &Config,
+    user_id: bson::oid::ObjectId,
+    code: &str,
+) -> Result<RecoveryCodes, WebError> {
+    let two_factors = serv_two_factor_database(database);
+    let two_factor = two_factors
+        .find_one(doc! {"user_id": user_id, "enabled": false}, None)
+        .await?
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Two-factor authentication is not set up!".to_string(),
+            )
+        })?;
+
+    let step = totp_verify(
+        &totp_secret_decrypt(&config.two_factor.secret_key, &two_factor.secret)?,
+        code,
+        Utc::now().timestamp(),
+        config.two_factor.skew,
+    )
+    .ok_or_else(code_error)?;
+
+    let (codes, hashes) = recovery_codes_issue(config)?;
+    two_factors
+        .update_one(
+            doc! {"_id": two_factor._id},
+            doc! {"$set": {"enabled": true, "recovery_codes": hashes, "last_used_step": step}},
+            None,
+        )
+        .await?;
+
+    Ok(RecoveryCodes {
+        recovery_codes: codes,
+    })
+}
+
+/**
+ * Check a code of the second factor
+ * @param database The database client
+ * @param config The service configuration
+ * @param user_id The id of the user
+ * @param code The TOTP code or a recovery code
+ * @param allow_recovery Whether a recovery code is accepted
+ *
+ * @note A TOTP code is accepted once, a recovery code is removed once used
+ */
+pub async fn serv_two_factor_verify(
+    database: &Client,
+    config: &Config,
+    user_id: bson::oid::ObjectId,
+    code: &str,
+    allow_recovery: bool,
+) -> Result<(), WebError> {
+    let two_factors = serv_two_factor_database(database);
+    let two_factor = two_factors
+        .find_one(doc! {"user_id": user_id, "enabled": true}, None)
+        .await?
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Two-factor authentication is not enabled!".to_string(),
+            )
+        })?;
+
+    let code = code.trim();
+    let totp = code.replace(' ', "");
+    if let Some(step) = totp_verify(
+        &totp_secret_decrypt(&config.two_factor.secret_key, &two_factor.secret)?,
+        &totp,
+        Utc::now().timestamp(),
+        config.two_factor.skew,
+    ) {
+        // the filter makes a replayed code fail, even with concurrent requests
+        let res = two_factors
+            .update_one(
+                doc! {"_id": two_factor._id, "last_used_step": {"$lt": step}},
+                doc! {"$set": {"last_used_step": step}},
+                None,
+            )
+            .await?;
+        if res.modified_count == 0 {
+            return Err(code_error());
+        }
+        return Ok(());
+    }
+
+    if !allow_recovery {
+        return Err(code_error());
+    }
+    let code = code.to_lowercase();
+    let used = two_factor
+        .recovery_codes
+        .iter()
+        .find(|hash| password_verify(&code, hash))
+        .ok_or_else(code_error)?;
+    let res = two_factors
+        .update_one(
+            doc! {"_id": two_factor._id},
+            doc! {"$pull": {"recovery_codes": used}},
+            None,
+        )
+        .await?;
+    if res.modified_count == 0 {
+        return Err(code_error());
+    }
+    Ok(())
+}
+
+/**
+ * Replace the recovery codes of a user
+ * @param database The database client
+ * @param config The service configuration
+ * @param user_id The id of the user
+ *
+ * @return The new recovery codes, the previous ones stop working
+ */
+pub async fn serv_two_factor_recovery_regenerate(
+    database: &Client,
+    config: &Config,
+    user_id: bson::oid::ObjectId,
+) -> Result<RecoveryCodes, WebError> {
+    let (codes, hashes) = recovery_codes_issue(config)?;
+    serv_two_factor_database(database)
+        .update_one(
+            doc! {"user_id": user_id, "enabled": true},
+            doc! {"$set": {"recovery_codes": hashes}},
+            None,
+        )
+        .await?;
+
+    Ok(RecoveryCodes {
+        recovery_codes: codes,
+    })
+}
+
+/**
+ * Remove the second factor of a user
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+pub async fn serv_two_factor_disable(
+    database: &Client,
+    user_id: bson::oid::ObjectId,
+) -> Result<(), WebError> {
+    serv_two_factor_database(database)
+        .delete_one(doc! {"user_id": user_id}, None)
+        .await?;
+    Ok(())
+}
@@ -1,11 +1,91 @@
-use actix_web::http::StatusCode;
+use actix_web::{http::StatusCode, ResponseError};
 use chrono::Utc;
-use mongodb::{bson::doc, Client};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::{
+        FindOneAndDeleteOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions,
+        IndexOptions, ReturnDocument,
+    },
+    Client, IndexModel,
+};
 
 use crate::{
+    config::{Config, DeletionMode},
     errors::WebError,
-    models::users::{CertificateUser, CreateUser, User},
-    utils::token::token_generator,
+    identity_providers::{ExternalIdentity, IdentityProviders},
+    mailers::{Mail, Mailer},
+    models::{
+        access_tokens::{AccessTokenInfo, CreateAccessToken, CreatedAccessToken},
+        audit_events::{AuditAction, AuditEvent},
+        email_verifications::VerifyEmail,
+        introspection::{IntrospectToken, Introspection},
+        linked_identities::{
+            ExternalAuthorization, ExternalCallback, ExternalLoginStart, LinkedIdentityInfo,
+        },
+        login_challenges::LoginTwoFactor,
+        password_resets::ResetPassword,
+        phone_codes::{LoginWithCode, PhoneCodePurpose, VerifyPhone},
+        roles::{Permission, Role, UpdateRole},
+        sessions::{
+            AuthenticatedUser, ClientInfo, Credential, ImpersonationToken, LoginResponse, Session,
+            SessionInfo, TokenPair,
+        },
+        two_factors::{RecoveryCodes, TwoFactorCode, TwoFactorSetup},
+        users::{
+            AdminUser, ChangePassword, CreateUser, Education, Gender, LoginUser, User, UserFilter,
+            UserPage,
+        },
+    },
+    services::access_tokens::{
+        serv_access_token_create, serv_access_token_find, serv_access_token_list,
+        serv_access_token_revoke, serv_access_token_revoke_all,
+    },
+    services::audit_events::serv_audit_record,
+    services::email_verifications::{
+        serv_email_verification_consume, serv_email_verification_revoke_all,
+        serv_email_verification_send,
+    },
+    services::linked_identities::{
+        serv_external_login_consume, serv_external_login_start, serv_linked_identity_find,
+        serv_linked_identity_link, serv_linked_identity_list, serv_linked_identity_revoke_all,
+        serv_linked_identity_touch, serv_linked_identity_unlink,
+    },
+    services::login_attempts::{
+        serv_login_attempt_fail, serv_login_attempt_release, serv_login_attempt_reserve,
+        serv_login_attempt_success,
+    },
+    services::login_challenges::{
+        serv_login_challenge_attempt, serv_login_challenge_create, serv_login_challenge_fail,
+        serv_login_challenge_remove, serv_login_challenge_revoke_all,
+    },
+    services::oauth::serv_oauth_revoke_all,
+    services::password_resets::{
+        serv_password_reset_consume, serv_password_reset_create, serv_password_reset_find,
+        serv_password_reset_revoke_all,
+    },
+    services::phone_codes::{serv_phone_code_consume, serv_phone_code_send},
+    services::sessions::{
+        serv_session_count, serv_session_create, serv_session_database, serv_session_find,
+        serv_session_impersonate, serv_session_list, serv_session_revoke, serv_session_revoke_all,
+        serv_session_revoke_others, serv_session_revoke_own, serv_session_rotate,
+        serv_session_touch,
+    },
+    services::two_factors::{
+        serv_two_factor_disable, serv_two_factor_enable, serv_two_factor_enabled,
+        serv_two_factor_recovery_regenerate, serv_two_factor_setup, serv_two_factor_verify,
+    },
+    sms::SmsSender,
+    stores::login_attempts::LoginAttemptStore,
+    utils::{
+        identifier::{email_normalize, identifier_parse, phone_normalize},
+        password::{password_hash, password_needs_rehash, password_verify, password_verify_dummy},
+        password_policy::password_policy_check,
+        token::{
+            access_token_decode, access_token_issue, code_generator, token_digest, AccessClaims,
+            TokenActor, PERSONAL_ACCESS_TOKEN_PREFIX,
+        },
+    },
 };
 
 /**
@@ -18,120 +98,922 @@ pub fn serv_user_database(database: &Client) -> mongodb::Collection<User> {
 }
 
 /**
- * Verify the user token
+ * Create the indexes of the user collection
  * @param database The database client
- * @param username The username of the user
  *
- * @return The data of the user
+ * @note Emails and phones are only unique when they are set,
+ *       usernames only among the users who are not deleted,
+ *       deleted users of earlier versions may share a username with a live one
  */
-pub async fn serv_user_token_verify(database: &Client, username: String) -> Result<User, WebError> {
-    let users: mongodb::Collection<User> = serv_user_database(database);
-    let user = users
-        .find_one(doc! {"username": username.clone()}, None)
-        .await
-        .map(|user| user.unwrap())?;
+pub async fn serv_user_indexes(database: &Client) -> Result<(), WebError> {
+    let users = serv_user_database(database);
+
+    // an earlier version built the username index over the deleted users too
+    let legacy = users
+        .list_indexes(None)
+        .await?
+        .try_collect::<Vec<IndexModel>>()
+        .await?
+        .into_iter()
+        .any(|index| {
+            index
+                .options
+                .as_ref()
+                .and_then(|options| options.name.as_deref())
+                == Some("username_1")
+                && index
+                    .options
+                    .as_ref()
+                    .and_then(|options| options.partial_filter_expression.as_ref())
+                    .is_none()
+        });
+    if legacy {
+        users.drop_index("username_1", None).await?;
+    }
+
+    let unique_when_set = |field: &str| {
+        IndexModel::builder()
+            .keys(doc! {field: 1})
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! {field: {"$gt": ""}})
+                    .build(),
+            )
+            .build()
+    };
+    users
+        .create_indexes(
+            vec![
+                IndexModel::builder()
+                    .keys(doc! {"username": 1})
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! {"is_deprecated": false})
+                            .build(),
+                    )
+                    .build(),
+                unique_when_set("email"),
+                unique_when_set("phone"),
+                IndexModel::builder()
+                    .keys(doc! {"is_deprecated": 1, "delete_time": 1})
+                    .build(),
+            ],
+            None,
+        )
+        .await?;
+    Ok(())
+}
 
-    if user.token == "" {
+/**
+ * Normalize the identifiers of a user before it is stored
+ * @param config The service configuration
+ * @param user The user to store
+ *
+ * @note A username must not look like an email or a phone, so a login identifier is never ambiguous
+ */
+fn user_identifiers_normalize(config: &Config, user: &mut User) -> Result<(), WebError> {
+    user.username = user.username.trim().to_string();
+    let country_code = &config.identifier.phone_country_code;
+    if user.username.is_empty()
+        || identifier_parse(&user.username, country_code).field() != "username"
+    {
         return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "You need to login first!".to_string(),
+            StatusCode::BAD_REQUEST,
+            "Username must not be empty, an email or a phone number!".to_string(),
         ));
     }
 
-    if user.valid_token_time < Utc::now().timestamp() {
-        users
-            .update_one(
-                doc! {"username": username},
-                doc! {"$set": {"token": ""}},
-                None,
-            )
-            .await?;
+    user.email = email_normalize(&user.email);
+    if !user.email.is_empty() && !user.email.contains('@') {
         return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "Token expired!".to_string(),
+            StatusCode::BAD_REQUEST,
+            "Email is invalid!".to_string(),
         ));
     }
+    if !user.phone.trim().is_empty() {
+        user.phone = phone_normalize(&user.phone, country_code).ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Phone number is invalid!".to_string(),
+            )
+        })?;
+    } else {
+        user.phone = String::new();
+    }
+    Ok(())
+}
 
-    Ok(user)
+/**
+ * Reject a user whose username, email or phone belongs to another user
+ * @param database The database client
+ * @param user The user to store
+ */
+async fn serv_user_conflict_check(database: &Client, user: &User) -> Result<(), WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+
+    let fields = [
+        ("username", &user.username, "Username is already taken!"),
+        ("email", &user.email, "Email is already registered!"),
+        ("phone", &user.phone, "Phone number is already registered!"),
+    ];
+    for (field, value, message) in fields {
+        if value.is_empty() {
+            continue;
+        }
+        let other = users
+            .find_one(doc! {field: value, "_id": {"$ne": user._id}}, None)
+            .await?;
+        if other.is_some() {
+            return Err(WebError::new(StatusCode::CONFLICT, message.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Restrict a user lookup to the users who can login
+ * @param config The service configuration
+ * @param filter The lookup
+ *
+ * @note A deleted user can still login during the grace period, the login restores the account
+ */
+fn user_login_filter(config: &Config, mut filter: bson::Document) -> bson::Document {
+    let grace_start = Utc::now().timestamp() - config.account_deletion.grace_period;
+    filter.insert(
+        "$or",
+        vec![
+            doc! {"is_deprecated": false},
+            doc! {"is_deprecated": true, "is_anonymized": {"$ne": true}, "delete_time": {"$gte": grace_start}},
+        ],
+    );
+    filter
+}
+
+/**
+ * Build the claims of an access token for a session
+ * @param config The service configuration
+ * @param session The session of the user
+ * @param user The user of the session
+ *
+ * @note The tokens of a session opened by the single sign-on carry its client and scopes,
+ *       the tokens of an impersonation carry the admin and end with the session
+ */
+fn session_claims(config: &Config, session: &Session, user: &User) -> AccessClaims {
+    let mut claims = AccessClaims::new(
+        &config.token,
+        session.user_id.to_hex(),
+        session.username.clone(),
+        vec![user.role.to_string()],
+        session._id.unwrap_or_default().to_hex(),
+    );
+    if let Some(grant) = &session.grant {
+        claims.client_id = Some(grant.client_id.clone());
+        claims.scope = Some(grant.scopes.join(" "));
+    }
+    if let (Some(impersonator), Some(impersonator_id)) =
+        (&session.impersonator, session.impersonator_id)
+    {
+        claims.act = Some(TokenActor {
+            sub: impersonator_id.to_hex(),
+            username: impersonator.clone(),
+        });
+        claims.exp = claims.exp.min(session.expire_time);
+    }
+    claims
+}
+
+/**
+ * Issue a signed access token for a session
+ * @param config The service configuration
+ * @param session The session of the user
+ * @param refresh_token The refresh token just issued for the session
+ * @param user The user of the session
+ *
+ * @return The access token along with the refresh token of the session
+ */
+pub fn serv_user_token_issue(
+    config: &Config,
+    session: &Session,
+    refresh_token: String,
+    user: &User,
+) -> Result<TokenPair, WebError> {
+    let claims = session_claims(config, session, user);
+
+    Ok(TokenPair {
+        access_token: access_token_issue(&claims, &config.token)?,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: config.token.access_token_lifetime,
+        session_expires_in: session.expire_time - Utc::now().timestamp(),
+    })
+}
+
+/**
+ * Verify the user token
+ * @param database The database client
+ * @param config The service configuration
+ * @param token The access token of the user
+ *
+ * @return The user and the session or the personal access token behind the token
+ *
+ * @note The session is checked as well, so a logout takes effect before the token expires,
+ *       and its last use is recorded. Personal access tokens are told apart by their prefix
+ */
+pub async fn serv_user_token_verify(
+    database: &Client,
+    config: &Config,
+    token: String,
+) -> Result<AuthenticatedUser, WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+
+    let (user_id, credential) = if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+        let access_token = serv_access_token_find(database, config, &token).await?;
+        (
+            access_token.user_id,
+            Credential::PersonalAccessToken(access_token),
+        )
+    } else {
+        let claims = access_token_decode(&token, &config.token)?;
+        let session = serv_session_find(database, &claims.sid).await?;
+        let session = serv_session_touch(database, config, session).await?;
+        (
+            session.user_id,
+            Credential::Session {
+                session: Box::new(session),
+                claims,
+            },
+        )
+    };
+
+    let user = users
+        .find_one(
+            doc! {"_id": user_id, "is_deprecated": false, "is_suspended": {"$ne": true}},
+            None,
+        )
+        .await?
+        .ok_or_else(|| {
+            WebError::new(
+                StatusCode::UNAUTHORIZED,
+                "You need to login first!".to_string(),
+            )
+        })?;
+
+    Ok(AuthenticatedUser {
+        user,
+        credential,
+        client: ClientInfo::default(),
+    })
+}
+
+/**
+ * Describe the token of an active user
+ * @param config The service configuration
+ * @param user The user of the token
+ * @param scope The scopes of the token, the permissions of the role for a login
+ * @param token_type The type of the token
+ * @param iat The issue time of the token
+ * @param exp The expiry of the token
+ */
+fn introspection_active(
+    config: &Config,
+    user: &User,
+    scope: Vec<String>,
+    token_type: &str,
+    iat: i64,
+    exp: Option<i64>,
+) -> Introspection {
+    Introspection {
+        active: true,
+        sub: user._id.map(|id| id.to_hex()),
+        username: Some(user.username.clone()),
+        roles: Some(vec![user.role.to_string()]),
+        scope: Some(scope.join(" ")),
+        token_type: Some(token_type.to_string()),
+        client_id: None,
+        act: None,
+        iss: Some(config.token.issuer.clone()),
+        iat: Some(iat),
+        exp,
+    }
+}
+
+// scopes of a session, the permissions of the role for a login to mlum itself
+fn session_scope(user: &User, session: &Session) -> Vec<String> {
+    match &session.grant {
+        Some(grant) => grant.scopes.clone(),
+        None => user
+            .role
+            .permissions()
+            .iter()
+            .map(|permission| permission.to_string())
+            .collect(),
+    }
+}
+
+/**
+ * Introspect a token for another service (RFC 7662)
+ * @param database The database client
+ * @param config The service configuration
+ * @param introspect The token to describe
+ *
+ * @return The state of the token, inactive tokens only carry `active: false`
+ *
+ * @note Access tokens and refresh tokens are both accepted, the type hint is not required
+ */
+pub async fn serv_user_token_introspect(
+    database: &Client,
+    config: &Config,
+    introspect: IntrospectToken,
+) -> Result<Introspection, WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+
+    if introspect.token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX)
+        || access_token_decode(&introspect.token, &config.token).is_ok()
+    {
+        return match serv_user_token_verify(database, config, introspect.token).await {
+            Ok(AuthenticatedUser {
+                user,
+                credential: Credential::Session { session, claims },
+                ..
+            }) => Ok(Introspection {
+                client_id: claims.client_id,
+                act: claims.act,
+                ..introspection_active(
+                    config,
+                    &user,
+                    session_scope(&user, &session),
+                    "access_token",
+                    claims.iat,
+                    Some(claims.exp),
+                )
+            }),
+            Ok(AuthenticatedUser {
+                user,
+                credential: Credential::PersonalAccessToken(token),
+                ..
+            }) => Ok(introspection_active(
+                config,
+                &user,
+                token.scopes.iter().map(|scope| scope.to_string()).collect(),
+                "personal_access_token",
+                token.create_time,
+                token.expire_time,
+            )),
+            Err(err) if err.status_code() == StatusCode::UNAUTHORIZED => {
+                Ok(Introspection::default())
+            }
+            Err(err) => Err(err),
+        };
+    }
+
+    let session = serv_session_database(database)
+        .find_one(
+            doc! {
+                "refresh_token_digest": token_digest(&config.token, &introspect.token),
+                "expire_time": {"$gte": Utc::now().timestamp()},
+            },
+            None,
+        )
+        .await?;
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Introspection::default()),
+    };
+    let user = users
+        .find_one(
+            doc! {"_id": session.user_id, "is_deprecated": false, "is_suspended": {"$ne": true}},
+            None,
+        )
+        .await?;
+    Ok(
+        user.map_or_else(Introspection::default, |user| Introspection {
+            client_id: session.grant.as_ref().map(|grant| grant.client_id.clone()),
+            ..introspection_active(
+                config,
+                &user,
+                session_scope(&user, &session),
+                "refresh_token",
+                session.create_time,
+                Some(session.expire_time),
+            )
+        }),
+    )
 }
 
 /**
  * Register a new user
  * @param database The database client
+ * @param config The service configuration
+ * @param mailer The mailer which sends the verification code
  * @param user_info The user information
+ * @param client The client which registers
  *
- * @return The token of the user
+ * @return The tokens of the user, none if the email must be verified before the first login
  *
  * @throws WebError::DBError
  *
- * @note The token is generated using nanoid
+ * @note The password must meet the password policy, it is stored as an Argon2id hash
  */
 pub async fn serv_user_register(
     database: &Client,
+    config: &Config,
+    mailer: &dyn Mailer,
     user_info: CreateUser,
-) -> Result<String, WebError> {
-    let users: mongodb::Collection<User> = serv_user_database(database);
-    let mut user = User::from(user_info.clone());
-    // let db automatically generate the id
-    user._id = Some(bson::oid::ObjectId::new());
+    client: ClientInfo,
+) -> Result<Option<TokenPair>, WebError> {
+    let mut event = AuditEvent::new(AuditAction::Register, &client);
+    event.set_target_name(&user_info.username);
+    let res: Result<Option<TokenPair>, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+        let mut user = User::from(user_info.clone());
+        // let db automatically generate the id
+        user._id = Some(bson::oid::ObjectId::new());
 
-    users.insert_one(user, None).await?;
+        user_identifiers_normalize(config, &mut user)?;
+        password_policy_check(
+            &user_info.password,
+            &config.password_policy,
+            &user.username,
+            &user.email,
+        )?;
+        user.password = password_hash(&user_info.password, &config.password_hash)?;
+        serv_user_conflict_check(database, &user).await?;
+        users.insert_one(&user, None).await?;
+        event.set_actor(&user);
+        event.set_target(&user);
 
-    serv_user_login(database, user_info).await
+        if !user.email.is_empty() {
+            serv_email_verification_send(database, config, mailer, &user).await?;
+        }
+        if config.email_verification.required {
+            return Ok(None);
+        }
+
+        serv_user_session_open(database, config, &user, client, false)
+            .await
+            .map(Some)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
 }
 
 /**
  * Login a user
  * @param database The database client
- * @param user_info The user information
+ * @param config The service configuration
+ * @param attempts The login attempt store
+ * @param user_info The identifier (username, email or phone) and the password
+ * @param client The client which logs in
+ *
+ * @return The tokens of the new session, or a challenge if the user has enabled 2FA
  *
- * @return The token of the user
+ * @note Legacy plaintext passwords are rehashed after a successful login.
+ *       Failed logins are delayed and lock the account or the client ip after too many attempts
  */
-pub async fn serv_user_login(database: &Client, user_info: CreateUser) -> Result<String, WebError> {
-    let users: mongodb::Collection<User> = serv_user_database(database);
-    let token = token_generator();
+pub async fn serv_user_login(
+    database: &Client,
+    config: &Config,
+    attempts: &dyn LoginAttemptStore,
+    user_info: LoginUser,
+    client: ClientInfo,
+) -> Result<LoginResponse, WebError> {
+    let mut event = AuditEvent::new(AuditAction::Login, &client);
+    event.set_target_name(&user_info.identifier);
+    let res: Result<LoginResponse, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
 
-    let res = users
-        .update_one(
-            doc! {"username": user_info.username, "password": user_info.password, "is_deprecated": false},
-            doc! {"$set": {"token": token.clone(), "valid_token_time": Utc::now().timestamp() + 3600}},
-            None,
+        let identifier = identifier_parse(&user_info.identifier, &config.identifier.phone_country_code);
+        let user = users
+            .find_one(
+                user_login_filter(config, doc! {identifier.field(): identifier.value()}),
+                None,
+            )
+            .await?;
+
+        // failures are counted per account, whichever identifier was typed
+        let key = user
+            .as_ref()
+            .map_or(identifier.value(), |user| user.username.as_str())
+            .to_string();
+        let reservation =
+            serv_login_attempt_reserve(attempts, &config.login_throttle, &key, &client.ip).await?;
+
+        if let Some(user) = &user {
+            event.set_target(user);
+        }
+        let verified = match &user {
+            Some(user) => password_verify(&user_info.password, &user.password),
+            None => password_verify_dummy(&user_info.password, &config.password_hash),
+        };
+        let user = match user {
+            Some(user) if verified => user,
+            _ => {
+                return Err(
+                    serv_login_attempt_fail(attempts, &config.login_throttle, reservation).await?,
+                )
+            }
+        };
+        serv_login_attempt_release(attempts, reservation).await?;
+
+        if password_needs_rehash(&user.password, &config.password_hash) {
+            users
+                .update_one(
+                    doc! {"_id": user._id},
+                    doc! {"$set": {"password": password_hash(&user_info.password, &config.password_hash)?}},
+                    None,
+                )
+                .await?;
+        }
+
+        event.set_actor(&user);
+        let response = serv_user_login_complete(
+            database,
+            config,
+            attempts,
+            &user,
+            client,
+            user_info.remember_me,
         )
         .await?;
+        if let LoginResponse::Challenge(_) = response {
+            event.detail = Some("Second factor required".to_string());
+        }
+        Ok(response)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
 
-    if res.modified_count == 0 {
-        return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "Username or password error!".to_string(),
-        ));
+/**
+ * Finish a login whose first factor has been checked
+ * @param database The database client
+ * @param config The service configuration
+ * @param attempts The login attempt store
+ * @param user The user who logs in
+ * @param client The client which logs in
+ * @param remember_me Whether the session gets the longer timeouts
+ *
+ * @return The tokens of the new session, or a challenge if the user has enabled 2FA
+ *
+ * @note The failed logins are only forgotten once every factor has been checked
+ */
+async fn serv_user_login_complete(
+    database: &Client,
+    config: &Config,
+    attempts: &dyn LoginAttemptStore,
+    user: &User,
+    client: ClientInfo,
+    remember_me: bool,
+) -> Result<LoginResponse, WebError> {
+    if serv_two_factor_enabled(database, user._id.unwrap_or_default()).await? {
+        return serv_login_challenge_create(
+            database,
+            config,
+            user._id.unwrap_or_default(),
+            remember_me,
+        )
+        .await
+        .map(LoginResponse::Challenge);
     }
 
-    Ok(token)
+    serv_login_attempt_success(attempts, &user.username).await?;
+    serv_user_session_open(database, config, user, client, remember_me)
+        .await
+        .map(LoginResponse::Token)
 }
 
 /**
- * Logout a user
+ * Complete a login with the second factor
  * @param database The database client
- * @param token_ The token of the user
+ * @param config The service configuration
+ * @param attempts The login attempt store
+ * @param login The challenge token and the TOTP or recovery code
+ * @param client The client which logs in
  *
+ * @return The tokens of the new session
+ *
+ * @note Wrong codes count as failed logins of the user, so the account is locked like a password guess
  */
-pub async fn serv_user_logout(database: &Client, token: String) -> Result<(), WebError> {
-    let users: mongodb::Collection<User> = serv_user_database(database);
-    let res = users
-        .update_one(
-            doc! {"token": token},
-            doc! {"$set": {"token": "", "valid_token_time": 0}},
-            None,
+pub async fn serv_user_login_two_factor(
+    database: &Client,
+    config: &Config,
+    attempts: &dyn LoginAttemptStore,
+    login: LoginTwoFactor,
+    client: ClientInfo,
+) -> Result<TokenPair, WebError> {
+    let mut event = AuditEvent::new(AuditAction::LoginTwoFactor, &client);
+    let res: Result<TokenPair, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let challenge =
+            serv_login_challenge_attempt(database, config, login.challenge_token).await?;
+        let user = users
+            .find_one(
+                user_login_filter(config, doc! {"_id": challenge.user_id}),
+                None,
+            )
+            .await?
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "Challenge is invalid or expired!".to_string(),
+                )
+            })?;
+        event.set_actor(&user);
+        event.set_target(&user);
+        let reservation = serv_login_attempt_reserve(
+            attempts,
+            &config.login_throttle,
+            &user.username,
+            &client.ip,
         )
         .await?;
-    if res.modified_count == 0 {
+
+        if let Err(error) =
+            serv_two_factor_verify(database, config, challenge.user_id, &login.code, true).await
+        {
+            serv_login_challenge_fail(database, config, &challenge).await?;
+            let locked =
+                serv_login_attempt_fail(attempts, &config.login_throttle, reservation).await?;
+            if locked.status_code() != StatusCode::UNAUTHORIZED {
+                return Err(locked);
+            }
+            return Err(error);
+        }
+        serv_login_attempt_release(attempts, reservation).await?;
+        serv_login_challenge_remove(database, &challenge).await?;
+        serv_login_attempt_success(attempts, &user.username).await?;
+
+        serv_user_session_open(database, config, &user, client, challenge.remember_me).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Open a session for a user whose credentials have been checked
+ * @param database The database client
+ * @param config The service configuration
+ * @param user The user who logs in
+ * @param client The client which logs in
+ * @param remember_me Whether the session gets the longer timeouts
+ *
+ * @return The tokens of the new session
+ */
+pub async fn serv_user_session_open(
+    database: &Client,
+    config: &Config,
+    user: &User,
+    client: ClientInfo,
+    remember_me: bool,
+) -> Result<TokenPair, WebError> {
+    if user.is_suspended {
+        return Err(WebError::new(
+            StatusCode::FORBIDDEN,
+            "Account is suspended!".to_string(),
+        ));
+    }
+    if config.email_verification.required && !user.email_verified {
         return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "Token expired!".to_string(),
+            StatusCode::FORBIDDEN,
+            "Email is not verified!".to_string(),
         ));
     }
 
-    Ok(())
+    if user.is_deprecated {
+        serv_user_deletion_cancel(database, user, &client).await?;
+    }
+
+    let (session, refresh_token) =
+        serv_session_create(database, config, user, client, None, remember_me).await?;
+    serv_user_token_issue(config, &session, refresh_token, user)
+}
+
+/**
+ * Restore a deleted user who logged in during the grace period
+ * @param database The database client
+ * @param user The user who logs in
+ * @param client The client which logs in
+ */
+async fn serv_user_deletion_cancel(
+    database: &Client,
+    user: &User,
+    client: &ClientInfo,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::new(AuditAction::Restore, client);
+    event.set_actor(user);
+    event.set_target(user);
+    event.detail = Some("Logged in during the deletion grace period".to_string());
+    let res: Result<(), WebError> = async {
+        serv_user_database(database)
+            .update_one(
+                doc! {"_id": user._id, "is_deprecated": true},
+                doc! {"$set": {"is_deprecated": false}, "$unset": {"delete_time": ""}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Send a login code to a verified phone number
+ * @param database The database client
+ * @param config The service configuration
+ * @param sender The SMS sender
+ * @param phone The phone number
+ * @param client The client which asks for the code
+ *
+ * @note Unknown or unverified numbers are ignored silently
+ */
+pub async fn serv_user_login_code_send(
+    database: &Client,
+    config: &Config,
+    sender: &dyn SmsSender,
+    phone: String,
+    client: ClientInfo,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::new(AuditAction::LoginCodeSend, &client);
+    event.set_target_name(&phone);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let phone = match phone_normalize(&phone, &config.identifier.phone_country_code) {
+            Some(phone) => phone,
+            None => return Ok(()),
+        };
+        let user = users
+            .find_one(
+                user_login_filter(
+                    config,
+                    doc! {"phone": phone.clone(), "phone_verified": true},
+                ),
+                None,
+            )
+            .await?;
+        match user {
+            Some(user) => event.set_target(&user),
+            None => return Ok(()),
+        }
+
+        serv_phone_code_send(database, config, sender, &phone, PhoneCodePurpose::Login).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Login a user with a code sent to the phone
+ * @param database The database client
+ * @param config The service configuration
+ * @param attempts The login attempt store
+ * @param login The phone number and the code
+ * @param client The client which logs in
+ *
+ * @return The tokens of the new session, or a challenge if the user has enabled 2FA
+ */
+pub async fn serv_user_login_code(
+    database: &Client,
+    config: &Config,
+    attempts: &dyn LoginAttemptStore,
+    login: LoginWithCode,
+    client: ClientInfo,
+) -> Result<LoginResponse, WebError> {
+    let mut event = AuditEvent::new(AuditAction::LoginCode, &client);
+    event.set_target_name(&login.phone);
+    let res: Result<LoginResponse, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let phone = phone_normalize(&login.phone, &config.identifier.phone_country_code)
+            .unwrap_or_default();
+        let user = users
+            .find_one(
+                user_login_filter(
+                    config,
+                    doc! {"phone": phone.clone(), "phone_verified": true},
+                ),
+                None,
+            )
+            .await?
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Verification code is invalid or expired!".to_string(),
+                )
+            })?;
+        event.set_target(&user);
+        let reservation = serv_login_attempt_reserve(
+            attempts,
+            &config.login_throttle,
+            &user.username,
+            &client.ip,
+        )
+        .await?;
+
+        if let Err(error) = serv_phone_code_consume(
+            database,
+            config,
+            &phone,
+            PhoneCodePurpose::Login,
+            login.code.trim(),
+        )
+        .await
+        {
+            let locked =
+                serv_login_attempt_fail(attempts, &config.login_throttle, reservation).await?;
+            if locked.status_code() != StatusCode::UNAUTHORIZED {
+                return Err(locked);
+            }
+            return Err(error);
+        }
+        serv_login_attempt_release(attempts, reservation).await?;
+
+        event.set_actor(&user);
+        let response =
+            serv_user_login_complete(database, config, attempts, &user, client, login.remember_me)
+                .await?;
+        if let LoginResponse::Challenge(_) = response {
+            event.detail = Some("Second factor required".to_string());
+        }
+        Ok(response)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Refresh the access token of a session
+ * @param database The database client
+ * @param config The service configuration
+ * @param refresh_token The refresh token of the session
+ * @param client The client which refreshes
+ *
+ * @return The new access token along with a new refresh token
+ *
+ * @note The presented refresh token is consumed, reusing it revokes the session
+ */
+pub async fn serv_user_token_refresh(
+    database: &Client,
+    config: &Config,
+    refresh_token: String,
+    client: ClientInfo,
+) -> Result<TokenPair, WebError> {
+    let mut event = AuditEvent::new(AuditAction::TokenRefresh, &client);
+    let res: Result<TokenPair, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let (session, refresh_token) =
+            serv_session_rotate(database, config, refresh_token, None, client).await?;
+        // the role may have changed since the login
+        let user = users
+            .find_one(
+                doc! {"_id": session.user_id, "is_deprecated": false, "is_suspended": {"$ne": true}},
+                None,
+            )
+            .await?
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "You need to login first!".to_string(),
+                )
+            })?;
+        event.set_actor(&user);
+        event.set_target(&user);
+        serv_user_token_issue(config, &session, refresh_token, &user)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Logout a user
+ * @param database The database client
+ * @param auth The authenticated user
+ *
+ * @note Only the session of the token is closed, other devices stay logged in.
+ *       Logging out of an impersonation stops it
+ */
+pub async fn serv_user_logout(database: &Client, auth: AuthenticatedUser) -> Result<(), WebError> {
+    if auth.impersonation().is_some() {
+        return serv_user_impersonate_stop(database, auth).await;
+    }
+    let event = AuditEvent::by(AuditAction::Logout, &auth);
+    let res: Result<(), WebError> =
+        async { serv_session_revoke(database, auth.session()?._id.unwrap_or_default()).await }
+            .await;
+    serv_audit_record(database, event, &res).await;
+    res
 }
 
 /**
@@ -145,95 +1027,1578 @@ pub async fn serv_user_profile(database: &Client, username: String) -> Result<Us
     let users: mongodb::Collection<User> = serv_user_database(database);
 
     let mut user_profile = users
-        .find_one(doc! {"username": username}, None)
-        .await
-        .map(|user| user.unwrap())?;
+        .find_one(doc! {"username": username, "is_deprecated": false}, None)
+        .await?
+        .ok_or_else(user_not_found)?;
 
     user_profile.password = "".to_string();
-    user_profile.token = "".to_string();
     Ok(user_profile)
 }
 
 /**
  * Update the user profile
  * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
  * @param user_info The user information
  *
  * @return The user profile
  */
-pub async fn serv_user_update(database: &Client, mut user_info: User) -> Result<User, WebError> {
-    let users: mongodb::Collection<User> = serv_user_database(database);
+pub async fn serv_user_update(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    mut user_info: User,
+) -> Result<User, WebError> {
+    let event = AuditEvent::by(AuditAction::ProfileUpdate, &auth);
+    let res: Result<User, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
 
-    let user_profile = serv_user_token_verify(database, user_info.username.clone()).await?;
-    if user_info.token != user_profile.token {
-        return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "Token error!".to_string(),
-        ));
-    }
+        if user_info.username != auth.user.username {
+            return Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "You can only update your own profile!".to_string(),
+            ));
+        }
 
-    users
-        .update_one(
-            doc! {"username": user_info.username.clone()},
-            doc! {"$set": &user_info},
-            None,
-        )
-        .await?;
+        // the conflict check skips the user itself
+        user_info._id = auth.user._id;
+        user_identifiers_normalize(config, &mut user_info)?;
+        serv_user_conflict_check(database, &user_info).await?;
+        if auth.impersonation().is_some()
+            && (user_info.email != auth.user.email || user_info.phone != auth.user.phone)
+        {
+            return Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "The email and the phone can not be changed while impersonating!".to_string(),
+            ));
+        }
+        // only the fields of the profile are taken from the request, the password,
+        // role, registration and deletion state are kept, a new email or phone must be verified again
+        let user = User {
+            gender: user_info.gender,
+            education: user_info.education,
+            description: user_info.description,
+            avatar: user_info.avatar,
+            school: user_info.school,
+            major: user_info.major,
+            email_verified: auth.user.email_verified && user_info.email == auth.user.email,
+            email: user_info.email,
+            phone_verified: auth.user.phone_verified && user_info.phone == auth.user.phone,
+            phone: user_info.phone,
+            following: user_info.following,
+            participated: user_info.participated,
+            published: user_info.published,
+            collection: user_info.collection,
+            ..auth.user
+        };
+        let update = doc! {
+            "gender": user.gender.clone(),
+            "education": user.education.clone(),
+            "description": &user.description,
+            "avatar": &user.avatar,
+            "school": &user.school,
+            "major": &user.major,
+            "email": &user.email,
+            "email_verified": user.email_verified,
+            "phone": &user.phone,
+            "phone_verified": user.phone_verified,
+            "following": &user.following,
+            "participated": &user.participated,
+            "published": &user.published,
+            "collection": &user.collection,
+        };
 
-    user_info.password = "".to_string();
-    user_info.token = "".to_string();
+        users
+            .update_one(doc! {"_id": user._id}, doc! {"$set": update}, None)
+            .await?;
 
-    Ok(user_info)
+        Ok(User {
+            password: "".to_string(),
+            ..user
+        })
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
 }
 
 /**
  * Delete the user profile
  * @param database The database client
- * @param username The username of the user
+ * @param auth The authenticated user
  *
+ * @note Every session and personal access token of the user is closed.
+ *       A login during the grace period restores the account, afterwards
+ *       `serv_user_deletion_cleanup` anonymizes or purges it
+ */
+pub async fn serv_user_delete(database: &Client, auth: AuthenticatedUser) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::Delete, &auth);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+        auth.own_session()?;
+        let user = auth.user;
+
+        users
+            .update_one(
+                doc! {"_id": user._id},
+                doc! {"$set": {"is_deprecated": true, "delete_time": Utc::now().timestamp()}},
+                None,
+            )
+            .await?;
+        serv_session_revoke_all(database, user._id.unwrap_or_default()).await?;
+        serv_access_token_revoke_all(database, user._id.unwrap_or_default()).await?;
+
+        Ok(())
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Send a password reset link to the user
+ * @param database The database client
+ * @param config The service configuration
+ * @param mailer The mailer which sends the link
+ * @param email The email of the user
+ * @param client The client which asks for the link
  *
+ * @note Unknown emails are ignored silently, so the endpoint does not reveal registered emails
  */
-pub async fn serv_user_delete(
+pub async fn serv_user_password_forgot(
     database: &Client,
-    certification: CertificateUser,
+    config: &Config,
+    mailer: &dyn Mailer,
+    email: String,
+    client: ClientInfo,
 ) -> Result<(), WebError> {
-    let users: mongodb::Collection<User> = serv_user_database(database);
+    let mut event = AuditEvent::new(AuditAction::PasswordForgot, &client);
+    event.set_target_name(&email);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
 
-    let res = users
-        .update_one(
-            doc! {"username": certification.username, "token": certification.token},
-            doc! {"$set": {"is_deprecated": true}},
-            None,
+        let email = email_normalize(&email);
+        if email.is_empty() {
+            return Ok(());
+        }
+        let user = match users
+            .find_one(doc! {"email": email, "is_deprecated": false}, None)
+            .await?
+        {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        event.set_target(&user);
+
+        let token = serv_password_reset_create(database, config, user._id.unwrap_or_default()).await?;
+        mailer
+            .send(Mail {
+                to: user.email,
+                subject: "Reset your mlum password".to_string(),
+                body: format!(
+                    "Hi {},\n\nOpen the link below to choose a new password:\n{}\n\nThe link expires in {} minutes. If you did not ask for it, just ignore this mail.",
+                    user.username,
+                    config.password_reset.url.replace("{token}", &token),
+                    config.password_reset.token_lifetime / 60
+                ),
+            })
+            .await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Set a new password with a reset token
+ * @param database The database client
+ * @param config The service configuration
+ * @param reset The reset token and the new password
+ * @param client The client which resets the password
+ *
+ * @note The token stays valid if the password does not meet the policy,
+ *       otherwise every session, personal access token, pending login challenge
+ *       and every other reset token of the user is revoked
+ */
+pub async fn serv_user_password_reset(
+    database: &Client,
+    config: &Config,
+    reset: ResetPassword,
+    client: ClientInfo,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::new(AuditAction::PasswordReset, &client);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        // the token is only consumed by a password which meets the policy
+        let user_id = serv_password_reset_find(database, config, &reset.token).await?;
+        let user = users
+            .find_one(doc! {"_id": user_id, "is_deprecated": false}, None)
+            .await?
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Reset token is invalid or expired!".to_string(),
+                )
+            })?;
+        event.set_actor(&user);
+        event.set_target(&user);
+        password_policy_check(
+            &reset.password,
+            &config.password_policy,
+            &user.username,
+            &user.email,
+        )?;
+
+        let user_id = serv_password_reset_consume(database, config, reset.token).await?;
+        users
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"password": password_hash(&reset.password, &config.password_hash)?}},
+                None,
+            )
+            .await?;
+
+        // whoever holds the old credentials is locked out
+        serv_password_reset_revoke_all(database, user_id).await?;
+        serv_login_challenge_revoke_all(database, user_id).await?;
+        serv_access_token_revoke_all(database, user_id).await?;
+        serv_session_revoke_all(database, user_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Change the password of the user
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param change The current and the new password
+ *
+ * @note Every other session, every personal access token and every reset token
+ *       of the user is revoked, the session of the request stays open
+ */
+pub async fn serv_user_password_change(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    change: ChangePassword,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::PasswordChange, &auth);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+        // refused before anything is checked or written
+        let session_id = auth.own_session()?._id.unwrap_or_default();
+
+        // a user created by an identity provider sets a first password without a current one
+        let has_password = !auth.user.password.is_empty();
+        if has_password && !password_verify(&change.current_password, &auth.user.password) {
+            return Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "Current password is incorrect!".to_string(),
+            ));
+        }
+        password_policy_check(
+            &change.new_password,
+            &config.password_policy,
+            &auth.user.username,
+            &auth.user.email,
+        )?;
+        if change.new_password == change.current_password {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "New password must be different from the current one!".to_string(),
+            ));
+        }
+
+        let user_id = auth.user._id.unwrap_or_default();
+        users
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {"password": password_hash(&change.new_password, &config.password_hash)?}},
+                None,
+            )
+            .await?;
+
+        serv_password_reset_revoke_all(database, user_id).await?;
+        serv_access_token_revoke_all(database, user_id).await?;
+        serv_session_revoke_others(database, user_id, session_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Mark the email of a user as verified
+ * @param database The database client
+ * @param config The service configuration
+ * @param verify The verification code
+ * @param client The client which verifies the email
+ *
+ * @note The code is rejected if the user changed the email since it was sent
+ */
+pub async fn serv_user_email_verify(
+    database: &Client,
+    config: &Config,
+    verify: VerifyEmail,
+    client: ClientInfo,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::new(AuditAction::EmailVerify, &client);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let verification = serv_email_verification_consume(database, config, verify.code).await?;
+        let user = users
+            .find_one_and_update(
+                doc! {"_id": verification.user_id, "email": verification.email, "is_deprecated": false},
+                doc! {"$set": {"email_verified": true}},
+                None,
+            )
+            .await?
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Verification code is invalid or expired!".to_string(),
+                )
+            })?;
+
+        event.set_actor(&user);
+        event.set_target(&user);
+        Ok(())
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Send the verification code of the user again
+ * @param database The database client
+ * @param config The service configuration
+ * @param mailer The mailer which sends the code
+ * @param auth The authenticated user
+ */
+pub async fn serv_user_email_resend(
+    database: &Client,
+    config: &Config,
+    mailer: &dyn Mailer,
+    auth: AuthenticatedUser,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::EmailResend, &auth);
+    let res: Result<(), WebError> = async {
+        auth.session()?;
+        if auth.user.email.is_empty() {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "No email to verify!".to_string(),
+            ));
+        }
+        if auth.user.email_verified {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Email is already verified!".to_string(),
+            ));
+        }
+
+        serv_email_verification_send(database, config, mailer, &auth.user).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Send a verification code to the phone of the user
+ * @param database The database client
+ * @param config The service configuration
+ * @param sender The SMS sender
+ * @param auth The authenticated user
+ */
+pub async fn serv_user_phone_send(
+    database: &Client,
+    config: &Config,
+    sender: &dyn SmsSender,
+    auth: AuthenticatedUser,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::PhoneSend, &auth);
+    let res: Result<(), WebError> = async {
+        auth.own_session()?;
+        let phone = auth.user.phone.trim();
+        if phone.is_empty() {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "No phone to verify!".to_string(),
+            ));
+        }
+        if auth.user.phone_verified {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Phone is already verified!".to_string(),
+            ));
+        }
+
+        serv_phone_code_send(database, config, sender, phone, PhoneCodePurpose::Verify).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Mark the phone of the user as verified
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param verify The code sent to the phone
+ */
+pub async fn serv_user_phone_verify(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    verify: VerifyPhone,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::PhoneVerify, &auth);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+        auth.own_session()?;
+
+        serv_phone_code_consume(
+            database,
+            config,
+            auth.user.phone.trim(),
+            PhoneCodePurpose::Verify,
+            verify.code.trim(),
         )
         .await?;
 
-    if res.modified_count == 0 {
+        users
+            .update_one(
+                doc! {"_id": auth.user._id, "phone": auth.user.phone},
+                doc! {"$set": {"phone_verified": true}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Start the enrollment of TOTP two-factor authentication
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ *
+ * @return The secret and its otpauth URI
+ */
+pub async fn serv_user_two_factor_setup(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+) -> Result<TwoFactorSetup, WebError> {
+    let event = AuditEvent::by(AuditAction::TwoFactorSetup, &auth);
+    let res: Result<TwoFactorSetup, WebError> = async {
+        auth.own_session()?;
+        serv_two_factor_setup(database, config, &auth.user).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Enable two-factor authentication with the first code of the app
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param code The TOTP code
+ *
+ * @return The recovery codes, only shown once
+ */
+pub async fn serv_user_two_factor_enable(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    code: TwoFactorCode,
+) -> Result<RecoveryCodes, WebError> {
+    let event = AuditEvent::by(AuditAction::TwoFactorEnable, &auth);
+    let res: Result<RecoveryCodes, WebError> = async {
+        auth.own_session()?;
+        serv_two_factor_enable(
+            database,
+            config,
+            auth.user._id.unwrap_or_default(),
+            code.code.trim(),
+        )
+        .await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Disable two-factor authentication
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param code A TOTP code or a recovery code
+ */
+pub async fn serv_user_two_factor_disable(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    code: TwoFactorCode,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::TwoFactorDisable, &auth);
+    let res: Result<(), WebError> = async {
+        auth.own_session()?;
+        let user_id = auth.user._id.unwrap_or_default();
+        serv_two_factor_verify(database, config, user_id, &code.code, true).await?;
+        serv_two_factor_disable(database, user_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Replace the recovery codes
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param code A TOTP code
+ *
+ * @return The new recovery codes, only shown once
+ */
+pub async fn serv_user_two_factor_recovery(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    code: TwoFactorCode,
+) -> Result<RecoveryCodes, WebError> {
+    let event = AuditEvent::by(AuditAction::TwoFactorRecovery, &auth);
+    let res: Result<RecoveryCodes, WebError> = async {
+        auth.own_session()?;
+        let user_id = auth.user._id.unwrap_or_default();
+        serv_two_factor_verify(database, config, user_id, &code.code, false).await?;
+        serv_two_factor_recovery_regenerate(database, config, user_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Create a personal access token
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated user
+ * @param create The name, the scopes and the lifetime of the token
+ *
+ * @return The token, only shown once
+ *
+ * @note Only a login session can manage tokens, a token can not create another one
+ */
+pub async fn serv_user_access_token_create(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    create: CreateAccessToken,
+) -> Result<CreatedAccessToken, WebError> {
+    let event = AuditEvent::by(AuditAction::AccessTokenCreate, &auth);
+    let res: Result<CreatedAccessToken, WebError> = async {
+        auth.own_session()?;
+        serv_access_token_create(database, config, auth.user._id.unwrap_or_default(), create).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * List the personal access tokens of the user
+ * @param database The database client
+ * @param auth The authenticated user
+ */
+pub async fn serv_user_access_token_list(
+    database: &Client,
+    auth: AuthenticatedUser,
+) -> Result<Vec<AccessTokenInfo>, WebError> {
+    auth.session()?;
+    serv_access_token_list(database, auth.user._id.unwrap_or_default()).await
+}
+
+/**
+ * Revoke a personal access token
+ * @param database The database client
+ * @param auth The authenticated user
+ * @param token_id The id of the token
+ */
+pub async fn serv_user_access_token_revoke(
+    database: &Client,
+    auth: AuthenticatedUser,
+    token_id: String,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::AccessTokenRevoke, &auth);
+    let res: Result<(), WebError> = async {
+        auth.own_session()?;
+        serv_access_token_revoke(database, auth.user._id.unwrap_or_default(), &token_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * List the open sessions of the user
+ * @param database The database client
+ * @param auth The authenticated user
+ *
+ * @return The sessions, the most recently used first, the session of the request is marked current
+ */
+pub async fn serv_user_session_list(
+    database: &Client,
+    auth: AuthenticatedUser,
+) -> Result<Vec<SessionInfo>, WebError> {
+    let current = auth.session()?._id;
+    let sessions = serv_session_list(database, auth.user._id.unwrap_or_default()).await?;
+    Ok(sessions
+        .into_iter()
+        .map(|session| SessionInfo {
+            current: session._id == current,
+            ..SessionInfo::from(session)
+        })
+        .collect())
+}
+
+/**
+ * Close a session of the user, e.g. on a lost device
+ * @param database The database client
+ * @param auth The authenticated user
+ * @param session_id The id of the session
+ *
+ * @note Closing the session of the request is a logout
+ */
+pub async fn serv_user_session_revoke(
+    database: &Client,
+    auth: AuthenticatedUser,
+    session_id: String,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::by(AuditAction::SessionRevoke, &auth);
+    event.detail = Some(format!("Session {}", session_id));
+    let res: Result<(), WebError> = async {
+        auth.own_session()?;
+        serv_session_revoke_own(database, auth.user._id.unwrap_or_default(), &session_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Close every session of the user but the session of the request
+ * @param database The database client
+ * @param auth The authenticated user
+ */
+pub async fn serv_user_session_revoke_others(
+    database: &Client,
+    auth: AuthenticatedUser,
+) -> Result<(), WebError> {
+    let event = AuditEvent::by(AuditAction::SessionRevokeOthers, &auth);
+    let res: Result<(), WebError> = async {
+        let session_id = auth.own_session()?._id.unwrap_or_default();
+        serv_session_revoke_others(database, auth.user._id.unwrap_or_default(), session_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Derive a username from an account of a provider
+ * @param identity The account at the provider
+ *
+ * @note Only letters, digits, "_", "-" and "." are kept and a letter is required,
+ *       so the username never looks like an email or a phone number
+ */
+fn external_username(identity: &ExternalIdentity) -> String {
+    let base = identity
+        .username
+        .clone()
+        .or_else(|| {
+            identity
+                .email
+                .as_ref()
+                .and_then(|email| email.split('@').next().map(str::to_string))
+        })
+        .unwrap_or_default();
+    let username: String = base
+        .chars()
+        .filter(|c| c.is_alphanumeric() || "_-.".contains(*c))
+        .take(32)
+        .collect();
+    if username.chars().any(char::is_alphabetic) {
+        username
+    } else {
+        format!("user{}", username)
+    }
+}
+
+/**
+ * Create the account of a user signing in with a provider for the first time
+ * @param database The database client
+ * @param config The service configuration
+ * @param identity The account at the provider
+ *
+ * @return The new user, without a password
+ *
+ * @note The email is only kept when the provider vouches for it. If it belongs to an existing
+ *       user, the user must login and link the provider, accounts are never merged by email
+ */
+async fn serv_user_external_create(
+    database: &Client,
+    config: &Config,
+    identity: &ExternalIdentity,
+) -> Result<User, WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+
+    let email = identity
+        .email
+        .as_deref()
+        .filter(|_| identity.email_verified)
+        .map(email_normalize)
+        .unwrap_or_default();
+    if !email.is_empty()
+        && users
+            .find_one(doc! {"email": email.clone()}, None)
+            .await?
+            .is_some()
+    {
+        return Err(WebError::new(
+            StatusCode::CONFLICT,
+            "Email is already registered, login and link the provider instead!".to_string(),
+        ));
+    }
+    if email.is_empty() && config.email_verification.required {
         return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "Username or token error!".to_string(),
+            StatusCode::FORBIDDEN,
+            "Identity provider did not share a verified email!".to_string(),
         ));
     }
 
+    // the first free name among the derived one and a few suffixed ones
+    let base = external_username(identity);
+    let mut username = base.clone();
+    for _ in 0..5 {
+        if users
+            .find_one(doc! {"username": username.clone()}, None)
+            .await?
+            .is_none()
+        {
+            break;
+        }
+        username = format!("{}-{}", base, code_generator());
+    }
+
+    let mut user = User::from(CreateUser {
+        username,
+        password: String::new(),
+        phone: String::new(),
+        email,
+    });
+    user._id = Some(ObjectId::new());
+    user.email_verified = !user.email.is_empty();
+    user_identifiers_normalize(config, &mut user)?;
+    serv_user_conflict_check(database, &user).await?;
+    users.insert_one(&user, None).await?;
+    Ok(user)
+}
+
+/**
+ * Start a login with an identity provider
+ * @param database The database client
+ * @param config The service configuration
+ * @param providers The configured identity providers
+ * @param provider The name of the provider
+ * @param start The login options
+ *
+ * @return The url of the provider the browser is sent to
+ */
+pub async fn serv_user_external_login_start(
+    database: &Client,
+    config: &Config,
+    providers: &IdentityProviders,
+    provider: String,
+    start: ExternalLoginStart,
+) -> Result<ExternalAuthorization, WebError> {
+    let provider = providers.get(&provider)?;
+    serv_external_login_start(database, config, provider, None, start.remember_me).await
+}
+
+/**
+ * Finish a login with an identity provider
+ * @param database The database client
+ * @param config The service configuration
+ * @param attempts The login attempt store
+ * @param providers The configured identity providers
+ * @param provider The name of the provider
+ * @param callback The code and the state given to the callback page
+ * @param client The client which logs in
+ *
+ * @return The same response as `serv_user_login`, the tokens or a 2FA challenge
+ *
+ * @note An account of the provider seen for the first time creates a new user
+ */
+pub async fn serv_user_external_login(
+    database: &Client,
+    config: &Config,
+    attempts: &dyn LoginAttemptStore,
+    providers: &IdentityProviders,
+    provider: String,
+    callback: ExternalCallback,
+    client: ClientInfo,
+) -> Result<LoginResponse, WebError> {
+    let mut event = AuditEvent::new(AuditAction::ExternalLogin, &client);
+    event.detail = Some(format!("Provider {}", provider));
+    let res: Result<LoginResponse, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let identity_provider = providers.get(&provider)?;
+        let state =
+            serv_external_login_consume(database, config, &provider, &callback.state).await?;
+        if state.user_id.is_some() {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Sign-in is invalid or expired!".to_string(),
+            ));
+        }
+        let identity = identity_provider
+            .identity(&state.redirect_uri, &callback.code, &state.code_verifier)
+            .await?;
+        event.set_target_name(&format!("{}:{}", provider, identity.subject));
+
+        let user = match serv_linked_identity_find(database, &provider, &identity.subject).await? {
+            Some(linked) => {
+                let user = users
+                    .find_one(
+                        user_login_filter(config, doc! {"_id": linked.user_id}),
+                        None,
+                    )
+                    .await?
+                    .ok_or_else(user_not_found)?;
+                serv_linked_identity_touch(database, &linked, &identity).await?;
+                user
+            }
+            None => {
+                let user = serv_user_external_create(database, config, &identity).await?;
+                let linked = serv_linked_identity_link(
+                    database,
+                    user._id.unwrap_or_default(),
+                    &provider,
+                    &identity,
+                )
+                .await?;
+                serv_linked_identity_touch(database, &linked, &identity).await?;
+                event.detail = Some(format!("Provider {}, account created", provider));
+                user
+            }
+        };
+        event.set_actor(&user);
+        event.set_target(&user);
+
+        let response =
+            serv_user_login_complete(database, config, attempts, &user, client, state.remember_me)
+                .await?;
+        if let LoginResponse::Challenge(_) = response {
+            event.detail = Some(format!(
+                "{}, second factor required",
+                event.detail.clone().unwrap_or_default()
+            ));
+        }
+        Ok(response)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * List the identity providers linked to the user
+ * @param database The database client
+ * @param auth The authenticated user
+ */
+pub async fn serv_user_identity_list(
+    database: &Client,
+    auth: AuthenticatedUser,
+) -> Result<Vec<LinkedIdentityInfo>, WebError> {
+    auth.own_session()?;
+    serv_linked_identity_list(database, auth.user._id.unwrap_or_default()).await
+}
+
+/**
+ * Start linking an identity provider to the user
+ * @param database The database client
+ * @param config The service configuration
+ * @param providers The configured identity providers
+ * @param auth The authenticated user
+ * @param provider The name of the provider
+ *
+ * @return The url of the provider the browser is sent to
+ */
+pub async fn serv_user_identity_link_start(
+    database: &Client,
+    config: &Config,
+    providers: &IdentityProviders,
+    auth: AuthenticatedUser,
+    provider: String,
+) -> Result<ExternalAuthorization, WebError> {
+    auth.own_session()?;
+    let provider = providers.get(&provider)?;
+    serv_external_login_start(database, config, provider, auth.user._id, false).await
+}
+
+/**
+ * Finish linking an identity provider to the user
+ * @param database The database client
+ * @param config The service configuration
+ * @param providers The configured identity providers
+ * @param auth The authenticated user
+ * @param provider The name of the provider
+ * @param callback The code and the state given to the callback page
+ *
+ * @return The identities linked to the user
+ *
+ * @note The state must have been created by the same user
+ */
+pub async fn serv_user_identity_link(
+    database: &Client,
+    config: &Config,
+    providers: &IdentityProviders,
+    auth: AuthenticatedUser,
+    provider: String,
+    callback: ExternalCallback,
+) -> Result<Vec<LinkedIdentityInfo>, WebError> {
+    let mut event = AuditEvent::by(AuditAction::IdentityLink, &auth);
+    event.detail = Some(format!("Provider {}", provider));
+    let res: Result<Vec<LinkedIdentityInfo>, WebError> = async {
+        auth.own_session()?;
+        let user_id = auth.user._id.unwrap_or_default();
+
+        let identity_provider = providers.get(&provider)?;
+        let state =
+            serv_external_login_consume(database, config, &provider, &callback.state).await?;
+        if state.user_id != Some(user_id) {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Sign-in is invalid or expired!".to_string(),
+            ));
+        }
+        let identity = identity_provider
+            .identity(&state.redirect_uri, &callback.code, &state.code_verifier)
+            .await?;
+        serv_linked_identity_link(database, user_id, &provider, &identity).await?;
+        serv_linked_identity_list(database, user_id).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Unlink an identity provider from the user
+ * @param database The database client
+ * @param auth The authenticated user
+ * @param provider The name of the provider
+ *
+ * @note A user without a password keeps at least one provider, or could not login anymore
+ */
+pub async fn serv_user_identity_unlink(
+    database: &Client,
+    auth: AuthenticatedUser,
+    provider: String,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::by(AuditAction::IdentityUnlink, &auth);
+    event.detail = Some(format!("Provider {}", provider));
+    let res: Result<(), WebError> = async {
+        auth.own_session()?;
+        let user_id = auth.user._id.unwrap_or_default();
+
+        if auth.user.password.is_empty()
+            && serv_linked_identity_list(database, user_id).await?.len() <= 1
+        {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "Set a password before unlinking the last identity provider!".to_string(),
+            ));
+        }
+        serv_linked_identity_unlink(database, user_id, &provider).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Change the role of a user
+ * @param database The database client
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ * @param role The new role
+ *
+ * @return The user profile
+ *
+ * @note Admins can not change their own role, so the last admin can not lock everyone out
+ */
+pub async fn serv_user_role_update(
+    database: &Client,
+    auth: AuthenticatedUser,
+    username: String,
+    role: UpdateRole,
+) -> Result<User, WebError> {
+    let mut event = AuditEvent::new(AuditAction::RoleUpdate, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    event.detail = Some(format!("New role: {}", role.role));
+    let res: Result<User, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        if username == auth.user.username {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "You can not change your own role!".to_string(),
+            ));
+        }
+
+        let mut user = users
+            .find_one_and_update(
+                doc! {"username": username, "is_deprecated": false},
+                doc! {"$set": {"role": role.role}},
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or_else(|| WebError::new(StatusCode::NOT_FOUND, "User not found!".to_string()))?;
+        event.set_target(&user);
+
+        user.password = "".to_string();
+        Ok(user)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Grant the admin role to the configured user, so a fresh deployment has an admin
+ * @param database The database client
+ * @param config The service configuration
+ */
+pub async fn serv_user_role_bootstrap(database: &Client, config: &Config) -> Result<(), WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+
+    if config.admin.bootstrap_username.is_empty() {
+        return Ok(());
+    }
+    let res = users
+        .update_one(
+            doc! {"username": config.admin.bootstrap_username.clone(), "is_deprecated": false},
+            doc! {"$set": {"role": Role::Admin}},
+            None,
+        )
+        .await?;
+
+    // only a real promotion is recorded, not every restart
+    if res.modified_count > 0 {
+        let mut event = AuditEvent::new(AuditAction::RoleBootstrap, &ClientInfo::default());
+        event.set_target_name(&config.admin.bootstrap_username);
+        serv_audit_record(database, event, &Ok::<(), WebError>(())).await;
+    }
     Ok(())
 }
 
+fn user_not_found() -> WebError {
+    WebError::new(StatusCode::NOT_FOUND, "User not found!".to_string())
+}
+
 /**
- * Verify the user
+ * List the users matching a filter
  * @param database The database client
- * @param certificate The certificate of the user
+ * @param auth The authenticated admin
+ * @param filter The filter and the page
+ *
+ * @return A page of users, newest first, passwords are blanked
  */
-pub async fn serv_user_verify(
+pub async fn serv_user_list(
     database: &Client,
-    certificate: CertificateUser,
+    auth: AuthenticatedUser,
+    filter: UserFilter,
+) -> Result<UserPage, WebError> {
+    let mut event = AuditEvent::new(AuditAction::UserList, &auth.client);
+    event.set_actor(&auth.user);
+    event.detail = Some(bson::Document::from(&filter).to_string());
+    let res: Result<UserPage, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let page = filter.page.unwrap_or(1).max(1);
+        let page_size = filter.page_size.unwrap_or(20).clamp(1, 100);
+        let query = bson::Document::from(&filter);
+
+        let total = users.count_documents(query.clone(), None).await?;
+        let mut list: Vec<User> = users
+            .find(
+                query,
+                FindOptions::builder()
+                    .sort(doc! {"register_time": -1})
+                    .skip((page - 1) * page_size)
+                    .limit(page_size as i64)
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        for user in list.iter_mut() {
+            user.password = "".to_string();
+        }
+
+        Ok(UserPage {
+            users: list,
+            total,
+            page,
+            page_size,
+        })
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Get the full record of a user
+ * @param database The database client
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ *
+ * @return The user with its security state, secrets are not included
+ */
+pub async fn serv_user_find(
+    database: &Client,
+    auth: AuthenticatedUser,
+    username: String,
+) -> Result<AdminUser, WebError> {
+    let mut event = AuditEvent::new(AuditAction::UserView, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    let res: Result<AdminUser, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        let mut user = users
+            .find_one(
+                doc! {"username": username},
+                FindOneOptions::builder()
+                    .sort(doc! {"is_deprecated": 1})
+                    .build(),
+            )
+            .await?
+            .ok_or_else(user_not_found)?;
+        event.set_target(&user);
+        user.password = "".to_string();
+
+        let user_id = user._id.unwrap_or_default();
+        Ok(AdminUser {
+            two_factor_enabled: serv_two_factor_enabled(database, user_id).await?,
+            sessions: serv_session_count(database, user_id).await?,
+            user,
+        })
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Close every session of a user
+ * @param database The database client
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ */
+pub async fn serv_user_force_logout(
+    database: &Client,
+    auth: AuthenticatedUser,
+    username: String,
 ) -> Result<(), WebError> {
-    let res = serv_user_token_verify(database, certificate.username.clone()).await?;
+    let mut event = AuditEvent::new(AuditAction::ForceLogout, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
 
-    if res.token != certificate.token {
-        return Err(WebError::new(
-            StatusCode::UNAUTHORIZED,
-            "Token error!".to_string(),
-        ));
+        let user = users
+            .find_one(doc! {"username": username, "is_deprecated": false}, None)
+            .await?
+            .ok_or_else(user_not_found)?;
+        event.set_target(&user);
+        serv_session_revoke_all(database, user._id.unwrap_or_default()).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Start impersonating a user
+ * @param database The database client
+ * @param config The service configuration
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ *
+ * @return A short-lived access token of the user, carrying the admin as its actor
+ *
+ * @note Only the login session of an admin can start it, admins can not be impersonated.
+ *       Everything done with the token is audited along with the admin
+ */
+pub async fn serv_user_impersonate_start(
+    database: &Client,
+    config: &Config,
+    auth: AuthenticatedUser,
+    username: String,
+) -> Result<ImpersonationToken, WebError> {
+    let mut event = AuditEvent::new(AuditAction::ImpersonationStart, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    let res: Result<ImpersonationToken, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+        auth.own_session()?;
+
+        if username == auth.user.username {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "You can not impersonate yourself!".to_string(),
+            ));
+        }
+        let user = users
+            .find_one(doc! {"username": username, "is_deprecated": false}, None)
+            .await?
+            .ok_or_else(user_not_found)?;
+        event.set_target(&user);
+        if user.role.has(Permission::UsersImpersonate) {
+            return Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "Admins can not be impersonated!".to_string(),
+            ));
+        }
+        if user.is_suspended {
+            return Err(WebError::new(
+                StatusCode::FORBIDDEN,
+                "Account is suspended!".to_string(),
+            ));
+        }
+
+        let session =
+            serv_session_impersonate(database, config, &auth.user, &user, auth.client.clone())
+                .await?;
+        let claims = session_claims(config, &session, &user);
+        Ok(ImpersonationToken {
+            access_token: access_token_issue(&claims, &config.token)?,
+            token_type: "Bearer".to_string(),
+            expires_in: claims.exp - claims.iat,
+            username: user.username,
+        })
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Stop an impersonation, its session is closed
+ * @param database The database client
+ * @param auth The impersonated user, authenticated by the token of the impersonation
+ *
+ * @note Recorded as done by the admin
+ */
+pub async fn serv_user_impersonate_stop(
+    database: &Client,
+    auth: AuthenticatedUser,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::by(AuditAction::ImpersonationStop, &auth);
+    let res: Result<(), WebError> = async {
+        let session = auth.impersonation().ok_or_else(|| {
+            WebError::new(
+                StatusCode::BAD_REQUEST,
+                "You are not impersonating anyone!".to_string(),
+            )
+        })?;
+        event.actor = session.impersonator.clone();
+        event.actor_id = session.impersonator_id;
+        serv_session_revoke(database, session._id.unwrap_or_default()).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Suspend or unsuspend a user
+ * @param database The database client
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ * @param suspended Whether the user is suspended
+ *
+ * @note Suspending closes every session of the user
+ */
+pub async fn serv_user_suspend(
+    database: &Client,
+    auth: AuthenticatedUser,
+    username: String,
+    suspended: bool,
+) -> Result<(), WebError> {
+    let action = if suspended {
+        AuditAction::Suspend
     } else {
+        AuditAction::Unsuspend
+    };
+    let mut event = AuditEvent::new(action, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        if username == auth.user.username {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "You can not suspend yourself!".to_string(),
+            ));
+        }
+
+        let user = users
+            .find_one_and_update(
+                doc! {"username": username, "is_deprecated": false},
+                doc! {"$set": {"is_suspended": suspended}},
+                None,
+            )
+            .await?
+            .ok_or_else(user_not_found)?;
+        event.set_target(&user);
+        if suspended {
+            serv_session_revoke_all(database, user._id.unwrap_or_default()).await?;
+        }
         Ok(())
     }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Restore a user deleted by `serv_user_delete`
+ * @param database The database client
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ *
+ * @return The user profile
+ *
+ * @note An anonymized user can not be restored, its data is gone
+ */
+pub async fn serv_user_restore(
+    database: &Client,
+    auth: AuthenticatedUser,
+    username: String,
+) -> Result<User, WebError> {
+    let mut event = AuditEvent::new(AuditAction::Restore, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    let res: Result<User, WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        if users
+            .find_one(
+                doc! {"username": username.clone(), "is_deprecated": false},
+                None,
+            )
+            .await?
+            .is_some()
+        {
+            return Err(WebError::new(
+                StatusCode::CONFLICT,
+                "Username is taken by another user!".to_string(),
+            ));
+        }
+
+        let mut user = users
+            .find_one_and_update(
+                doc! {"username": username, "is_deprecated": true, "is_anonymized": {"$ne": true}},
+                doc! {"$set": {"is_deprecated": false}, "$unset": {"delete_time": ""}},
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or_else(user_not_found)?;
+        event.set_target(&user);
+
+        user.password = "".to_string();
+        Ok(user)
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Permanently remove a user and every record attached to it
+ * @param database The database client
+ * @param auth The authenticated admin
+ * @param username The username of the user
+ *
+ * @note Security events are kept, they are not linked to a live account anymore
+ */
+pub async fn serv_user_purge(
+    database: &Client,
+    auth: AuthenticatedUser,
+    username: String,
+) -> Result<(), WebError> {
+    let mut event = AuditEvent::new(AuditAction::Purge, &auth.client);
+    event.set_actor(&auth.user);
+    event.set_target_name(&username);
+    let res: Result<(), WebError> = async {
+        let users: mongodb::Collection<User> = serv_user_database(database);
+
+        if username == auth.user.username {
+            return Err(WebError::new(
+                StatusCode::BAD_REQUEST,
+                "You can not purge yourself!".to_string(),
+            ));
+        }
+
+        let user = users
+            .find_one_and_delete(
+                doc! {"username": username},
+                FindOneAndDeleteOptions::builder()
+                    .sort(doc! {"is_deprecated": -1})
+                    .build(),
+            )
+            .await?
+            .ok_or_else(user_not_found)?;
+        event.set_target(&user);
+
+        serv_user_records_remove(database, user._id.unwrap_or_default()).await
+    }
+    .await;
+    serv_audit_record(database, event, &res).await;
+    res
+}
+
+/**
+ * Remove every record attached to a user, the user itself is left to the caller
+ * @param database The database client
+ * @param user_id The id of the user
+ */
+async fn serv_user_records_remove(database: &Client, user_id: ObjectId) -> Result<(), WebError> {
+    serv_session_revoke_all(database, user_id).await?;
+    serv_password_reset_revoke_all(database, user_id).await?;
+    serv_email_verification_revoke_all(database, user_id).await?;
+    serv_login_challenge_revoke_all(database, user_id).await?;
+    serv_two_factor_disable(database, user_id).await?;
+    serv_access_token_revoke_all(database, user_id).await?;
+    serv_oauth_revoke_all(database, user_id).await?;
+    serv_linked_identity_revoke_all(database, user_id).await?;
+    Ok(())
+}
+
+/**
+ * Anonymize or purge the deleted users whose grace period is over
+ * @param database The database client
+ * @param config The service configuration
+ *
+ * @return The number of processed users
+ *
+ * @note An anonymized user keeps its id and its content lists, the personal data is erased
+ *       and the username is freed. A purged user is removed like `serv_user_purge` does
+ */
+pub async fn serv_user_deletion_cleanup(
+    database: &Client,
+    config: &Config,
+) -> Result<u64, WebError> {
+    let users: mongodb::Collection<User> = serv_user_database(database);
+    let now = Utc::now().timestamp();
+
+    // users deleted before the grace period existed start it now
+    users
+        .update_many(
+            doc! {"is_deprecated": true, "is_anonymized": {"$ne": true}, "delete_time": null},
+            doc! {"$set": {"delete_time": now}},
+            None,
+        )
+        .await?;
+
+    let expired: Vec<User> = users
+        .find(
+            doc! {
+                "is_deprecated": true,
+                "is_anonymized": {"$ne": true},
+                "delete_time": {"$lt": now - config.account_deletion.grace_period},
+            },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+
+    let mut count = 0;
+    for user in expired {
+        let action = match config.account_deletion.mode {
+            DeletionMode::Anonymize => AuditAction::Anonymize,
+            DeletionMode::Purge => AuditAction::Purge,
+        };
+        let mut event = AuditEvent::new(action, &ClientInfo::default());
+        event.set_target(&user);
+        event.detail = Some("Deletion grace period is over".to_string());
+        let res: Result<(), WebError> = async {
+            let user_id = user._id.unwrap_or_default();
+            match config.account_deletion.mode {
+                DeletionMode::Anonymize => {
+                    users
+                        .update_one(
+                            doc! {"_id": user_id},
+                            doc! {"$set": {
+                                "username": format!("deleted-{}", user_id.to_hex()),
+                                "password": "",
+                                "gender": Gender::Other,
+                                "education": Education::Other,
+                                "description": "",
+                                "avatar": "",
+                                "school": "",
+                                "major": "",
+                                "phone": "",
+                                "phone_verified": false,
+                                "email": "",
+                                "email_verified": false,
+                                "following": [],
+                                "collection": [],
+                                "is_anonymized": true,
+                            }},
+                            None,
+                        )
+                        .await?;
+                }
+                DeletionMode::Purge => {
+                    users.delete_one(doc! {"_id": user_id}, None).await?;
+                }
+            }
+            serv_user_records_remove(database, user_id).await
+        }
+        .await;
+        serv_audit_record(database, event, &res).await;
+        res?;
+        count += 1;
+    }
+    Ok(count)
 }
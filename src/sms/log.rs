@@ -0,0 +1,26 @@
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::{
+    errors::WebError,
+    sms::{Sms, SmsSender},
+};
+
+/**
+ * Development sender, prints the messages to stdout
+ */
+#[derive(Default)]
+pub struct LogSmsSender;
+
+#[async_trait]
+impl SmsSender for LogSmsSender {
+    async fn send(&self, sms: Sms) -> Result<(), WebError> {
+        println!(
+            "[{}] SMS to {}: {}",
+            Utc::now().to_rfc3339(),
+            sms.to,
+            sms.text
+        );
+        Ok(())
+    }
+}
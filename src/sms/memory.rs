@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::{
+    errors::WebError,
+    sms::{Sms, SmsSender},
+};
+
+/**
+ * Test sender, keeps every sent message in memory
+ */
+#[derive(Default)]
+pub struct MemorySmsSender {
+    messages: Mutex<Vec<Sms>>,
+}
+
+impl MemorySmsSender {
+    /**
+     * Get the messages sent so far
+     */
+    pub async fn messages(&self) -> Vec<Sms> {
+        self.messages.lock().await.clone()
+    }
+}
+
+#[async_trait]
+impl SmsSender for MemorySmsSender {
+    async fn send(&self, sms: Sms) -> Result<(), WebError> {
+        self.messages.lock().await.push(sms);
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+pub mod log;
+pub mod memory;
+
+use async_trait::async_trait;
+
+use crate::errors::WebError;
+
+/**
+ * A text message
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sms {
+    pub to: String,
+    pub text: String,
+}
+
+/**
+ * Sends the text messages of the service (phone verification, login codes...)
+ */
+#[async_trait]
+pub trait SmsSender: Send + Sync {
+    async fn send(&self, sms: Sms) -> Result<(), WebError>;
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, DateTime},
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument, UpdateModifications},
+    Client, IndexModel,
+};
+use tokio::sync::Mutex;
+
+use crate::{config::LoginThrottleConfig, errors::WebError, models::login_attempts::LoginAttempt};
+
+/**
+ * Storage of failed login attempts
+ */
+#[async_trait]
+pub trait LoginAttemptStore: Send + Sync {
+    /**
+     * Get the attempts of a key
+     * @param key The username or ip key
+     */
+    async fn get(&self, key: &str) -> Result<Option<LoginAttempt>, WebError>;
+
+    /**
+     * Count a failure of a key atomically
+     * @param key The username or ip key
+     * @param now The current timestamp
+     * @param window_start Failures before this timestamp are forgotten
+     *
+     * @return The attempts including this failure
+     *
+     * @note The count starts over once the window or the previous lockout is over
+     */
+    async fn increment(
+        &self,
+        key: &str,
+        now: i64,
+        window_start: i64,
+    ) -> Result<LoginAttempt, WebError>;
+
+    /**
+     * Give back an attempt counted by `increment` whose credentials were right
+     * @param key The username or ip key
+     */
+    async fn release(&self, key: &str) -> Result<(), WebError>;
+
+    /**
+     * Lock a key, an earlier lock which lasts longer is kept
+     * @param key The username or ip key
+     * @param until The timestamp the lock ends at
+     */
+    async fn lock(&self, key: &str, until: i64) -> Result<(), WebError>;
+
+    /**
+     * Forget the attempts of a key
+     * @param key The username or ip key
+     */
+    async fn remove(&self, key: &str) -> Result<(), WebError>;
+}
+
+/**
+ * Keeps the attempts in memory, they are lost on restart and not shared between instances
+ */
+#[derive(Default)]
+pub struct MemoryLoginAttemptStore {
+    attempts: Mutex<HashMap<String, LoginAttempt>>,
+}
+
+#[async_trait]
+impl LoginAttemptStore for MemoryLoginAttemptStore {
+    async fn get(&self, key: &str) -> Result<Option<LoginAttempt>, WebError> {
+        Ok(self.attempts.lock().await.get(key).cloned())
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        now: i64,
+        window_start: i64,
+    ) -> Result<LoginAttempt, WebError> {
+        let mut attempts = self.attempts.lock().await;
+        let attempt = attempts
+            .entry(key.to_string())
+            .or_insert_with(|| LoginAttempt {
+                _id: key.to_string(),
+                ..Default::default()
+            });
+        attempt.count_failure(now, window_start);
+        Ok(attempt.clone())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), WebError> {
+        if let Some(attempt) = self.attempts.lock().await.get_mut(key) {
+            attempt.failures = attempt.failures.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    async fn lock(&self, key: &str, until: i64) -> Result<(), WebError> {
+        if let Some(attempt) = self.attempts.lock().await.get_mut(key) {
+            attempt.locked_until = attempt.locked_until.max(until);
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), WebError> {
+        self.attempts.lock().await.remove(key);
+        Ok(())
+    }
+}
+
+/**
+ * Keeps the attempts in the login_attempts collection
+ *
+ * @note Every document carries an expire_date, the database drops it once
+ *       neither its failures nor its lock matter anymore
+ */
+pub struct MongoLoginAttemptStore {
+    attempts: mongodb::Collection<LoginAttempt>,
+    // seconds a document is kept after its last failure
+    retention: i64,
+}
+
+impl MongoLoginAttemptStore {
+    pub fn new(database: &Client, config: &LoginThrottleConfig) -> Self {
+        MongoLoginAttemptStore {
+            attempts: database.database("test").collection("login_attempts"),
+            retention: config.failure_window + config.lockout_duration,
+        }
+    }
+
+    /**
+     * Create the TTL index of the login_attempts collection
+     * @param database The database client
+     *
+     * @note Keys are chosen by the clients, without it the collection would grow without bound
+     */
+    pub async fn create_indexes(database: &Client) -> Result<(), WebError> {
+        database
+            .database("test")
+            .collection::<LoginAttempt>("login_attempts")
+            .create_indexes(
+                vec![IndexModel::builder()
+                    .keys(doc! {"expire_date": 1})
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(std::time::Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build()],
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for MongoLoginAttemptStore {
+    async fn get(&self, key: &str) -> Result<Option<LoginAttempt>, WebError> {
+        Ok(self.attempts.find_one(doc! {"_id": key}, None).await?)
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        now: i64,
+        window_start: i64,
+    ) -> Result<LoginAttempt, WebError> {
+        // the same rule as LoginAttempt::count_failure, evaluated by the database
+        let stale = doc! {"$or": [
+            {"$lt": [{"$ifNull": ["$last_failure", 0]}, window_start]},
+            {"$and": [
+                {"$ne": [{"$ifNull": ["$locked_until", 0]}, 0]},
+                {"$lte": [{"$ifNull": ["$locked_until", 0]}, now]},
+            ]},
+        ]};
+        let update = vec![doc! {"$set": {
+            "failures": {"$cond": [stale.clone(), 1, {"$add": [{"$ifNull": ["$failures", 0]}, 1]}]},
+            "locked_until": {"$cond": [stale, 0, {"$ifNull": ["$locked_until", 0]}]},
+            "last_failure": now,
+            "expire_date": DateTime::from_millis((now + self.retention) * 1000),
+        }}];
+        self.attempts
+            .find_one_and_update(
+                doc! {"_id": key},
+                UpdateModifications::Pipeline(update),
+                FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or_else(|| {
+                WebError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to count the login failure!".to_string(),
+                )
+            })
+    }
+
+    async fn release(&self, key: &str) -> Result<(), WebError> {
+        self.attempts
+            .update_one(
+                doc! {"_id": key, "failures": {"$gt": 0}},
+                doc! {"$inc": {"failures": -1}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn lock(&self, key: &str, until: i64) -> Result<(), WebError> {
+        self.attempts
+            .update_one(
+                doc! {"_id": key},
+                doc! {"$max": {"locked_until": until}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), WebError> {
+        self.attempts.delete_one(doc! {"_id": key}, None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1 @@
+pub mod login_attempts;
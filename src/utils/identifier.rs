@@ -0,0 +1,122 @@
+/**
+ * What a login identifier refers to, already normalized
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    Email(String),
+    Phone(String),
+    Username(String),
+}
+
+impl Identifier {
+    /**
+     * Get the user field matched by the identifier
+     */
+    pub fn field(&self) -> &'static str {
+        match self {
+            Identifier::Email(_) => "email",
+            Identifier::Phone(_) => "phone",
+            Identifier::Username(_) => "username",
+        }
+    }
+
+    /**
+     * Get the normalized value of the identifier
+     */
+    pub fn value(&self) -> &str {
+        match self {
+            Identifier::Email(value) | Identifier::Phone(value) | Identifier::Username(value) => {
+                value
+            }
+        }
+    }
+}
+
+/**
+ * Normalize an email, addresses are compared case-insensitively
+ * @param email The email typed by the user
+ */
+pub fn email_normalize(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/**
+ * Normalize a phone number to the E.164 format, e.g. "+8613800138000"
+ * @param phone The phone number typed by the user
+ * @param country_code The country code of numbers typed without one
+ *
+ * @return The normalized number, none if it is not a phone number
+ */
+pub fn phone_normalize(phone: &str, country_code: &str) -> Option<String> {
+    let phone: String = phone
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.'))
+        .collect();
+
+    let number = if let Some(number) = phone.strip_prefix('+') {
+        number.to_string()
+    } else if let Some(number) = phone.strip_prefix("00") {
+        number.to_string()
+    } else {
+        // drop the trunk prefix of national numbers
+        format!("{}{}", country_code, phone.trim_start_matches('0'))
+    };
+
+    let valid = (8..=15).contains(&number.len()) && number.chars().all(|c| c.is_ascii_digit());
+    valid.then(|| format!("+{}", number))
+}
+
+/**
+ * Detect whether a login identifier is an email, a phone number or a username
+ * @param identifier The identifier typed by the user
+ * @param country_code The country code of numbers typed without one
+ */
+pub fn identifier_parse(identifier: &str, country_code: &str) -> Identifier {
+    let identifier = identifier.trim();
+    if identifier.contains('@') {
+        return Identifier::Email(email_normalize(identifier));
+    }
+    // a phone number is made of digits and separators only
+    let is_phone = identifier
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')' | '.'))
+        && identifier.chars().filter(|c| c.is_ascii_digit()).count() >= 7;
+    match phone_normalize(identifier, country_code) {
+        Some(phone) if is_phone => Identifier::Phone(phone),
+        _ => Identifier::Username(identifier.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod identifier_test {
+    use super::*;
+
+    #[test]
+    fn test_identifier_parse() {
+        assert_eq!(
+            identifier_parse(" Dessera@Example.COM ", "86"),
+            Identifier::Email("dessera@example.com".to_string())
+        );
+        assert_eq!(
+            identifier_parse("138 0013 8000", "86"),
+            Identifier::Phone("+8613800138000".to_string())
+        );
+        assert_eq!(
+            identifier_parse("+86 138-0013-8000", "86"),
+            Identifier::Phone("+8613800138000".to_string())
+        );
+        assert_eq!(
+            identifier_parse("008613800138000", "86"),
+            Identifier::Phone("+8613800138000".to_string())
+        );
+        assert_eq!(
+            identifier_parse("dessera", "86"),
+            Identifier::Username("dessera".to_string())
+        );
+        assert_eq!(
+            identifier_parse("dessera2023", "86"),
+            Identifier::Username("dessera2023".to_string())
+        );
+    }
+}
@@ -1 +1,7 @@
-pub mod token;
\ No newline at end of file
+pub mod identifier;
+pub mod oauth;
+pub mod password;
+pub mod password_policy;
+pub mod token;
+pub mod totp;
+pub mod user_agent;
@@ -0,0 +1,135 @@
+use data_encoding::BASE64URL_NOPAD;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::models::oauth::OAUTH_SCOPES;
+
+/**
+ * Compute the PKCE code challenge of a verifier (RFC 7636, S256)
+ * @param verifier The code verifier
+ */
+pub fn pkce_challenge(verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()))
+}
+
+/**
+ * Check a PKCE code verifier against the challenge of the authorization request (RFC 7636, S256)
+ * @param verifier The code verifier sent to the token endpoint
+ * @param challenge The code challenge sent to the authorization endpoint
+ */
+pub fn pkce_verify(verifier: &str, challenge: &str) -> bool {
+    // 43 to 128 characters of [A-Z] / [a-z] / [0-9] / "-" / "." / "_" / "~"
+    let valid = (43..=128).contains(&verifier.len())
+        && verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+    let computed = pkce_challenge(verifier);
+    valid && bool::from(computed.as_bytes().ct_eq(challenge.as_bytes()))
+}
+
+/**
+ * Check the redirect uri of a client registration
+ * @param uri The redirect uri
+ *
+ * @note Only https is accepted, except for the loopback addresses of native apps and local tests
+ */
+pub fn redirect_uri_valid(uri: &str) -> bool {
+    let loopback = ["http://localhost", "http://127.0.0.1", "http://[::1]"];
+    let secure = uri.starts_with("https://")
+        || loopback.iter().any(|prefix| {
+            uri.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with([':', '/']))
+        });
+    secure && !uri.contains('#') && uri.len() <= 2048
+}
+
+/**
+ * Split a space separated scope parameter
+ * @param scope The scope parameter
+ *
+ * @return The scopes without duplicates, none if a scope is not supported
+ */
+pub fn oauth_scope_parse(scope: &str) -> Option<Vec<String>> {
+    let mut scopes: Vec<String> = vec![];
+    for scope in scope.split_whitespace() {
+        if !OAUTH_SCOPES.contains(&scope) {
+            return None;
+        }
+        if !scopes.iter().any(|known| known == scope) {
+            scopes.push(scope.to_string());
+        }
+    }
+    Some(scopes)
+}
+
+/**
+ * Append query parameters to a url
+ * @param url The url, which may already hold a query
+ * @param pairs The parameters
+ */
+pub fn url_with_query(url: &str, pairs: &[(&str, &str)]) -> String {
+    let query: Vec<String> = pairs
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, NON_ALPHANUMERIC),
+                utf8_percent_encode(value, NON_ALPHANUMERIC)
+            )
+        })
+        .collect();
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", url, separator, query.join("&"))
+}
+
+#[cfg(test)]
+mod oauth_test {
+    use super::*;
+
+    #[test]
+    fn test_pkce_verify() {
+        // example of RFC 7636 appendix B
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        assert!(pkce_verify(verifier, challenge));
+        assert!(!pkce_verify(
+            verifier,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cm"
+        ));
+        assert!(!pkce_verify("short", challenge));
+        assert_eq!(pkce_challenge(verifier), challenge);
+    }
+
+    #[test]
+    fn test_redirect_uri_valid() {
+        assert!(redirect_uri_valid("https://forum.mlum.com/callback"));
+        assert!(redirect_uri_valid("http://localhost:3000/callback"));
+        assert!(redirect_uri_valid("http://127.0.0.1/callback"));
+        assert!(!redirect_uri_valid("http://forum.mlum.com/callback"));
+        assert!(!redirect_uri_valid("http://localhost.evil.com/callback"));
+        assert!(!redirect_uri_valid("https://forum.mlum.com/callback#token"));
+    }
+
+    #[test]
+    fn test_oauth_scope_parse() {
+        assert_eq!(
+            oauth_scope_parse("openid  profile openid"),
+            Some(vec!["openid".to_string(), "profile".to_string()])
+        );
+        assert_eq!(oauth_scope_parse(""), Some(vec![]));
+        assert_eq!(oauth_scope_parse("openid admin"), None);
+    }
+
+    #[test]
+    fn test_url_with_query() {
+        assert_eq!(
+            url_with_query("https://a.com/cb", &[("code", "x y"), ("state", "1")]),
+            "https://a.com/cb?code=x%20y&state=1"
+        );
+        assert_eq!(
+            url_with_query("https://a.com/cb?app=1", &[("code", "x")]),
+            "https://a.com/cb?app=1&code=x"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use actix_web::http::StatusCode;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+
+use subtle::ConstantTimeEq;
+
+use crate::{config::PasswordHashConfig, errors::WebError};
+
+/**
+ * Build the Argon2id hasher from the configured cost parameters
+ * @param config The password hash configuration
+ */
+fn password_hasher(config: &PasswordHashConfig) -> Result<Argon2<'static>, WebError> {
+    let params = Params::new(
+        config.memory_cost,
+        config.time_cost,
+        config.parallelism,
+        None,
+    )
+    .map_err(|err| {
+        WebError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Argon2 params error: {}", err),
+        )
+    })?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/**
+ * Hash a password with Argon2id
+ * @param password The plaintext password
+ * @param config The password hash configuration
+ *
+ * @return The PHC string of the hash
+ */
+pub fn password_hash(password: &str, config: &PasswordHashConfig) -> Result<String, WebError> {
+    let salt = SaltString::generate(&mut OsRng);
+    password_hasher(config)?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| {
+            WebError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Argon2 hash error: {}", err),
+            )
+        })
+}
+
+/**
+ * Check whether a stored password is a PHC hash or a legacy plaintext password
+ * @param stored The password stored in the database
+ */
+pub fn password_is_hashed(stored: &str) -> bool {
+    PasswordHash::new(stored).is_ok()
+}
+
+/**
+ * Verify a password against the stored one
+ * @param password The plaintext password
+ * @param stored The password stored in the database, hashed or legacy plaintext
+ *
+ * @note Legacy plaintext passwords are compared directly,
+ *       the caller should rehash them after a successful login
+ */
+pub fn password_verify(password: &str, stored: &str) -> bool {
+    match PasswordHash::new(stored) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => !stored.is_empty() && bool::from(stored.as_bytes().ct_eq(password.as_bytes())),
+    }
+}
+
+/**
+ * Spend the time of a password verification when there is no user to verify against
+ * @param password The plaintext password
+ * @param config The password hash configuration
+ *
+ * @return Always false
+ *
+ * @note Hashing costs as much as verifying against a hash of the same parameters,
+ *       so the response time does not reveal whether the user exists
+ */
+pub fn password_verify_dummy(password: &str, config: &PasswordHashConfig) -> bool {
+    let _ = password_hash(password, config);
+    false
+}
+
+/**
+ * Check whether a stored hash should be recomputed,
+ * either because it is legacy plaintext or because the cost parameters changed
+ * @param stored The password stored in the database
+ * @param config The password hash configuration
+ */
+pub fn password_needs_rehash(stored: &str, config: &PasswordHashConfig) -> bool {
+    let hash = match PasswordHash::new(stored) {
+        Ok(hash) => hash,
+        Err(_) => return true,
+    };
+    let params = match Params::try_from(&hash) {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+    hash.algorithm != Algorithm::Argon2id.ident()
+        || params.m_cost() != config.memory_cost
+        || params.t_cost() != config.time_cost
+        || params.p_cost() != config.parallelism
+}
+
+#[cfg(test)]
+mod password_test {
+    use super::*;
+
+    fn test_config() -> PasswordHashConfig {
+        PasswordHashConfig {
+            memory_cost: 1024,
+            time_cost: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_password_hash_verify() {
+        let hash = password_hash("123456", &test_config()).unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(password_is_hashed(&hash));
+        assert!(password_verify("123456", &hash));
+        assert!(!password_verify("654321", &hash));
+        assert!(!password_needs_rehash(&hash, &test_config()));
+        assert!(password_needs_rehash(&hash, &PasswordHashConfig::default()));
+    }
+
+    #[test]
+    fn test_password_legacy_plaintext() {
+        assert!(!password_is_hashed("123456"));
+        assert!(password_verify("123456", "123456"));
+        assert!(!password_verify("", ""));
+        assert!(!password_verify("12345", "123456"));
+        assert!(!password_verify_dummy("123456", &test_config()));
+        assert!(password_needs_rehash("123456", &test_config()));
+    }
+}
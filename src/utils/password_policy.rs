@@ -0,0 +1,427 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use actix_web::http::StatusCode;
+use chrono::{Datelike, Utc};
+
+use crate::{
+    config::PasswordPolicyConfig,
+    errors::{WebError, WebErrorDetail},
+};
+
+// most used passwords first, the rank is the number of guesses an attacker needs
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+// longest part of a password matched against the patterns, keeps the estimate linear
+const MAX_PATTERN_LENGTH: usize = 32;
+
+const KEYBOARD_ROWS: &[&str] = &[
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+    "1qaz2wsx3edc4rfv5tgb6yhn7ujm8ik,9ol.0p;/",
+];
+
+/**
+ * Get the rank of every common password
+ */
+fn common_passwords() -> &'static HashMap<&'static str, usize> {
+    static RANKS: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        let mut ranks = HashMap::new();
+        for (rank, password) in COMMON_PASSWORDS.lines().map(str::trim).enumerate() {
+            if !password.is_empty() {
+                ranks.entry(password).or_insert(rank + 1);
+            }
+        }
+        ranks
+    })
+}
+
+/**
+ * Check whether a password is one of the bundled common passwords
+ * @param password The plaintext password
+ */
+pub fn password_is_common(password: &str) -> bool {
+    common_passwords().contains_key(password.to_lowercase().as_str())
+}
+
+/**
+ * Undo the usual letter substitutions, e.g. "p@ssw0rd" to "password"
+ * @param word The lowercase word
+ */
+fn unleet(word: &str) -> String {
+    word.chars()
+        .map(|c| match c {
+            '4' | '@' => 'a',
+            '8' => 'b',
+            '(' | '{' | '[' | '<' => 'c',
+            '3' => 'e',
+            '6' | '9' => 'g',
+            '1' | '!' | '|' => 'i',
+            '0' => 'o',
+            '5' | '$' => 's',
+            '7' | '+' => 't',
+            '2' => 'z',
+            c => c,
+        })
+        .collect()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/**
+ * Number of ways to capitalize a word the same way, "Password" is tried before "pAssword"
+ * @param word The word as typed
+ */
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        return 1.0;
+    }
+    if lower == 0 || (upper == 1 && word[0].is_uppercase()) {
+        return 2.0;
+    }
+    (1..=upper.min(lower))
+        .map(|i| binomial(upper + lower, i))
+        .sum()
+}
+
+/**
+ * Guesses needed for a single character of the class of `c`
+ */
+fn char_cardinality(c: char) -> f64 {
+    if c.is_ascii_digit() {
+        10.0
+    } else if c.is_alphabetic() {
+        26.0
+    } else {
+        33.0
+    }
+}
+
+/**
+ * Guesses needed for a part of the password recognized as a dictionary word
+ * @param part The part as typed
+ * @param inputs The lowercase words of the user, tried first
+ */
+fn dictionary_guesses(part: &[char], inputs: &[String]) -> Option<f64> {
+    let lower: String = part.iter().collect::<String>().to_lowercase();
+    let reversed: String = lower.chars().rev().collect();
+    let rank = |word: &str| {
+        inputs
+            .iter()
+            .position(|input| input == word)
+            .map(|index| index + 1)
+            .or_else(|| common_passwords().get(word).copied())
+    };
+
+    let mut best: Option<f64> = None;
+    for (word, factor) in [(lower.clone(), 1.0), (reversed, 2.0)] {
+        let leet = unleet(&word);
+        let found = rank(&word)
+            .map(|rank| rank as f64 * factor)
+            .or_else(|| rank(&leet).map(|rank| rank as f64 * factor * 2.0));
+        if let Some(guesses) = found {
+            let guesses = guesses * uppercase_variations(part);
+            best = Some(best.map_or(guesses, |best: f64| best.min(guesses)));
+        }
+    }
+    best
+}
+
+/**
+ * Guesses needed for a run of one repeated character, e.g. "aaaa"
+ */
+fn repeat_guesses(part: &[char]) -> Option<f64> {
+    (part.len() >= 3 && part.iter().all(|c| *c == part[0]))
+        .then(|| char_cardinality(part[0]) * part.len() as f64)
+}
+
+/**
+ * Guesses needed for an alphabetic or numeric sequence, e.g. "abcd" or "9876"
+ */
+fn sequence_guesses(part: &[char]) -> Option<f64> {
+    if part.len() < 3 {
+        return None;
+    }
+    let delta = part[1] as i64 - part[0] as i64;
+    let sequence = (delta == 1 || delta == -1)
+        && part
+            .windows(2)
+            .all(|pair| pair[1] as i64 - pair[0] as i64 == delta)
+        && part.iter().all(|c| c.is_ascii_alphanumeric());
+    if !sequence {
+        return None;
+    }
+    let base = if "aAzZ019".contains(part[0]) {
+        4.0
+    } else {
+        char_cardinality(part[0])
+    };
+    let direction = if delta < 0 { 2.0 } else { 1.0 };
+    Some(base * part.len() as f64 * direction)
+}
+
+/**
+ * Guesses needed for adjacent keys of a keyboard row, e.g. "qwer" or "1qaz"
+ */
+fn keyboard_guesses(part: &[char]) -> Option<f64> {
+    if part.len() < 4 {
+        return None;
+    }
+    let lower: String = part.iter().collect::<String>().to_lowercase();
+    let reversed: String = lower.chars().rev().collect();
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| row.contains(&lower) || row.contains(&reversed))
+        .then_some(94.0 * part.len() as f64)
+}
+
+/**
+ * Guesses needed for a year around the current one, e.g. "2003"
+ */
+fn year_guesses(part: &[char]) -> Option<f64> {
+    if part.len() != 4 || !part.iter().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let year: i32 = part.iter().collect::<String>().parse().ok()?;
+    (1900..=2099)
+        .contains(&year)
+        .then(|| ((year - Utc::now().year()).abs() as f64).max(20.0))
+}
+
+/**
+ * Estimate how many guesses an attacker needs to find a password
+ * @param password The plaintext password
+ * @param inputs Words of the user an attacker knows, e.g. the username
+ *
+ * @return The base 10 logarithm of the guesses
+ *
+ * @note Follows the idea of zxcvbn: the password is split into known patterns
+ *       (common passwords, user inputs, l33t, repeats, sequences, keyboard rows, years)
+ *       and brute forced characters, the cheapest split gives the estimate
+ */
+pub fn password_guesses_log10(password: &str, inputs: &[&str]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let inputs: Vec<String> = inputs
+        .iter()
+        .map(|input| input.trim().to_lowercase())
+        .filter(|input| input.chars().count() >= 3)
+        .collect();
+
+    // best[j] is the cheapest estimate of the first j characters
+    let mut best = vec![0.0_f64; chars.len() + 1];
+    for end in 1..=chars.len() {
+        // a brute forced character
+        best[end] = best[end - 1] + 1.0;
+        for start in end.saturating_sub(MAX_PATTERN_LENGTH)..end {
+            let part = &chars[start..end];
+            let guesses = [
+                dictionary_guesses(part, &inputs),
+                repeat_guesses(part),
+                sequence_guesses(part),
+                keyboard_guesses(part),
+                year_guesses(part),
+            ]
+            .into_iter()
+            .flatten()
+            .fold(f64::INFINITY, f64::min);
+            if guesses.is_finite() {
+                // a known pattern still costs more than a single guess
+                let minimum = if part.len() == 1 { 10.0 } else { 50.0 };
+                best[end] = best[end].min(best[start] + guesses.max(minimum).log10());
+            }
+        }
+    }
+    best[chars.len()]
+}
+
+/**
+ * Score the strength of a password like zxcvbn
+ * @param password The plaintext password
+ * @param inputs Words of the user an attacker knows, e.g. the username
+ *
+ * @return 0 (too guessable) to 4 (very unguessable)
+ */
+pub fn password_score(password: &str, inputs: &[&str]) -> u8 {
+    match password_guesses_log10(password, inputs) {
+        guesses if guesses < 3.0 => 0,
+        guesses if guesses < 6.0 => 1,
+        guesses if guesses < 8.0 => 2,
+        guesses if guesses < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+/**
+ * Check a new password against the password policy
+ * @param password The new plaintext password
+ * @param config The password policy
+ * @param username The username of the user
+ * @param email The email of the user, may be empty
+ *
+ * @return Every broken rule in the `details` of the error
+ */
+pub fn password_policy_check(
+    password: &str,
+    config: &PasswordPolicyConfig,
+    username: &str,
+    email: &str,
+) -> Result<(), WebError> {
+    let mut details = vec![];
+    let mut broken = |rule: &str, message: String| {
+        details.push(WebErrorDetail {
+            rule: rule.to_string(),
+            message,
+        })
+    };
+
+    let length = password.chars().count();
+    // refused before the costly checks below, no other rule is reported then
+    if length > config.max_length {
+        return Err(password_policy_error(vec![WebErrorDetail {
+            rule: "max_length".to_string(),
+            message: format!(
+                "Password must be at most {} characters long!",
+                config.max_length
+            ),
+        }]));
+    }
+    if length < config.min_length {
+        broken(
+            "min_length",
+            format!(
+                "Password must be at least {} characters long!",
+                config.min_length
+            ),
+        );
+    }
+    let classes = [
+        (
+            config.require_letter,
+            "letter",
+            password.chars().any(char::is_alphabetic),
+        ),
+        (
+            config.require_lowercase,
+            "lowercase",
+            password.chars().any(char::is_lowercase),
+        ),
+        (
+            config.require_uppercase,
+            "uppercase",
+            password.chars().any(char::is_uppercase),
+        ),
+        (
+            config.require_digit,
+            "digit",
+            password.chars().any(|c| c.is_ascii_digit()),
+        ),
+        (
+            config.require_symbol,
+            "symbol",
+            password
+                .chars()
+                .any(|c| !c.is_alphanumeric() && !c.is_whitespace()),
+        ),
+    ];
+    for (required, class, found) in classes {
+        if required && !found {
+            broken(class, format!("Password must contain a {}!", class));
+        }
+    }
+
+    if config.reject_common && password_is_common(password) {
+        broken("common", "Password is too common!".to_string());
+    }
+
+    let lower = password.to_lowercase();
+    let email = email.trim().to_lowercase();
+    let email_name = email.split('@').next().unwrap_or_default();
+    let personal = [username.trim().to_lowercase(), email_name.to_string()];
+    if personal
+        .iter()
+        .any(|word| word.chars().count() >= 3 && lower.contains(word.as_str()))
+    {
+        broken(
+            "personal_info",
+            "Password must not contain the username or the email!".to_string(),
+        );
+    }
+
+    if password_score(password, &[username, email_name]) < config.min_score {
+        broken(
+            "strength",
+            "Password is too easy to guess, add more words or characters!".to_string(),
+        );
+    }
+
+    if details.is_empty() {
+        return Ok(());
+    }
+    Err(password_policy_error(details))
+}
+
+fn password_policy_error(details: Vec<WebErrorDetail>) -> WebError {
+    WebError::new(
+        StatusCode::BAD_REQUEST,
+        "Password does not meet the policy!".to_string(),
+    )
+    .with_details(details)
+}
+
+#[cfg(test)]
+mod password_policy_test {
+    use super::*;
+
+    fn broken_rules(password: &str, username: &str, email: &str) -> Vec<String> {
+        match password_policy_check(password, &PasswordPolicyConfig::default(), username, email) {
+            Ok(()) => vec![],
+            Err(err) => err
+                .message
+                .details
+                .into_iter()
+                .map(|detail| detail.rule)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_password_score() {
+        assert_eq!(password_score("123456", &[]), 0);
+        assert_eq!(password_score("P@ssw0rd", &[]), 0);
+        assert_eq!(password_score("qwertyuiop", &[]), 0);
+        assert_eq!(password_score("dessera2023", &["dessera"]), 1);
+        assert!(password_score("dessera2023", &[]) >= 2);
+        assert_eq!(password_score("Tide-pool-lantern-42", &[]), 4);
+    }
+
+    #[test]
+    fn test_password_policy_check() {
+        assert!(broken_rules("Tide-pool-lantern-42", "dessera", "").is_empty());
+        assert_eq!(
+            broken_rules("123456", "dessera", ""),
+            vec!["min_length", "letter", "common", "strength"]
+        );
+        assert_eq!(
+            broken_rules("password1", "dessera", ""),
+            vec!["common", "strength"]
+        );
+        assert_eq!(
+            broken_rules("xDessera-2023x", "dessera", ""),
+            vec!["personal_info"]
+        );
+        assert_eq!(
+            broken_rules("mlum-river-9-stone", "dessera", "river-9@mlum.com"),
+            vec!["personal_info"]
+        );
+        assert_eq!(
+            broken_rules(&"1".repeat(100_000), "dessera", ""),
+            vec!["max_length"]
+        );
+    }
+}
@@ -1,9 +1,269 @@
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use data_encoding::{BASE64, HEXLOWER};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 
+use crate::{
+    config::{TokenAlgorithm, TokenConfig},
+    errors::WebError,
+};
+
+/**
+ * Generate an opaque random token
+ *
+ * @note Only used for refresh tokens and other opaque tokens,
+ *       access tokens are signed by `access_token_issue`
+ */
 pub fn token_generator() -> String {
     nanoid!(32)
 }
 
+// prefix of personal access tokens, so they are told apart from signed access tokens
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "mlum_pat_";
+
+/**
+ * Generate a personal access token
+ */
+pub fn personal_access_token_generator() -> String {
+    format!("{}{}", PERSONAL_ACCESS_TOKEN_PREFIX, nanoid!(40))
+}
+
+/**
+ * Keyed digest of a token, stored instead of the token itself
+ * @param config The token configuration, holding the digest key
+ * @param token The token
+ *
+ * @note HMAC-SHA256, the tokens are random and long so a fast hash is enough.
+ *       The key keeps a leaked digest from being checked against guesses, e.g. the 6-digit codes
+ */
+pub fn token_digest(config: &TokenConfig, token: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config.digest_key.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(token.as_bytes());
+    HEXLOWER.encode(&mac.finalize().into_bytes())
+}
+
+/**
+ * Unkeyed digest of a token, as stored before the digests were keyed
+ * @param token The token
+ *
+ * @note Only used to recognize the personal access tokens, OAuth codes and client secrets
+ *       stored by earlier versions, they are upgraded to the keyed digest on use
+ */
+pub fn token_digest_legacy(token: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(token.as_bytes()))
+}
+
+/**
+ * Check a token against a stored digest in constant time
+ * @param config The token configuration, holding the digest key
+ * @param token The token presented by the client
+ * @param digest The stored digest
+ */
+pub fn token_digest_verify(config: &TokenConfig, token: &str, digest: &str) -> bool {
+    bool::from(
+        token_digest(config, token)
+            .as_bytes()
+            .ct_eq(digest.as_bytes()),
+    )
+}
+
+/**
+ * Generate a 6-digit one-time code, to be typed by the user
+ */
+pub fn code_generator() -> String {
+    nanoid!(6, &['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'])
+}
+
+/**
+ * Generate a recovery code of the second factor, e.g. "k3x9q-7pm2a"
+ */
+pub fn recovery_code_generator() -> String {
+    let alphabet: Vec<char> = "abcdefghijkmnpqrstuvwxyz23456789".chars().collect();
+    format!("{}-{}", nanoid!(5, &alphabet), nanoid!(5, &alphabet))
+}
+
+/**
+ * Claims carried by a signed access token
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccessClaims {
+    // user id
+    pub sub: String,
+    pub username: String,
+    pub roles: Vec<String>,
+    // session id
+    pub sid: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    // OAuth client and space separated scopes, only set for the tokens of the single sign-on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // the admin impersonating the user, only set for an impersonation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<TokenActor>,
+}
+
+/**
+ * The real actor behind a token (the `act` claim of RFC 8693)
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenActor {
+    // user id
+    pub sub: String,
+    pub username: String,
+}
+
+impl AccessClaims {
+    /**
+     * Build the claims of a new access token
+     * @param config The token configuration
+     * @param sub The id of the user
+     * @param username The username of the user
+     * @param roles The roles of the user
+     * @param sid The id of the session
+     */
+    pub fn new(
+        config: &TokenConfig,
+        sub: String,
+        username: String,
+        roles: Vec<String>,
+        sid: String,
+    ) -> Self {
+        let now = Utc::now().timestamp();
+        AccessClaims {
+            sub,
+            username,
+            roles,
+            sid,
+            iss: config.issuer.clone(),
+            iat: now,
+            exp: now + config.access_token_lifetime,
+            client_id: None,
+            scope: None,
+            act: None,
+        }
+    }
+}
+
+fn token_error(err: jsonwebtoken::errors::Error) -> WebError {
+    use jsonwebtoken::errors::ErrorKind::*;
+    match err.kind() {
+        ExpiredSignature => WebError::new(StatusCode::UNAUTHORIZED, "Token expired!".to_string()),
+        InvalidToken | InvalidSignature | InvalidIssuer | InvalidAlgorithm | Base64(_)
+        | Json(_) | Utf8(_) => WebError::new(StatusCode::UNAUTHORIZED, "Token error!".to_string()),
+        _ => WebError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("JWT error: {}", err),
+        ),
+    }
+}
+
+fn token_algorithm(config: &TokenConfig) -> Algorithm {
+    match config.algorithm {
+        TokenAlgorithm::HS256 => Algorithm::HS256,
+        TokenAlgorithm::EdDSA => Algorithm::EdDSA,
+    }
+}
+
+/**
+ * Get the raw Ed25519 public key of the EdDSA configuration
+ * @param config The token configuration
+ *
+ * @return The 32 bytes of the key, none for HS256 or an invalid key
+ */
+pub fn token_public_key(config: &TokenConfig) -> Option<Vec<u8>> {
+    if config.algorithm != TokenAlgorithm::EdDSA {
+        return None;
+    }
+    let encoded: String = config
+        .public_key
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = BASE64.decode(encoded.trim().as_bytes()).ok()?;
+    // the key ends the SubjectPublicKeyInfo structure
+    (der.len() >= 32).then(|| der[der.len() - 32..].to_vec())
+}
+
+/**
+ * Id of the signing key, published in the JWKS document
+ * @param config The token configuration
+ */
+pub fn token_key_id(config: &TokenConfig) -> Option<String> {
+    token_public_key(config).map(|key| HEXLOWER.encode(&Sha256::digest(&key)[..8]))
+}
+
+/**
+ * Sign a JWT with the configured key
+ * @param claims The claims of the token
+ * @param config The token configuration
+ *
+ * @return The encoded JWT
+ */
+pub fn token_sign<T: Serialize>(claims: &T, config: &TokenConfig) -> Result<String, WebError> {
+    let key = match config.algorithm {
+        TokenAlgorithm::HS256 => EncodingKey::from_secret(config.secret.as_bytes()),
+        TokenAlgorithm::EdDSA => {
+            EncodingKey::from_ed_pem(config.private_key.as_bytes()).map_err(token_error)?
+        }
+    };
+    let mut header = Header::new(token_algorithm(config));
+    header.kid = token_key_id(config);
+    encode(&header, claims, &key).map_err(token_error)
+}
+
+/**
+ * Sign an access token
+ * @param claims The claims of the token
+ * @param config The token configuration
+ *
+ * @return The encoded JWT
+ */
+pub fn access_token_issue(claims: &AccessClaims, config: &TokenConfig) -> Result<String, WebError> {
+    token_sign(claims, config)
+}
+
+/**
+ * Verify the signature and the expiry of an access token
+ * @param token The encoded JWT
+ * @param config The token configuration
+ *
+ * @return The claims of the token
+ */
+pub fn access_token_decode(token: &str, config: &TokenConfig) -> Result<AccessClaims, WebError> {
+    let key = match config.algorithm {
+        TokenAlgorithm::HS256 => DecodingKey::from_secret(config.secret.as_bytes()),
+        TokenAlgorithm::EdDSA => {
+            DecodingKey::from_ed_pem(config.public_key.as_bytes()).map_err(token_error)?
+        }
+    };
+    let mut validation = Validation::new(token_algorithm(config));
+    validation.set_issuer(&[config.issuer.as_str()]);
+    decode::<AccessClaims>(token, &key, &validation)
+        .map(|data| data.claims)
+        .map_err(token_error)
+}
+
+/**
+ * Check that the configured keys can sign and verify a token
+ * @param config The token configuration
+ *
+ * @note Called at startup, a missing or mismatched EdDSA key pair would fail every login
+ */
+pub fn token_keys_check(config: &TokenConfig) -> Result<(), WebError> {
+    let claims = AccessClaims::new(config, String::new(), String::new(), vec![], String::new());
+    access_token_decode(&access_token_issue(&claims, config)?, config).map(|_| ())
+}
+
 #[cfg(test)]
 mod token_generator_test {
     use super::*;
@@ -14,4 +274,72 @@ mod token_generator_test {
         println!("{}", token);
         assert_eq!(token.len(), 32);
     }
+
+    #[test]
+    fn test_code_generator() {
+        let code = code_generator();
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_token_keys_check() {
+        let mut config = TokenConfig::default();
+        assert!(token_keys_check(&config).is_ok());
+        config.algorithm = TokenAlgorithm::EdDSA;
+        assert!(token_keys_check(&config).is_err());
+    }
+
+    #[test]
+    fn test_token_digest() {
+        let config = TokenConfig {
+            digest_key: "mlum test key".to_string(),
+            ..TokenConfig::default()
+        };
+        let digest = token_digest(&config, "token");
+        assert_eq!(digest.len(), 64);
+        assert_ne!(digest, token_digest_legacy("token"));
+        assert!(token_digest_verify(&config, "token", &digest));
+        assert!(!token_digest_verify(&config, "tokem", &digest));
+
+        let other = TokenConfig {
+            digest_key: "other key".to_string(),
+            ..TokenConfig::default()
+        };
+        assert!(!token_digest_verify(&other, "token", &digest));
+    }
+
+    #[test]
+    fn test_access_token() {
+        let config = TokenConfig::default();
+        let claims = AccessClaims::new(
+            &config,
+            "id".to_string(),
+            "dessera".to_string(),
+            vec!["user".to_string()],
+            "sid".to_string(),
+        );
+        let token = access_token_issue(&claims, &config).unwrap();
+        let decoded = access_token_decode(&token, &config).unwrap();
+        assert_eq!(decoded.username, "dessera");
+        assert_eq!(decoded.sid, "sid");
+
+        let other = TokenConfig::default();
+        assert!(access_token_decode(&token, &other).is_err());
+    }
+
+    #[test]
+    fn test_access_token_expired() {
+        let config = TokenConfig::default();
+        let mut claims = AccessClaims::new(
+            &config,
+            "id".to_string(),
+            "dessera".to_string(),
+            vec![],
+            "sid".to_string(),
+        );
+        claims.exp = Utc::now().timestamp() - 3600;
+        let token = access_token_issue(&claims, &config).unwrap();
+        assert!(access_token_decode(&token, &config).is_err());
+    }
 }
@@ -0,0 +1,205 @@
+use actix_web::http::StatusCode;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::{BASE32_NOPAD, BASE64};
+use hmac::{Hmac, Mac};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::errors::WebError;
+
+// RFC 6238 defaults, understood by every authenticator app
+const TOTP_PERIOD: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+
+// prefix of the encrypted secrets, the base32 secrets of earlier versions never contain it
+const ENCRYPTED_SECRET_PREFIX: &str = "aes256gcm:";
+const SECRET_NONCE_LEN: usize = 12;
+const SECRET_TAG_LEN: usize = 16;
+
+/**
+ * Generate a random TOTP secret
+ *
+ * @return The base32 encoded secret, as typed in authenticator apps
+ */
+pub fn totp_secret_generate() -> String {
+    let mut secret = [0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+/**
+ * Build the otpauth URI of a secret, usually shown as a QR code
+ * @param issuer The name of the service
+ * @param account The account name shown in the app
+ * @param secret The base32 encoded secret
+ */
+pub fn totp_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC).to_string();
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC).to_string();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer, account, secret, issuer, TOTP_DIGITS, TOTP_PERIOD
+    )
+}
+
+/**
+ * Compute the code of a time step (RFC 4226 HOTP)
+ * @param key The raw secret
+ * @param step The time step
+ */
+fn totp_code(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/**
+ * Check a code against a secret
+ * @param secret The base32 encoded secret
+ * @param code The code typed by the user
+ * @param now The current timestamp
+ * @param skew The number of steps accepted before and after the current one
+ *
+ * @return The matched time step, so the caller can refuse to accept it twice
+ */
+pub fn totp_verify(secret: &str, code: &str, now: i64, skew: i64) -> Option<i64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let current = now / TOTP_PERIOD;
+    (current - skew..=current + skew)
+        .find(|step| bool::from(totp_code(&key, *step).as_bytes().ct_eq(code.as_bytes())))
+}
+
+fn secret_error(action: &str) -> WebError {
+    WebError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {} the two-factor secret!", action),
+    )
+}
+
+/**
+ * Check whether a stored secret is encrypted or stored in plaintext by an earlier version
+ * @param stored The secret stored in the database
+ */
+pub fn totp_secret_is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_SECRET_PREFIX)
+}
+
+/**
+ * Encrypt a secret with AES-256-GCM before it is stored
+ * @param key The configured encryption key, hashed into the AES key
+ * @param secret The base32 encoded secret
+ *
+ * @return The prefixed base64 of the nonce, the ciphertext and the tag
+ *
+ * @note Unlike the recovery codes the secret can not be hashed,
+ *       it is needed in plaintext to compute the expected codes
+ */
+pub fn totp_secret_encrypt(key: &str, secret: &str) -> Result<String, WebError> {
+    let mut nonce = [0u8; SECRET_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let mut tag = [0u8; SECRET_TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &Sha256::digest(key.as_bytes()),
+        Some(&nonce),
+        &[],
+        secret.as_bytes(),
+        &mut tag,
+    )
+    .map_err(|_| secret_error("encrypt"))?;
+
+    let sealed = [&nonce[..], &ciphertext, &tag].concat();
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_SECRET_PREFIX,
+        BASE64.encode(&sealed)
+    ))
+}
+
+/**
+ * Decrypt a stored secret
+ * @param key The configured encryption key
+ * @param stored The secret stored in the database
+ *
+ * @return The base32 encoded secret, plaintext secrets of earlier versions are returned as they are
+ */
+pub fn totp_secret_decrypt(key: &str, stored: &str) -> Result<String, WebError> {
+    let sealed = match stored.strip_prefix(ENCRYPTED_SECRET_PREFIX) {
+        Some(sealed) => BASE64
+            .decode(sealed.as_bytes())
+            .map_err(|_| secret_error("decrypt"))?,
+        None => return Ok(stored.to_string()),
+    };
+    if sealed.len() < SECRET_NONCE_LEN + SECRET_TAG_LEN {
+        return Err(secret_error("decrypt"));
+    }
+    let (nonce, rest) = sealed.split_at(SECRET_NONCE_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - SECRET_TAG_LEN);
+    let secret = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &Sha256::digest(key.as_bytes()),
+        Some(nonce),
+        &[],
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| secret_error("decrypt"))?;
+    String::from_utf8(secret).map_err(|_| secret_error("decrypt"))
+}
+
+#[cfg(test)]
+mod totp_test {
+    use super::*;
+
+    #[test]
+    fn test_totp_rfc6238() {
+        // SHA1 test vectors of RFC 6238, truncated to 6 digits
+        let key = b"12345678901234567890";
+        assert_eq!(totp_code(key, 59 / TOTP_PERIOD), "287082");
+        assert_eq!(totp_code(key, 1111111109 / TOTP_PERIOD), "081804");
+        assert_eq!(totp_code(key, 2000000000 / TOTP_PERIOD), "279037");
+    }
+
+    #[test]
+    fn test_totp_verify() {
+        let secret = totp_secret_generate();
+        let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        let now = 1700000000;
+        let previous = totp_code(&key, now / TOTP_PERIOD - 1);
+
+        assert_eq!(
+            totp_verify(&secret, &previous, now, 1),
+            Some(now / TOTP_PERIOD - 1)
+        );
+        assert_eq!(totp_verify(&secret, &previous, now, 0), None);
+        assert!(totp_uri("mlum", "dessera lin", &secret).contains("dessera%20lin"));
+    }
+
+    #[test]
+    fn test_totp_secret_encrypt() {
+        let secret = totp_secret_generate();
+        let stored = totp_secret_encrypt("key", &secret).unwrap();
+        assert!(totp_secret_is_encrypted(&stored));
+        assert!(!stored.contains(&secret));
+        assert_eq!(totp_secret_decrypt("key", &stored).unwrap(), secret);
+        assert!(totp_secret_decrypt("other", &stored).is_err());
+        assert!(!totp_secret_is_encrypted(&secret));
+        assert_eq!(totp_secret_decrypt("key", &secret).unwrap(), secret);
+    }
+}
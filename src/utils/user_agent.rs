@@ -0,0 +1,69 @@
+/**
+ * Describe a user agent in a few words, so users can tell their devices apart
+ * @param user_agent The User-Agent header
+ *
+ * @return e.g. "Chrome on Windows", or "Unknown device" if nothing is recognized
+ *
+ * @note Only the common browsers and systems are known, the full header is kept beside it
+ */
+pub fn user_agent_describe(user_agent: &str) -> String {
+    // the order matters, Edge and Opera also claim to be Chrome, which claims to be Safari
+    let browsers = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("CriOS/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+        ("PostmanRuntime/", "Postman"),
+    ];
+    let systems = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ];
+    let browser = browsers
+        .iter()
+        .find(|(token, _)| user_agent.contains(token))
+        .map(|(_, name)| *name);
+    let system = systems
+        .iter()
+        .find(|(token, _)| user_agent.contains(token))
+        .map(|(_, name)| *name);
+
+    match (browser, system) {
+        (Some(browser), Some(system)) => format!("{} on {}", browser, system),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Unknown device".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod user_agent_test {
+    use super::*;
+
+    #[test]
+    fn test_user_agent_describe() {
+        assert_eq!(
+            user_agent_describe("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0"),
+            "Edge on Windows"
+        );
+        assert_eq!(
+            user_agent_describe("Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1"),
+            "Safari on iOS"
+        );
+        assert_eq!(
+            user_agent_describe(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0"
+            ),
+            "Firefox on Linux"
+        );
+        assert_eq!(user_agent_describe("curl/8.4.0"), "curl");
+        assert_eq!(user_agent_describe(""), "Unknown device");
+    }
+}